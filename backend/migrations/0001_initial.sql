@@ -85,19 +85,19 @@ CREATE TABLE IF NOT EXISTS sessions (
 );
 
 -- Index for performance
-CREATE INDEX idx_sessions_user ON sessions(user_id);
-CREATE INDEX idx_sessions_token ON sessions(session_token);
+CREATE INDEX IF NOT EXISTS idx_sessions_user ON sessions(user_id);
+CREATE INDEX IF NOT EXISTS idx_sessions_token ON sessions(session_token);
 
 
 -- Indexes for performance
-CREATE INDEX idx_templates_user ON templates(user_id);
-CREATE INDEX idx_template_exercises_template ON template_exercises(template_id);
-CREATE INDEX idx_template_exercises_exercise ON template_exercises(exercise_id);
+CREATE INDEX IF NOT EXISTS idx_templates_user ON templates(user_id);
+CREATE INDEX IF NOT EXISTS idx_template_exercises_template ON template_exercises(template_id);
+CREATE INDEX IF NOT EXISTS idx_template_exercises_exercise ON template_exercises(exercise_id);
 
 -- Indexes for performance
-CREATE INDEX idx_user_exercises_user ON user_exercises(user_id);
-CREATE INDEX idx_workouts_user ON workouts(user_id);
-CREATE INDEX idx_sets_workout_exercise ON sets(workout_exercise_id);
+CREATE INDEX IF NOT EXISTS idx_user_exercises_user ON user_exercises(user_id);
+CREATE INDEX IF NOT EXISTS idx_workouts_user ON workouts(user_id);
+CREATE INDEX IF NOT EXISTS idx_sets_workout_exercise ON sets(workout_exercise_id);
 
 -- Create trigger to copy master exercises when new user is created
 CREATE TRIGGER IF NOT EXISTS copy_master_exercises
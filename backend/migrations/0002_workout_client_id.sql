@@ -0,0 +1,5 @@
+-- Client-generated workout id (or Idempotency-Key header) so retried saves
+-- return the original workout instead of inserting a duplicate.
+ALTER TABLE workouts ADD COLUMN client_id TEXT;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_workouts_client_id ON workouts(user_id, client_id);
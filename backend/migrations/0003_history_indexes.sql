@@ -0,0 +1,5 @@
+-- History is paged newest first on (start_time, id) and then joined down to
+-- exercises and sets; these keep both steps off full table scans.
+CREATE INDEX IF NOT EXISTS idx_workouts_user_start ON workouts(user_id, start_time, id);
+
+CREATE INDEX IF NOT EXISTS idx_workout_exercises_workout ON workout_exercises(workout_id);
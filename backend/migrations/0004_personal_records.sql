@@ -0,0 +1,21 @@
+-- Personal records, one row per record a set (or, for session volume, a whole
+-- workout) set for an exercise. Rebuilt per exercise whenever its sets change.
+CREATE TABLE IF NOT EXISTS personal_records (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    exercise_id INTEGER NOT NULL,
+    workout_id INTEGER NOT NULL,
+    set_id INTEGER, -- NULL for records that belong to the whole session
+    record_type TEXT NOT NULL,
+    value REAL NOT NULL,
+    previous_value REAL NOT NULL,
+    weight REAL, -- the weight a reps_at_weight record was set at
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (exercise_id) REFERENCES user_exercises(id) ON DELETE CASCADE,
+    FOREIGN KEY (workout_id) REFERENCES workouts(id) ON DELETE CASCADE,
+    FOREIGN KEY (set_id) REFERENCES sets(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_personal_records_exercise ON personal_records(user_id, exercise_id);
+CREATE INDEX IF NOT EXISTS idx_personal_records_workout ON personal_records(workout_id);
+CREATE INDEX IF NOT EXISTS idx_personal_records_set ON personal_records(set_id);
@@ -0,0 +1,2 @@
+-- Per-user settings. Formula names match OneRepMaxFormula.
+ALTER TABLE users ADD COLUMN one_rep_max_formula TEXT NOT NULL DEFAULT 'epley';
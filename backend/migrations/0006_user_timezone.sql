@@ -0,0 +1,4 @@
+-- Timestamps are stored in UTC and shown in the user's timezone and language.
+-- Workouts saved before this were stored as sent, so they read as UTC.
+ALTER TABLE users ADD COLUMN timezone TEXT NOT NULL DEFAULT 'UTC';
+ALTER TABLE users ADD COLUMN locale TEXT NOT NULL DEFAULT 'en_GB';
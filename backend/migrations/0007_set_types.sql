@@ -0,0 +1,4 @@
+-- Set types (see SetType) and reps in reserve. rpe and notes already existed
+-- but were never written.
+ALTER TABLE sets ADD COLUMN set_type TEXT NOT NULL DEFAULT 'working';
+ALTER TABLE sets ADD COLUMN rir INTEGER;
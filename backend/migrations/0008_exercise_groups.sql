@@ -0,0 +1,23 @@
+-- Supersets, tri-sets and circuits. A group belongs to one workout or
+-- template; its exercises point at it and keep their own order by id.
+CREATE TABLE IF NOT EXISTS workout_exercise_groups (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    workout_id INTEGER NOT NULL,
+    position INTEGER NOT NULL,    -- 1 for the first group in the workout
+    group_type TEXT NOT NULL,     -- superset, triset or circuit
+    FOREIGN KEY (workout_id) REFERENCES workouts(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS template_exercise_groups (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    template_id INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    group_type TEXT NOT NULL,
+    FOREIGN KEY (template_id) REFERENCES templates(id) ON DELETE CASCADE
+);
+
+ALTER TABLE workout_exercises ADD COLUMN group_id INTEGER REFERENCES workout_exercise_groups(id);
+ALTER TABLE template_exercises ADD COLUMN group_id INTEGER REFERENCES template_exercise_groups(id);
+
+CREATE INDEX IF NOT EXISTS idx_workout_exercise_groups_workout ON workout_exercise_groups(workout_id);
+CREATE INDEX IF NOT EXISTS idx_template_exercise_groups_template ON template_exercise_groups(template_id);
@@ -0,0 +1,30 @@
+-- Exercise kinds (see ExerciseKind) and the set fields they are measured by.
+-- For bodyweight and assisted sets, weight is the added or assisting weight
+-- and bodyweight is what the lifter weighed at the time.
+ALTER TABLE master_exercises ADD COLUMN kind TEXT NOT NULL DEFAULT 'weight_reps';
+ALTER TABLE user_exercises ADD COLUMN kind TEXT NOT NULL DEFAULT 'weight_reps';
+
+ALTER TABLE sets ADD COLUMN duration_seconds INTEGER;
+ALTER TABLE sets ADD COLUMN distance_meters REAL;
+ALTER TABLE sets ADD COLUMN bodyweight REAL;
+
+UPDATE master_exercises SET kind = 'bodyweight'
+WHERE name IN ('Push-Up', 'Dips (Chest Version)', 'Pull-Up', 'Hyperextension', 'Tricep Dip',
+               'Leg Raise', 'Hanging Leg Raise', 'Mountain Climbers', 'Bicycle Crunch',
+               'Russian Twist', 'Ab Rollout', 'Toe Touches');
+UPDATE master_exercises SET kind = 'timed' WHERE name IN ('Plank', 'Side Plank');
+
+-- Copies made before kinds existed pick up the master's kind.
+UPDATE user_exercises SET kind = (
+    SELECT m.kind FROM master_exercises m WHERE m.name = user_exercises.name
+)
+WHERE name IN (SELECT name FROM master_exercises WHERE kind != 'weight_reps');
+
+DROP TRIGGER IF EXISTS copy_master_exercises;
+CREATE TRIGGER copy_master_exercises
+AFTER INSERT ON users
+BEGIN
+    INSERT INTO user_exercises (user_id, name, description, muscle_group, kind)
+    SELECT NEW.id, name, description, muscle_group, kind
+    FROM master_exercises;
+END;
@@ -0,0 +1,4 @@
+-- Weights are stored in kg. Each set remembers the unit it was entered in
+-- and each user the unit they want to see (see WeightUnit).
+ALTER TABLE users ADD COLUMN weight_unit TEXT NOT NULL DEFAULT 'kg';
+ALTER TABLE sets ADD COLUMN weight_unit TEXT NOT NULL DEFAULT 'kg';
@@ -0,0 +1,6 @@
+-- Archived exercises keep their history but are left out of the exercise list.
+ALTER TABLE user_exercises ADD COLUMN archived INTEGER NOT NULL DEFAULT 0;
+
+-- For the per-user, case-insensitive duplicate name check made when adding
+-- or renaming an exercise.
+CREATE INDEX IF NOT EXISTS idx_user_exercises_name ON user_exercises(user_id, name COLLATE NOCASE);
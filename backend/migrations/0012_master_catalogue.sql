@@ -0,0 +1,29 @@
+-- The built-in exercise catalogue (see catalogue.rs) is loaded by the code
+-- migration that follows this one.
+ALTER TABLE master_exercises ADD COLUMN equipment TEXT;
+ALTER TABLE master_exercises ADD COLUMN catalogue_version INTEGER NOT NULL DEFAULT 1;
+
+CREATE TABLE IF NOT EXISTS master_exercise_aliases (
+    master_exercise_id INTEGER NOT NULL,
+    alias TEXT NOT NULL COLLATE NOCASE,
+    PRIMARY KEY (master_exercise_id, alias),
+    FOREIGN KEY (master_exercise_id) REFERENCES master_exercises(id) ON DELETE CASCADE
+);
+
+-- The catalogue entry a user's exercise was copied from or matched to.
+ALTER TABLE user_exercises ADD COLUMN master_exercise_id INTEGER REFERENCES master_exercises(id);
+
+-- Newest catalogue version the user has been given exercises from.
+ALTER TABLE users ADD COLUMN catalogue_version INTEGER NOT NULL DEFAULT 0;
+
+DROP TRIGGER IF EXISTS copy_master_exercises;
+CREATE TRIGGER copy_master_exercises
+AFTER INSERT ON users
+BEGIN
+    INSERT INTO user_exercises (user_id, name, description, muscle_group, kind, master_exercise_id)
+    SELECT NEW.id, name, description, muscle_group, kind, id
+    FROM master_exercises;
+    UPDATE users
+    SET catalogue_version = (SELECT COALESCE(MAX(catalogue_version), 0) FROM master_exercises)
+    WHERE id = NEW.id;
+END;
@@ -0,0 +1,12 @@
+-- Order of exercises within a template, starting at 1. Existing templates
+-- keep the order they were saved in.
+ALTER TABLE template_exercises ADD COLUMN position INTEGER NOT NULL DEFAULT 0;
+
+UPDATE template_exercises
+SET position = (
+    SELECT COUNT(*) FROM template_exercises earlier
+    WHERE earlier.template_id = template_exercises.template_id
+      AND earlier.id <= template_exercises.id
+);
+
+CREATE INDEX IF NOT EXISTS idx_template_exercises_position ON template_exercises(template_id, position);
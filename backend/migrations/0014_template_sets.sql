@@ -0,0 +1,16 @@
+-- What a template prescribes for each set of an exercise. Every target is
+-- optional; `weight` is in kg and takes precedence over `percent_e1rm`.
+CREATE TABLE IF NOT EXISTS template_sets (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    template_exercise_id INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    reps_min INTEGER,
+    reps_max INTEGER,
+    weight REAL,
+    percent_e1rm REAL,
+    rpe REAL,
+    rest_seconds INTEGER,
+    FOREIGN KEY (template_exercise_id) REFERENCES template_exercises(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_template_sets_exercise ON template_sets(template_exercise_id, position);
@@ -0,0 +1,2 @@
+-- The template a workout was started from, if any.
+ALTER TABLE workouts ADD COLUMN template_id INTEGER REFERENCES templates(id) ON DELETE SET NULL;
@@ -0,0 +1,56 @@
+-- Training max per exercise, in kg, that program percentages are taken from.
+CREATE TABLE IF NOT EXISTS training_maxes (
+    user_id INTEGER NOT NULL,
+    exercise_id INTEGER NOT NULL,
+    weight REAL NOT NULL,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (user_id, exercise_id),
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (exercise_id) REFERENCES user_exercises(id) ON DELETE CASCADE
+);
+
+ALTER TABLE template_sets ADD COLUMN percent_training_max REAL;
+
+-- A program runs its weeks in order, each week's days in order, and then
+-- starts over. A day is one of the user's templates.
+CREATE TABLE IF NOT EXISTS programs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    -- Share of the usual weight lifted in deload weeks.
+    deload_percent REAL NOT NULL DEFAULT 60,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS program_weeks (
+    program_id INTEGER NOT NULL,
+    week INTEGER NOT NULL,
+    deload INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (program_id, week),
+    FOREIGN KEY (program_id) REFERENCES programs(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS program_days (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    program_id INTEGER NOT NULL,
+    week INTEGER NOT NULL,
+    day INTEGER NOT NULL,
+    template_id INTEGER NOT NULL,
+    FOREIGN KEY (program_id) REFERENCES programs(id) ON DELETE CASCADE,
+    FOREIGN KEY (template_id) REFERENCES templates(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_program_days_order ON program_days(program_id, week, day);
+
+-- The program each user is following and the day they are up to.
+CREATE TABLE IF NOT EXISTS user_programs (
+    user_id INTEGER PRIMARY KEY,
+    program_id INTEGER NOT NULL,
+    cycle INTEGER NOT NULL DEFAULT 1,
+    week INTEGER NOT NULL DEFAULT 1,
+    day INTEGER NOT NULL DEFAULT 1,
+    started_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (program_id) REFERENCES programs(id) ON DELETE CASCADE
+);
@@ -0,0 +1,25 @@
+-- Progression rule of a template exercise as JSON (see progression.rs), and
+-- how many sessions in a row it has been failed.
+ALTER TABLE template_exercises ADD COLUMN progression_rule TEXT;
+ALTER TABLE template_exercises ADD COLUMN failed_sessions INTEGER NOT NULL DEFAULT 0;
+
+-- Every change a progression rule made after a workout. Weights are in kg.
+CREATE TABLE IF NOT EXISTS progression_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    template_id INTEGER NOT NULL,
+    exercise_id INTEGER NOT NULL,
+    workout_id INTEGER,
+    outcome TEXT NOT NULL,
+    target TEXT NOT NULL,
+    weight_before REAL,
+    weight_after REAL,
+    failed_sessions INTEGER NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (template_id) REFERENCES templates(id) ON DELETE CASCADE,
+    FOREIGN KEY (exercise_id) REFERENCES user_exercises(id) ON DELETE CASCADE,
+    FOREIGN KEY (workout_id) REFERENCES workouts(id) ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_progression_log_template ON progression_log(template_id, id);
@@ -0,0 +1,6 @@
+-- Clients used to send wall clock times without an offset, and 0006 kept
+-- those as if they were UTC. Workouts of users who haven't picked a
+-- timezone yet are flagged here and converted to UTC once they do.
+ALTER TABLE workouts ADD COLUMN local_time INTEGER NOT NULL DEFAULT 0;
+UPDATE workouts SET local_time = 1
+WHERE user_id IN (SELECT id FROM users WHERE timezone = 'UTC');
@@ -0,0 +1,29 @@
+-- What progression changed when a workout was saved, so that editing or
+-- deleting the workout can put it back. Weights are in kg.
+CREATE TABLE IF NOT EXISTS progression_changes (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    workout_id INTEGER NOT NULL,
+    -- template_set, training_max or failed_sessions
+    target TEXT NOT NULL,
+    -- The template_sets row, the exercise or the template_exercises row.
+    target_id INTEGER NOT NULL,
+    value_before REAL NOT NULL,
+    value_after REAL NOT NULL,
+    FOREIGN KEY (workout_id) REFERENCES workouts(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_progression_changes_workout ON progression_changes(workout_id);
+
+-- The program day a workout moved the user on from, and the one it moved them to.
+CREATE TABLE IF NOT EXISTS program_advances (
+    workout_id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    program_id INTEGER NOT NULL,
+    cycle_before INTEGER NOT NULL,
+    week_before INTEGER NOT NULL,
+    day_before INTEGER NOT NULL,
+    cycle_after INTEGER NOT NULL,
+    week_after INTEGER NOT NULL,
+    day_after INTEGER NOT NULL,
+    FOREIGN KEY (workout_id) REFERENCES workouts(id) ON DELETE CASCADE
+);
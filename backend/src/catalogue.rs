@@ -0,0 +1,244 @@
+use rusqlite::{params, Connection, OptionalExtension, Result};
+
+use crate::exercise_kind::ExerciseKind;
+
+/// One exercise in the built-in catalogue that new users start with.
+pub struct CatalogueExercise {
+    pub name: &'static str,
+    pub muscle_group: &'static str,
+    pub equipment: &'static str,
+    pub kind: ExerciseKind,
+    pub description: &'static str,
+    /// Other names it goes by. A user who already has an exercise by one of
+    /// these names is not given a second copy.
+    pub aliases: &'static [&'static str],
+    /// Catalogue version the exercise was added in.
+    pub since: u32,
+}
+
+const fn lift(
+    name: &'static str,
+    muscle_group: &'static str,
+    equipment: &'static str,
+    description: &'static str,
+    aliases: &'static [&'static str],
+) -> CatalogueExercise {
+    CatalogueExercise { name, muscle_group, equipment, kind: ExerciseKind::WeightReps, description, aliases, since: 1 }
+}
+
+const fn of_kind(kind: ExerciseKind, exercise: CatalogueExercise) -> CatalogueExercise {
+    CatalogueExercise { kind, ..exercise }
+}
+
+/// To add exercises, append them with `since` one above the newest entry and
+/// append another `Migration::Code(catalogue::seed)` so existing users get them.
+pub const CATALOGUE: &[CatalogueExercise] = &[
+    lift("Bench Press (Barbell)", "Chest", "barbell", "Flat bench press with a barbell", &["Bench Press", "Flat Bench"]),
+    lift("Bench Press (Dumbbell)", "Chest", "dumbbell", "Flat bench press with dumbbells", &["DB Bench Press"]),
+    lift("Incline Bench Press (Barbell)", "Chest", "barbell", "Incline bench press with a barbell", &["Incline Bench"]),
+    lift("Incline Bench Press (Dumbbell)", "Chest", "dumbbell", "Incline bench press with dumbbells", &["Incline DB Press"]),
+    lift("Decline Bench Press (Barbell)", "Chest", "barbell", "Decline bench press with a barbell", &["Decline Bench"]),
+    lift("Chest Fly (Dumbbell)", "Chest", "dumbbell", "Chest fly with dumbbells", &["Dumbbell Fly"]),
+    lift("Chest Fly (Cable)", "Chest", "cable", "Chest fly using a cable machine", &["Cable Crossover"]),
+    of_kind(ExerciseKind::Bodyweight, lift("Push-Up", "Chest", "bodyweight", "Bodyweight push-up", &["Push Up", "Press-Up"])),
+    of_kind(
+        ExerciseKind::Bodyweight,
+        lift("Dips (Chest Version)", "Chest", "dip bars", "Dips focusing on chest activation", &["Chest Dip"]),
+    ),
+    lift("Pec Deck Machine", "Chest", "machine", "Machine-based chest isolation exercise", &["Pec Deck", "Machine Fly"]),
+    lift("Deadlift (Barbell)", "Back", "barbell", "Conventional deadlift with a barbell", &["Deadlift", "Conventional Deadlift"]),
+    lift("Deadlift (Dumbbell)", "Back", "dumbbell", "Deadlift with dumbbells", &["DB Deadlift"]),
+    of_kind(ExerciseKind::Bodyweight, lift("Pull-Up", "Back", "pull-up bar", "Bodyweight pull-up", &["Pull Up", "Pullup"])),
+    lift("Lat Pulldown", "Back", "cable", "Lat pulldown using a cable machine", &["Pulldown"]),
+    lift("Bent-Over Row (Barbell)", "Back", "barbell", "Bent-over row with a barbell", &["Barbell Row", "Bent Over Row"]),
+    lift("Bent-Over Row (Dumbbell)", "Back", "dumbbell", "Bent-over row with dumbbells", &["Dumbbell Row"]),
+    lift("T-Bar Row", "Back", "barbell", "T-bar row using a barbell", &["T Bar Row"]),
+    lift("Seated Cable Row", "Back", "cable", "Seated row using a cable machine", &["Cable Row"]),
+    lift("Single-Arm Dumbbell Row", "Back", "dumbbell", "Single-arm row with a dumbbell", &["One-Arm Dumbbell Row"]),
+    of_kind(
+        ExerciseKind::Bodyweight,
+        lift("Hyperextension", "Back", "hyperextension bench", "Back extension exercise", &["Back Extension"]),
+    ),
+    lift("Squat (Barbell)", "Legs", "barbell", "Back squat with a barbell", &["Squat", "Back Squat"]),
+    lift("Squat (Dumbbell)", "Legs", "dumbbell", "Squat with dumbbells", &["Goblet Squat"]),
+    lift("Front Squat (Barbell)", "Legs", "barbell", "Front squat with a barbell", &["Front Squat"]),
+    lift("Leg Press", "Legs", "machine", "Leg press using a machine", &[]),
+    lift("Lunges (Dumbbell)", "Legs", "dumbbell", "Lunges with dumbbells", &["Lunge", "Walking Lunge"]),
+    lift("Romanian Deadlift (Barbell)", "Legs", "barbell", "Romanian deadlift with a barbell", &["RDL", "Romanian Deadlift"]),
+    lift("Romanian Deadlift (Dumbbell)", "Legs", "dumbbell", "Romanian deadlift with dumbbells", &["DB RDL"]),
+    lift("Leg Curl (Machine)", "Legs", "machine", "Leg curl using a machine", &["Hamstring Curl", "Leg Curl"]),
+    lift("Leg Extension (Machine)", "Legs", "machine", "Leg extension using a machine", &["Leg Extension"]),
+    lift("Calf Raise (Barbell)", "Legs", "barbell", "Calf raise with a barbell", &["Calf Raise"]),
+    lift("Overhead Press (Barbell)", "Shoulders", "barbell", "Overhead press with a barbell", &["OHP", "Military Press"]),
+    lift("Overhead Press (Dumbbell)", "Shoulders", "dumbbell", "Overhead press with dumbbells", &["Dumbbell Shoulder Press"]),
+    lift("Arnold Press", "Shoulders", "dumbbell", "Arnold press with dumbbells", &[]),
+    lift("Lateral Raise (Dumbbell)", "Shoulders", "dumbbell", "Lateral raise with dumbbells", &["Lateral Raise", "Side Raise"]),
+    lift("Front Raise (Dumbbell)", "Shoulders", "dumbbell", "Front raise with dumbbells", &["Front Raise"]),
+    lift("Rear Delt Fly (Dumbbell)", "Shoulders", "dumbbell", "Rear delt fly with dumbbells", &["Reverse Fly"]),
+    lift("Face Pull (Cable)", "Shoulders", "cable", "Face pull using a cable machine", &["Face Pull"]),
+    lift("Shrug (Barbell)", "Shoulders", "barbell", "Shrug with a barbell", &["Barbell Shrug"]),
+    lift("Shrug (Dumbbell)", "Shoulders", "dumbbell", "Shrug with dumbbells", &["Dumbbell Shrug"]),
+    lift("Upright Row (Barbell)", "Shoulders", "barbell", "Upright row with a barbell", &["Upright Row"]),
+    lift("Bicep Curl (Barbell)", "Arms", "barbell", "Bicep curl with a barbell", &["Barbell Curl"]),
+    lift("Bicep Curl (Dumbbell)", "Arms", "dumbbell", "Bicep curl with dumbbells", &["Dumbbell Curl"]),
+    lift("Hammer Curl", "Arms", "dumbbell", "Hammer curl with dumbbells", &[]),
+    lift("Preacher Curl", "Arms", "barbell", "Preacher curl using a barbell or machine", &[]),
+    of_kind(ExerciseKind::Bodyweight, lift("Tricep Dip", "Arms", "dip bars", "Bodyweight tricep dip", &["Bench Dip"])),
+    lift("Tricep Pushdown (Cable)", "Arms", "cable", "Tricep pushdown using a cable machine", &["Tricep Pushdown"]),
+    lift("Skull Crusher (Barbell)", "Arms", "barbell", "Skull crusher with a barbell", &["Skull Crusher", "Lying Tricep Extension"]),
+    lift(
+        "Overhead Tricep Extension (Dumbbell)",
+        "Arms",
+        "dumbbell",
+        "Overhead tricep extension with a dumbbell",
+        &["Overhead Tricep Extension"],
+    ),
+    lift("Close-Grip Bench Press (Barbell)", "Arms", "barbell", "Close-grip bench press with a barbell", &["Close-Grip Bench"]),
+    lift("Concentration Curl", "Arms", "dumbbell", "Concentration curl with a dumbbell", &[]),
+    of_kind(ExerciseKind::Timed, lift("Plank", "Core", "bodyweight", "Bodyweight plank for core stability", &["Front Plank"])),
+    of_kind(ExerciseKind::Bodyweight, lift("Russian Twist", "Core", "bodyweight", "Russian twist with or without weight", &[])),
+    of_kind(ExerciseKind::Bodyweight, lift("Leg Raise", "Core", "bodyweight", "Leg raise for lower abs", &["Lying Leg Raise"])),
+    of_kind(
+        ExerciseKind::Bodyweight,
+        lift("Hanging Leg Raise", "Core", "pull-up bar", "Hanging leg raise for core strength", &[]),
+    ),
+    lift("Cable Crunch", "Core", "cable", "Cable crunch using a cable machine", &[]),
+    of_kind(ExerciseKind::Bodyweight, lift("Mountain Climbers", "Core", "bodyweight", "Bodyweight mountain climbers", &[])),
+    of_kind(ExerciseKind::Bodyweight, lift("Bicycle Crunch", "Core", "bodyweight", "Bicycle crunch for obliques", &[])),
+    of_kind(ExerciseKind::Bodyweight, lift("Ab Rollout", "Core", "ab wheel", "Ab rollout using an ab wheel", &["Ab Wheel"])),
+    of_kind(ExerciseKind::Timed, lift("Side Plank", "Core", "bodyweight", "Side plank for obliques", &[])),
+    of_kind(ExerciseKind::Bodyweight, lift("Toe Touches", "Core", "bodyweight", "Toe touches for upper abs", &[])),
+    of_kind(ExerciseKind::Bodyweight, lift("Chin-Up", "Back", "pull-up bar", "Underhand-grip bodyweight pull-up", &["Chin Up"])),
+    of_kind(
+        ExerciseKind::Assisted,
+        lift("Assisted Pull-Up (Machine)", "Back", "machine", "Pull-up with counterweight assistance", &["Assisted Pull-Up"]),
+    ),
+    lift("Hip Thrust (Barbell)", "Legs", "barbell", "Glute bridge from a bench with a barbell", &["Hip Thrust"]),
+    lift("Bulgarian Split Squat (Dumbbell)", "Legs", "dumbbell", "Rear-foot-elevated split squat with dumbbells", &["Split Squat"]),
+    of_kind(ExerciseKind::Timed, lift("Dead Hang", "Back", "pull-up bar", "Hanging from a bar for grip and shoulders", &[])),
+    of_kind(ExerciseKind::Cardio, lift("Running", "Cardio", "none", "Running outdoors or on a treadmill", &["Run", "Treadmill"])),
+    of_kind(ExerciseKind::Cardio, lift("Rowing (Machine)", "Cardio", "rowing machine", "Indoor rowing", &["Rower", "Erg"])),
+    of_kind(ExerciseKind::Cardio, lift("Cycling", "Cardio", "bike", "Cycling outdoors or on a stationary bike", &["Bike"])),
+];
+
+/// Loads the catalogue into `master_exercises`, updating entries that already
+/// exist by name, then gives existing users anything new to them.
+pub fn seed(conn: &Connection) -> Result<()> {
+    for exercise in CATALOGUE {
+        let existing: Option<u32> = conn
+            .query_row(
+                "SELECT id FROM master_exercises WHERE name = ?1 COLLATE NOCASE",
+                params![exercise.name],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let master_id = match existing {
+            Some(id) => {
+                conn.execute(
+                    "UPDATE master_exercises
+                     SET name = ?1, muscle_group = ?2, equipment = ?3, kind = ?4, description = ?5,
+                         catalogue_version = ?6
+                     WHERE id = ?7",
+                    params![
+                        exercise.name,
+                        exercise.muscle_group,
+                        exercise.equipment,
+                        exercise.kind.as_str(),
+                        exercise.description,
+                        exercise.since,
+                        id,
+                    ],
+                )?;
+                id
+            }
+            None => {
+                conn.execute(
+                    "INSERT INTO master_exercises (name, muscle_group, equipment, kind, description, catalogue_version)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    params![
+                        exercise.name,
+                        exercise.muscle_group,
+                        exercise.equipment,
+                        exercise.kind.as_str(),
+                        exercise.description,
+                        exercise.since,
+                    ],
+                )?;
+                conn.last_insert_rowid() as u32
+            }
+        };
+
+        conn.execute("DELETE FROM master_exercise_aliases WHERE master_exercise_id = ?1", params![master_id])?;
+        for alias in exercise.aliases {
+            conn.execute(
+                "INSERT OR IGNORE INTO master_exercise_aliases (master_exercise_id, alias) VALUES (?1, ?2)",
+                params![master_id, alias],
+            )?;
+        }
+    }
+
+    sync_users(conn)?;
+    Ok(())
+}
+
+/// Gives every user the master exercises added since they were last synced,
+/// returning how many exercises were created. An exercise the user already
+/// has under the same name or an alias is linked instead of copied, and each
+/// master exercise is only ever offered once, so ones a user has since
+/// renamed or merged away don't come back.
+pub fn sync_users(conn: &Connection) -> Result<u32> {
+    let latest: u32 = conn.query_row(
+        "SELECT COALESCE(MAX(catalogue_version), 0) FROM master_exercises",
+        [],
+        |row| row.get(0),
+    )?;
+    let mut users = conn.prepare("SELECT id, catalogue_version FROM users WHERE catalogue_version < ?1")?;
+    let users = users
+        .query_map(params![latest], |row| Ok((row.get::<_, u32>(0)?, row.get::<_, u32>(1)?)))?
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut find_existing = conn.prepare(
+        "SELECT ue.id FROM user_exercises ue
+         WHERE ue.user_id = ?1
+           AND (ue.master_exercise_id = ?2
+                OR trim(ue.name) = ?3 COLLATE NOCASE
+                OR trim(ue.name) COLLATE NOCASE IN (SELECT alias FROM master_exercise_aliases WHERE master_exercise_id = ?2))
+         ORDER BY ue.master_exercise_id IS NOT ?2, ue.id
+         LIMIT 1",
+    )?;
+    let mut created = 0;
+
+    for (user_id, synced_version) in users {
+        let mut masters = conn.prepare("SELECT id, name FROM master_exercises WHERE catalogue_version > ?1")?;
+        let masters = masters
+            .query_map(params![synced_version], |row| Ok((row.get::<_, u32>(0)?, row.get::<_, String>(1)?)))?
+            .collect::<Result<Vec<_>>>()?;
+
+        for (master_id, name) in masters {
+            let existing: Option<u32> = find_existing
+                .query_row(params![user_id, master_id, name], |row| row.get(0))
+                .optional()?;
+            match existing {
+                Some(exercise_id) => {
+                    conn.execute(
+                        "UPDATE user_exercises SET master_exercise_id = ?1
+                         WHERE id = ?2 AND master_exercise_id IS NULL",
+                        params![master_id, exercise_id],
+                    )?;
+                }
+                None => {
+                    conn.execute(
+                        "INSERT INTO user_exercises (user_id, name, description, muscle_group, kind, master_exercise_id)
+                         SELECT ?1, name, description, muscle_group, kind, id FROM master_exercises WHERE id = ?2",
+                        params![user_id, master_id],
+                    )?;
+                    created += 1;
+                }
+            }
+        }
+
+        conn.execute("UPDATE users SET catalogue_version = ?1 WHERE id = ?2", params![latest, user_id])?;
+    }
+
+    Ok(created)
+}
@@ -0,0 +1,195 @@
+use std::{fs, net::IpAddr, path::Path};
+
+use serde::Deserialize;
+
+/// Server settings.
+///
+/// Values are layered, each source overriding the previous one:
+/// built-in defaults, the TOML config file, `WT_*` environment variables and
+/// finally `--flag value` command line arguments. Every key can be set in all
+/// three places, e.g. `database_path` in the file, `WT_DATABASE_PATH` in the
+/// environment or `--database-path` on the command line.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub listen_addr: String,
+    /// Set to false to serve plain HTTP, e.g. behind a TLS-terminating proxy.
+    pub tls: bool,
+    pub tls_key_path: String,
+    pub tls_cert_path: String,
+    pub database_path: String,
+    pub db_pool_size: usize,
+    pub upload_dir: String,
+    pub processed_dir: String,
+    /// Uploaded and processed videos older than this are deleted.
+    pub file_max_age_secs: u64,
+    pub cleanup_interval_secs: u64,
+    pub session_lifetime_hours: i64,
+    /// Largest request body accepted, which in practice bounds video uploads.
+    pub max_upload_bytes: usize,
+    pub workers: usize,
+    pub queue_depth: usize,
+    /// Peers whose `X-Forwarded-For` / `X-Forwarded-Proto` headers are believed.
+    /// Comma-separated when given through the environment or a flag.
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            listen_addr: "0.0.0.0:25561".to_string(),
+            tls: true,
+            tls_key_path: "ssl/server.key".to_string(),
+            tls_cert_path: "ssl/server.crt".to_string(),
+            database_path: "workout_tracker.db".to_string(),
+            db_pool_size: 8,
+            upload_dir: "./uploads".to_string(),
+            processed_dir: "./processed".to_string(),
+            file_max_age_secs: 3600,
+            cleanup_interval_secs: 600,
+            session_lifetime_hours: 24,
+            max_upload_bytes: 200 * 1024 * 1024,
+            workers: 16,
+            queue_depth: 64,
+            trusted_proxies: Vec::new(),
+        }
+    }
+}
+
+const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+impl Config {
+    /// Builds the configuration from the process environment and arguments.
+    ///
+    /// The file is taken from `--config`, then `WT_CONFIG`, then
+    /// `config.toml` if it exists; an explicitly named file must exist.
+    pub fn load() -> Result<Config, String> {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        let env: Vec<(String, String)> = std::env::vars().collect();
+        Config::load_from(&args, &env)
+    }
+
+    pub fn load_from(args: &[String], env: &[(String, String)]) -> Result<Config, String> {
+        let flags = parse_flags(args)?;
+        let env_value = |name: &str| env.iter().find(|(k, _)| k == name).map(|(_, v)| v.clone());
+
+        let explicit_path = flags
+            .iter()
+            .find(|(k, _)| k == "config")
+            .map(|(_, v)| v.clone())
+            .or_else(|| env_value("WT_CONFIG"));
+
+        let mut config = match &explicit_path {
+            Some(path) => Config::from_file(path)?,
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => Config::from_file(DEFAULT_CONFIG_PATH)?,
+            None => Config::default(),
+        };
+
+        for key in KEYS {
+            if let Some(value) = env_value(&format!("WT_{}", key.to_ascii_uppercase())) {
+                config.set(key, &value)?;
+            }
+        }
+
+        for (key, value) in flags.iter().filter(|(k, _)| k != "config") {
+            config.set(key, value)?;
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Rejects settings the server cannot run with: the worker pool needs at
+    /// least one thread, and a queue depth of 0 would turn almost every
+    /// connection away with 503.
+    fn validate(&self) -> Result<(), String> {
+        if self.workers == 0 {
+            return Err("workers must be at least 1".to_string());
+        }
+        if self.queue_depth == 0 {
+            return Err("queue_depth must be at least 1".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn from_file(path: &str) -> Result<Config, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read config file {}: {}", path, e))?;
+        toml::from_str(&contents).map_err(|e| format!("Invalid config file {}: {}", path, e))
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        fn parse<T: std::str::FromStr>(key: &str, value: &str) -> Result<T, String> {
+            value
+                .parse()
+                .map_err(|_| format!("Invalid value for {}: {}", key, value))
+        }
+
+        match key {
+            "listen_addr" => self.listen_addr = value.to_string(),
+            "tls" => self.tls = parse(key, value)?,
+            "tls_key_path" => self.tls_key_path = value.to_string(),
+            "tls_cert_path" => self.tls_cert_path = value.to_string(),
+            "database_path" => self.database_path = value.to_string(),
+            "db_pool_size" => self.db_pool_size = parse(key, value)?,
+            "upload_dir" => self.upload_dir = value.to_string(),
+            "processed_dir" => self.processed_dir = value.to_string(),
+            "file_max_age_secs" => self.file_max_age_secs = parse(key, value)?,
+            "cleanup_interval_secs" => self.cleanup_interval_secs = parse(key, value)?,
+            "session_lifetime_hours" => self.session_lifetime_hours = parse(key, value)?,
+            "max_upload_bytes" => self.max_upload_bytes = parse(key, value)?,
+            "workers" => self.workers = parse(key, value)?,
+            "queue_depth" => self.queue_depth = parse(key, value)?,
+            "trusted_proxies" => {
+                self.trusted_proxies = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|ip| !ip.is_empty())
+                    .map(|ip| parse(key, ip))
+                    .collect::<Result<_, _>>()?
+            }
+            _ => return Err(format!("Unknown setting: {}", key)),
+        }
+        Ok(())
+    }
+}
+
+const KEYS: &[&str] = &[
+    "listen_addr",
+    "tls",
+    "tls_key_path",
+    "tls_cert_path",
+    "database_path",
+    "db_pool_size",
+    "upload_dir",
+    "processed_dir",
+    "file_max_age_secs",
+    "cleanup_interval_secs",
+    "session_lifetime_hours",
+    "max_upload_bytes",
+    "workers",
+    "queue_depth",
+    "trusted_proxies",
+];
+
+/// Turns `--some-key value` / `--some-key=value` pairs into `(some_key, value)`.
+fn parse_flags(args: &[String]) -> Result<Vec<(String, String)>, String> {
+    let mut flags = Vec::new();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let Some(flag) = arg.strip_prefix("--") else {
+            return Err(format!("Unexpected argument: {}", arg));
+        };
+        let (name, value) = match flag.split_once('=') {
+            Some((name, value)) => (name, value.to_string()),
+            None => match args.next() {
+                Some(value) => (flag, value.clone()),
+                None => return Err(format!("Missing value for --{}", flag)),
+            },
+        };
+        flags.push((name.replace('-', "_"), value));
+    }
+
+    Ok(flags)
+}
@@ -1,24 +1,58 @@
-use crate::wt_types::{Set, Workout};
-use chrono::{DateTime, Datelike, Duration, Local, NaiveDateTime, TimeZone, Utc};
+use crate::exercise_kind::ExerciseKind;
+use crate::migrations;
+use crate::one_rep_max::OneRepMaxFormula;
+use crate::personal_records;
+use crate::progression::{self, ProgressionLogEntry, ProgressionRule};
+use crate::time_buckets::{Bucket, TimeRange};
+use crate::units::WeightUnit;
+use crate::user_time::{self, UserClock};
+use crate::wt_types::{
+    DraftExercise, ExerciseGroup, ExerciseRecord, ExerciseUpdate, PreferencesUpdate, Program, ProgramPosition, ProgramRequest,
+    ProgramWeek, Set, SetTarget, SetType, TodaysWorkout, TrainingMax, UserPreferences, Workout, WorkoutDetail,
+    WorkoutDetailExercise, WorkoutDraft,
+    WorkoutDetailSet, WorkoutUpdate,
+};
+use chrono::{Duration, NaiveDate, NaiveDateTime, Utc};
 use rand::Rng;
-use rusqlite::{params, Connection, Error, OptionalExtension, Result};
+use rusqlite::{
+    functions::FunctionFlags, params, Connection, Error, OptionalExtension, Result, Row, Transaction,
+    TransactionBehavior,
+};
 use serde::{Deserialize, Serialize};
 use bcrypt::{hash, verify, DEFAULT_COST};
+use std::collections::HashMap;
 
 #[derive(Serialize)]
 pub struct HistorySet {
     pub reps: String,
     pub weight: String,
+    /// `weight` rounded to what can be loaded on a bar.
+    pub plate_weight: String,
+    pub unit: WeightUnit,
+    pub set_type: SetType,
+    pub rpe: Option<f64>,
+    pub rir: Option<u32>,
+    pub notes: Option<String>,
+    pub duration_seconds: Option<u32>,
+    pub distance_meters: Option<f64>,
+    pub bodyweight: Option<f64>,
+    /// Record types this set earned, e.g. `heaviest_weight`.
+    pub records: Vec<String>,
 }
 
 #[derive(Serialize)]
 pub struct HistoryExercise {
     pub name: String,
+    pub kind: ExerciseKind,
+    pub group: Option<ExerciseGroup>,
     pub sets: Vec<HistorySet>,
+    /// Records earned by the exercise as a whole, i.e. `session_volume`.
+    pub records: Vec<String>,
 }
 
 #[derive(Serialize)]
 pub struct HistoryData {
+    pub id: u32,
     pub date: String,
     pub date_epoch: u64,
     pub duration: String,
@@ -37,9 +71,11 @@ pub struct Exercise {
     pub id: u32,
     pub _user_id: u32,
     pub name: String,
-    pub _description: Option<String>,
+    pub description: Option<String>,
     pub muscle_group: String,
     pub best_set: Option<String>,
+    pub kind: ExerciseKind,
+    pub archived: bool,
 }
 
 pub struct _DbWorkout {
@@ -59,12 +95,38 @@ pub struct _WorkoutExercise {
 
 pub struct DatabaseHandler {
     pub conn: Connection,
+    pub session_lifetime: Duration,
 }
 
 impl DatabaseHandler {
     pub fn new(db_path: &str) -> Result<Self> {
         let conn = Connection::open(db_path)?;
-        Ok(DatabaseHandler { conn })
+
+        // WAL lets readers carry on while another connection writes, and the
+        // busy timeout makes concurrent writers queue instead of failing.
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        conn.busy_timeout(std::time::Duration::from_secs(5))?;
+
+        DatabaseHandler::from_connection(conn)
+    }
+
+    pub fn from_connection(conn: Connection) -> Result<Self> {
+        register_functions(&conn)?;
+        Ok(DatabaseHandler {
+            conn,
+            session_lifetime: Duration::hours(24),
+        })
+    }
+
+    pub fn with_session_lifetime(mut self, session_lifetime: Duration) -> Self {
+        self.session_lifetime = session_lifetime;
+        self
+    }
+
+    /// Brings the schema up to date; returns the number of migrations applied.
+    pub fn migrate(&self) -> Result<u32> {
+        migrations::run(&self.conn)
     }
 
     pub fn _delete_user(&self, user_id: u32) -> Result<()> {
@@ -78,14 +140,103 @@ impl DatabaseHandler {
         let user_id = self.get_user_id_from_token(&request.user_id)?;
 
         self.conn.execute(
-            "INSERT INTO user_exercises (user_id, name, muscle_group) VALUES (?1, ?2, ?3)",
-            params![user_id, request.name, request.body_part],
+            "INSERT INTO user_exercises (user_id, name, muscle_group, kind) VALUES (?1, ?2, ?3, ?4)",
+            params![user_id, request.name, request.body_part, request.kind.as_str()],
         )?;
         let exercise_id = self.conn.last_insert_rowid() as u32;
         Ok(exercise_id)
     }
 
-    pub fn get_user_exercises(&self, user_id: u32) -> Result<Vec<Exercise>> {
+    /// Whether the user already has an exercise called `name`, ignoring case
+    /// and surrounding spaces. `except` is left out, so renaming an exercise
+    /// to a new spelling of its own name is allowed.
+    pub fn exercise_name_taken(&self, user_id: u32, name: &str, except: Option<u32>) -> Result<bool> {
+        self.conn.query_row(
+            "SELECT EXISTS (
+                 SELECT 1 FROM user_exercises
+                 WHERE user_id = ?1 AND trim(name) = trim(?2) COLLATE NOCASE AND id IS NOT ?3)",
+            params![user_id, name, except],
+            |row| row.get(0),
+        )
+    }
+
+    /// Applies an edit to one of the user's exercises. Returns false if the
+    /// exercise does not exist or belongs to another user.
+    pub fn update_exercise(&self, user_id: u32, exercise_id: u32, update: &ExerciseUpdate) -> Result<bool> {
+        let updated = self.conn.execute(
+            "UPDATE user_exercises
+             SET name = COALESCE(trim(?1), name),
+                 muscle_group = COALESCE(?2, muscle_group),
+                 description = COALESCE(?3, description),
+                 archived = COALESCE(?4, archived)
+             WHERE id = ?5 AND user_id = ?6",
+            params![update.name, update.body_part, update.description, update.archived, exercise_id, user_id],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Moves every workout and template entry of `source` onto `target`, then
+    /// deletes `source` and recomputes `target`'s records over the combined
+    /// history. Returns false if either exercise isn't the user's.
+    pub fn merge_exercises(&self, user_id: u32, source: u32, target: u32) -> Result<bool> {
+        let tx = Transaction::new_unchecked(&self.conn, TransactionBehavior::Immediate)?;
+        if source == target || !self.owns_exercises(user_id, &[source, target])? {
+            return Ok(false);
+        }
+
+        tx.execute("DELETE FROM personal_records WHERE exercise_id = ?1", params![source])?;
+        // Where both have a training max the survivor keeps its own.
+        tx.execute(
+            "UPDATE OR IGNORE training_maxes SET exercise_id = ?1 WHERE exercise_id = ?2",
+            params![target, source],
+        )?;
+        tx.execute("DELETE FROM training_maxes WHERE exercise_id = ?1", params![source])?;
+        tx.execute(
+            "UPDATE progression_log SET exercise_id = ?1 WHERE exercise_id = ?2",
+            params![target, source],
+        )?;
+        tx.execute(
+            "UPDATE progression_changes SET target_id = ?1 WHERE target = 'training_max' AND target_id = ?2",
+            params![target, source],
+        )?;
+        tx.execute(
+            "UPDATE user_exercises
+             SET master_exercise_id = (SELECT master_exercise_id FROM user_exercises WHERE id = ?2)
+             WHERE id = ?1 AND master_exercise_id IS NULL",
+            params![target, source],
+        )?;
+        tx.execute(
+            "UPDATE workout_exercises SET exercise_id = ?1 WHERE exercise_id = ?2",
+            params![target, source],
+        )?;
+        // Templates that already list the survivor keep only its entry.
+        tx.execute(
+            "DELETE FROM template_sets WHERE template_exercise_id IN
+                 (SELECT id FROM template_exercises
+                  WHERE exercise_id = ?2
+                    AND template_id IN (SELECT template_id FROM template_exercises WHERE exercise_id = ?1))",
+            params![target, source],
+        )?;
+        tx.execute(
+            "DELETE FROM template_exercises
+             WHERE exercise_id = ?2
+               AND template_id IN (SELECT template_id FROM template_exercises WHERE exercise_id = ?1)",
+            params![target, source],
+        )?;
+        tx.execute(
+            "UPDATE template_exercises SET exercise_id = ?1 WHERE exercise_id = ?2",
+            params![target, source],
+        )?;
+        tx.execute("DELETE FROM user_exercises WHERE id = ?1", params![source])?;
+        personal_records::rebuild_for_exercises(&tx, user_id, &[target], None)?;
+
+        tx.commit()?;
+        Ok(true)
+    }
+
+    /// The user's exercises with their best set; archived ones only if asked for.
+    pub fn get_user_exercises(&self, user_id: u32, include_archived: bool) -> Result<Vec<Exercise>> {
+        let unit = self.weight_unit(user_id)?;
         let mut stmt = self.conn.prepare(
             "WITH ranked_sets AS (
                 SELECT 
@@ -94,17 +245,22 @@ impl DatabaseHandler {
                     user_exercises.name, 
                     user_exercises.description, 
                     user_exercises.muscle_group,
+                    user_exercises.kind,
+                    user_exercises.archived,
                     COALESCE(sets.reps, 0) AS reps,
                     COALESCE(sets.weight, 0) AS weight,
-                    COALESCE((sets.weight * sets.reps), 0) AS volume,
+                    sets.duration_seconds,
+                    sets.distance_meters,
+                    sets.bodyweight,
                     ROW_NUMBER() OVER (
                         PARTITION BY user_exercises.id 
-                        ORDER BY (sets.weight * sets.reps) DESC NULLS LAST
+                        ORDER BY set_score(user_exercises.kind, sets.weight, sets.reps, sets.duration_seconds,
+                                           sets.distance_meters, sets.bodyweight) DESC NULLS LAST
                     ) AS rank
                 FROM user_exercises
                 LEFT JOIN workout_exercises ON user_exercises.id = workout_exercises.exercise_id
                 LEFT JOIN sets ON workout_exercises.id = sets.workout_exercise_id
-                WHERE user_exercises.user_id = ?1
+                WHERE user_exercises.user_id = ?1 AND (?2 OR NOT user_exercises.archived)
                 )
                 SELECT 
                     ue_id, 
@@ -112,29 +268,44 @@ impl DatabaseHandler {
                     name, 
                     description, 
                     muscle_group, 
+                    kind,
                     weight, 
-                    reps
+                    reps,
+                    duration_seconds,
+                    distance_meters,
+                    bodyweight,
+                    archived
                 FROM ranked_sets
                 WHERE rank = 1;;
                 ",)?;
 
-        let exercise_iter = stmt.query_map(params![user_id], |row| {
+        let exercise_iter = stmt.query_map(params![user_id, include_archived], |row| {
+            let kind: ExerciseKind = row.get::<_, String>(5)?.parse().unwrap_or_default();
             Ok(Exercise {
                 id: row.get(0)?,
                 _user_id: row.get(1)?,
                 name: row.get(2)?,
-                _description: row.get(3)?,
+                description: row.get(3)?,
                 muscle_group: row.get(4)?,
                 best_set: {
-                    let weight: Option<f64> = row.get(5).ok();
-                    let reps: Option<u32> = row.get(6).ok();
-                    if let (Some(w), Some(r)) = (weight, reps) {
-                        let bs = format!("{} x {}kg", r, w);
-                        Some(bs)
+                    let weight: Option<f64> = row.get(6).ok();
+                    let reps: Option<u32> = row.get(7).ok();
+                    if let (Some(weight), Some(reps)) = (weight, reps) {
+                        let best = Set {
+                            reps,
+                            weight,
+                            duration_seconds: row.get(8)?,
+                            distance_meters: row.get(9)?,
+                            bodyweight: row.get(10)?,
+                            ..Default::default()
+                        };
+                        Some(kind.describe(&best.in_unit(unit)))
                     } else {
                         None
                     }
                 },
+                kind,
+                archived: row.get(11)?,
             })
         })?;
 
@@ -157,333 +328,659 @@ impl DatabaseHandler {
         }
     }
 
-    pub fn save_workout(&self, workout: Workout, user_id: u32) -> Result<u32> {
-        
-        self.conn.execute(
-            "INSERT INTO workouts (user_id, start_time, end_time, notes, prs) VALUES (?1, ?2, ?3, ?4, 0)",
+    /// Saves a workout with all of its exercises and sets in one transaction.
+    ///
+    /// If the workout carries a `client_id` that was already saved for this
+    /// user, nothing is inserted and the original workout is returned.
+    pub fn save_workout(&self, workout: Workout, user_id: u32) -> Result<SavedWorkout> {
+        // IMMEDIATE takes the write lock up front, so two retries of the same
+        // workout racing each other are serialised and the second one sees the first.
+        let tx = Transaction::new_unchecked(&self.conn, TransactionBehavior::Immediate)?;
+
+        if let Some(client_id) = &workout.client_id {
+            let existing: Option<u32> = tx
+                .query_row(
+                    "SELECT id FROM workouts WHERE user_id = ?1 AND client_id = ?2",
+                    params![user_id, client_id],
+                    |row| row.get(0),
+                )
+                .optional()?;
+
+            if let Some(workout_id) = existing {
+                let sets_saved: u32 = tx.query_row(
+                    "SELECT COUNT(*) FROM sets s
+                     JOIN workout_exercises we ON s.workout_exercise_id = we.id
+                     WHERE we.workout_id = ?1",
+                    params![workout_id],
+                    |row| row.get(0),
+                )?;
+                return Ok(SavedWorkout { workout_id, sets_saved, replayed: true });
+            }
+        }
+
+        tx.execute(
+            "INSERT INTO workouts (user_id, start_time, end_time, notes, prs, client_id, template_id)
+             VALUES (?1, ?2, ?3, ?4, 0, ?5, (SELECT id FROM templates WHERE id = ?6 AND user_id = ?1))",
             params![
                 user_id,
                 workout.start_time,
                 workout.end_time,
-                "",
+                workout.notes,
+                workout.client_id,
+                workout.template_id,
             ],
         )?;
-        let workout_id = self.conn.last_insert_rowid() as u32;
-
-        
-        let mut workout_exercise_ids = Vec::new(); 
+        let workout_id = tx.last_insert_rowid() as u32;
 
-        for exercise in &workout.exercises {
-            
-            self.conn.execute(
-                "INSERT INTO workout_exercises (workout_id, exercise_id) VALUES (?1, ?2)",
-                params![workout_id, exercise.exercise_id],
-            )?;
-            let workout_exercise_id = self.conn.last_insert_rowid() as u32;
-            workout_exercise_ids.push(workout_exercise_id);
+        let unit = self.weight_unit(user_id)?;
+        let total_sets_saved = insert_workout_exercises(&tx, workout_id, &workout.exercises, unit)?;
+        if let Some(template_id) = workout.template_id {
+            progression::apply(&tx, user_id, template_id, workout_id, unit)?;
+            advance_program(&tx, user_id, template_id, workout_id)?;
         }
 
-        
-        let mut total_sets_saved = 0; 
-
-        for (exercise, workout_exercise_id) in
-            workout.exercises.iter().zip(workout_exercise_ids.iter())
-        {
-            for (set_index, set) in exercise.sets.iter().enumerate() {
-                
-                self.conn.execute(
-                    "INSERT INTO sets (workout_exercise_id, set_number, weight, reps) VALUES (?1, ?2, ?3, ?4)",
-                    params![
-                        workout_exercise_id, 
-                        set_index + 1,       
-                        set.weight,          
-                        set.reps,            
-                    ],
-                )?;
-                total_sets_saved += 1; 
-            }
-        }
+        let exercise_ids: Vec<u32> = workout.exercises.iter().map(|e| e.exercise_id).collect();
+        personal_records::rebuild_for_exercises(&tx, user_id, &exercise_ids, Some(&workout.start_time))?;
 
-        
-        Ok(total_sets_saved)
-    }
-
-    pub fn get_history_data(&self, user_id: u32) -> Result<Vec<HistoryData>> {
-        
-        let mut stmt = self.conn.prepare(
-            "SELECT id, start_time, end_time, prs FROM workouts WHERE user_id = ?1 ORDER BY start_time DESC"
-        )?;
-        let workout_iter = stmt.query_map(params![user_id], |row| {
-            Ok(WorkoutRow {
-                workout_id: row.get(0)?,
-                start_time: row.get(1)?,
-                end_time: row.get(2)?,
-                prs: row.get(3)?,
-            })
-        })?;
-
-        let mut history_vec = Vec::new();
-        for workout_res in workout_iter {
-            let workout = workout_res?;
-
-            println!("Started {}, Ended {}", workout.start_time, workout.end_time);
+        tx.commit()?;
 
-            
-            let duration = compute_duration(&workout.start_time, &workout.end_time)
-                .unwrap_or_else(|| "Unknown".to_string());
-
-            
-            
-            let date_dt = parse_as_date_time_utc(&workout.start_time);
-            let date = date_dt.format("%A, %d %b").to_string();
-            let date_epoch = date_dt.timestamp() as u64;
+        Ok(SavedWorkout { workout_id, sets_saved: total_sets_saved, replayed: false })
+    }
 
-            
-            let mut stmt_ex = self.conn.prepare(
-                "SELECT we.id, ue.name 
-                 FROM workout_exercises we 
-                 JOIN user_exercises ue ON we.exercise_id = ue.id 
-                 WHERE we.workout_id = ?1",
-            )?;
-            let exercise_iter = stmt_ex.query_map(params![workout.workout_id], |row| {
-                Ok(ExerciseRow {
-                    workout_exercise_id: row.get(0)?,
-                    name: row.get(1)?,
-                })
-            })?;
-
-            let mut history_exercises = Vec::new();
-            let mut workout_total_volume: u64 = 0;
-
-            for ex_res in exercise_iter {
-                let ex = ex_res?;
-                
-                let mut stmt_set = self.conn.prepare(
-                    "SELECT set_number, weight, reps 
-                     FROM sets 
-                     WHERE workout_exercise_id = ?1 
-                     ORDER BY set_number ASC",
-                )?;
-                let set_iter = stmt_set.query_map(params![ex.workout_exercise_id], |row| {
-                    Ok(HistorySet {
-                        
-                        
-                        reps: row.get::<_, u32>(2)?.to_string(),
-                        weight: row.get::<_, f64>(1)?.to_string(),
+    /// Loads one workout with its exercises and sets, or `None` if it does
+    /// not exist or belongs to another user.
+    pub fn get_workout(&self, user_id: u32, workout_id: u32) -> Result<Option<WorkoutDetail>> {
+        let workout = self
+            .conn
+            .query_row(
+                "SELECT id, start_time, end_time, notes, template_id FROM workouts WHERE id = ?1 AND user_id = ?2",
+                params![workout_id, user_id],
+                |row| {
+                    Ok(WorkoutDetail {
+                        id: row.get(0)?,
+                        start_time: row.get(1)?,
+                        end_time: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+                        notes: row.get::<_, Option<String>>(3)?.unwrap_or_default(),
+                        template_id: row.get(4)?,
+                        exercises: Vec::new(),
                     })
-                })?;
-
-                let mut history_sets = Vec::new();
-                for set_res in set_iter {
-                    let hs = set_res?;
-                    
-                    let weight: f64 = hs.weight.parse().unwrap_or(0.0);
-                    let reps: u32 = hs.reps.parse().unwrap_or(0);
-                    workout_total_volume += (weight * reps as f64) as u64;
-                    history_sets.push(hs);
-                }
+                },
+            )
+            .optional()?;
+        let clock = self.user_clock(user_id)?;
+        let unit = self.weight_unit(user_id)?;
+        let workout = workout.map(|mut workout| {
+            // Shown with the user's offset, which PUT accepts back unchanged.
+            workout.start_time = clock.format_rfc3339(&workout.start_time).unwrap_or(workout.start_time);
+            workout.end_time = clock.format_rfc3339(&workout.end_time).unwrap_or(workout.end_time);
+            workout
+        });
+
+        let Some(mut workout) = workout else {
+            return Ok(None);
+        };
 
-                history_exercises.push(HistoryExercise {
-                    name: ex.name,
-                    sets: history_sets,
+        let mut stmt = self.conn.prepare(
+            "SELECT we.id, we.exercise_id, ue.name, s.id, s.set_number,
+                    s.reps, s.weight, s.set_type, s.rpe, s.rir, s.notes,
+                    s.duration_seconds, s.distance_meters, s.bodyweight, g.position, g.group_type, ue.kind
+             FROM workout_exercises we
+             JOIN user_exercises ue ON we.exercise_id = ue.id
+             LEFT JOIN workout_exercise_groups g ON g.id = we.group_id
+             LEFT JOIN sets s ON s.workout_exercise_id = we.id
+             WHERE we.workout_id = ?1
+             ORDER BY we.id, s.set_number",
+        )?;
+        let mut rows = stmt.query(params![workout_id])?;
+
+        let mut current_workout_exercise = None;
+        while let Some(row) = rows.next()? {
+            let workout_exercise_id: u32 = row.get(0)?;
+            if current_workout_exercise != Some(workout_exercise_id) {
+                current_workout_exercise = Some(workout_exercise_id);
+                workout.exercises.push(WorkoutDetailExercise {
+                    exercise_id: row.get(1)?,
+                    name: row.get(2)?,
+                    kind: row.get::<_, String>(16)?.parse().unwrap_or_default(),
+                    group: group_from_row(row, 14)?,
+                    sets: Vec::new(),
                 });
             }
 
-            history_vec.push(HistoryData {
-                date,
-                date_epoch,
-                duration,
-                prs: workout.prs,
-                total_volume: workout_total_volume,
-                exercises: history_exercises,
-            });
+            if let Some(set_id) = row.get::<_, Option<u32>>(3)? {
+                if let Some(exercise) = workout.exercises.last_mut() {
+                    exercise.sets.push(WorkoutDetailSet {
+                        id: set_id,
+                        set_number: row.get(4)?,
+                        set: set_from_row(row, 5)?.in_unit(unit),
+                    });
+                }
+            }
         }
 
-        Ok(history_vec)
+        Ok(Some(workout))
     }
 
-    pub fn get_workouts_per_week(&self, user_id: u32) -> Result<WorkoutsPerWeek> {
-        let now = Local::now();
-        let mut week_starts = Vec::new();
+    /// Applies an edit to a saved workout in one transaction. Returns false if
+    /// the workout does not exist or belongs to another user.
+    pub fn update_workout(&self, user_id: u32, workout_id: u32, update: &WorkoutUpdate) -> Result<bool> {
+        let tx = Transaction::new_unchecked(&self.conn, TransactionBehavior::Immediate)?;
 
-        
-        for i in (0..8).rev() {
-            let start_of_week = now.date_naive() - Duration::weeks(i);
-            let start_of_week = start_of_week
-                - Duration::days(start_of_week.weekday().num_days_from_monday() as i64);
-            week_starts.push(start_of_week);
-        }
+        let Some(previous_start) = workout_start_time(&tx, user_id, workout_id)? else {
+            return Ok(false);
+        };
+        let template_id: Option<u32> =
+            tx.query_row("SELECT template_id FROM workouts WHERE id = ?1", params![workout_id], |row| row.get(0))?;
+        tx.execute(
+            "UPDATE workouts
+             SET start_time = COALESCE(?1, start_time),
+                 end_time = COALESCE(?2, end_time),
+                 notes = COALESCE(?3, notes)
+             WHERE id = ?4",
+            params![update.start_time, update.end_time, update.notes, workout_id],
+        )?;
 
-        let mut workout_counts = vec![0; 8];
+        // Moving a workout in time can change which sets were records too.
+        let mut affected_exercises = workout_exercise_ids(&tx, workout_id)?;
+        if let Some(exercises) = &update.exercises {
+            let unit = self.weight_unit(user_id)?;
+            delete_workout_children(&tx, workout_id)?;
+            insert_workout_exercises(&tx, workout_id, exercises, unit)?;
+            affected_exercises.extend(exercises.iter().map(|e| e.exercise_id));
+            // Judged again on the new sets, unless later sessions or edits have
+            // already built on the first outcome.
+            if let Some(template_id) = template_id {
+                if progression::revert(&tx, workout_id)? {
+                    progression::apply(&tx, user_id, template_id, workout_id, unit)?;
+                }
+            }
+        }
+        let since = match &update.start_time {
+            Some(start_time) => previous_start.min(start_time.clone()),
+            None => previous_start,
+        };
+        personal_records::rebuild_for_exercises(&tx, user_id, &affected_exercises, Some(&since))?;
 
-        for (i, week_start) in week_starts.iter().enumerate() {
-            let week_end = *week_start + Duration::days(7);
+        tx.commit()?;
+        Ok(true)
+    }
 
-            let start_of_week_str = week_start.format("%Y-%m-%d").to_string();
-            let end_of_week_str = week_end.format("%Y-%m-%d").to_string();
+    /// Deletes a workout with its exercises and sets. Returns false if the
+    /// workout does not exist or belongs to another user.
+    pub fn delete_workout(&self, user_id: u32, workout_id: u32) -> Result<bool> {
+        let tx = Transaction::new_unchecked(&self.conn, TransactionBehavior::Immediate)?;
 
-            println!("Start {} End {}", start_of_week_str, end_of_week_str);
+        let Some(start_time) = workout_start_time(&tx, user_id, workout_id)? else {
+            return Ok(false);
+        };
 
-            let mut stmt = self.conn.prepare(
-                "SELECT COUNT(*) FROM workouts 
-                 WHERE user_id = ?1 
-                 AND start_time >= ?2 
-                 AND start_time < ?3",
-            )?;
+        let affected_exercises = workout_exercise_ids(&tx, workout_id)?;
+        tx.execute("DELETE FROM personal_records WHERE workout_id = ?1", params![workout_id])?;
+        if !progression::revert(&tx, workout_id)? {
+            progression::forget(&tx, workout_id)?;
+        }
+        revert_program_advance(&tx, workout_id)?;
+        delete_workout_children(&tx, workout_id)?;
+        tx.execute("DELETE FROM workouts WHERE id = ?1", params![workout_id])?;
+        personal_records::rebuild_for_exercises(&tx, user_id, &affected_exercises, Some(&start_time))?;
 
-            let count: u32 = stmt.query_row(
-                params![user_id, start_of_week_str, end_of_week_str],
-                |row| row.get(0),
-            )?;
+        tx.commit()?;
+        Ok(true)
+    }
 
-            workout_counts[i] = count;
+    /// True if every id refers to an exercise in this user's list.
+    pub fn owns_exercises(&self, user_id: u32, exercise_ids: &[u32]) -> Result<bool> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT COUNT(*) FROM user_exercises WHERE id = ?1 AND user_id = ?2")?;
+        for exercise_id in exercise_ids {
+            let count: u32 = stmt.query_row(params![exercise_id, user_id], |row| row.get(0))?;
+            if count == 0 {
+                return Ok(false);
+            }
         }
+        Ok(true)
+    }
 
-        let labels: Vec<String> = week_starts
-            .iter()
-            .map(|date| format!("{}/{}", date.day(), date.month()))
-            .collect();
-
-        Ok(WorkoutsPerWeek {
-            labels,
-            data: workout_counts,
-        })
+    /// Best estimated one-rep max in kg of each of the user's exercises that
+    /// has one, using their preferred formula over all working sets. With a
+    /// template, only its exercises are looked at.
+    pub fn estimated_one_rep_maxes(&self, user_id: u32, template_id: Option<u32>) -> Result<HashMap<u32, f64>> {
+        let formula = self.get_preferences(user_id)?.one_rep_max_formula;
+        let mut stmt = self.conn.prepare(
+            "SELECT we.exercise_id,
+                    MAX(e1rm(?2, set_load(ue.kind, s.weight, s.bodyweight), s.reps, COALESCE(s.rpe, 10 - s.rir)))
+             FROM sets s
+             JOIN workout_exercises we ON s.workout_exercise_id = we.id
+             JOIN user_exercises ue ON ue.id = we.exercise_id
+             JOIN workouts w ON we.workout_id = w.id
+             WHERE w.user_id = ?1 AND s.set_type != 'warmup'
+               AND (?3 IS NULL OR we.exercise_id IN (SELECT exercise_id FROM template_exercises WHERE template_id = ?3))
+             GROUP BY we.exercise_id
+             HAVING MAX(e1rm(?2, set_load(ue.kind, s.weight, s.bodyweight), s.reps, COALESCE(s.rpe, 10 - s.rir))) IS NOT NULL",
+        )?;
+        let rows = stmt.query_map(params![user_id, formula.as_str(), template_id], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?;
+        rows.collect()
     }
 
-    pub fn get_1_rep_maxes(&self, user_id: u32, exercise_id: u32) -> Result<OneRepMaxes> {
-        let now = Local::now();
-        let mut month_starts = Vec::new();
+    /// Kinds of the given exercises; ones the user doesn't own are left out.
+    pub fn exercise_kinds(&self, user_id: u32, exercise_ids: &[u32]) -> Result<HashMap<u32, ExerciseKind>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT kind FROM user_exercises WHERE id = ?1 AND user_id = ?2")?;
+        let mut kinds = HashMap::new();
+        for &exercise_id in exercise_ids {
+            let kind: Option<String> = stmt
+                .query_row(params![exercise_id, user_id], |row| row.get(0))
+                .optional()?;
+            if let Some(kind) = kind {
+                kinds.insert(exercise_id, kind.parse().unwrap_or_default());
+            }
+        }
+        Ok(kinds)
+    }
 
-        
-        for i in (0..8).rev() {
-            
-            let month_start = if now.month() as i32 - i < 1 {
-                
-                let adjusted_month = now.month() as i32 - i + 12; 
-                let final_month = if adjusted_month <= 12 {
-                    adjusted_month
-                } else {
-                    adjusted_month - 12
-                };
-                let adjusted_year = if adjusted_month <= 12 {
-                    now.year() - 1
-                } else {
-                    now.year()
-                };
+    /// Returns one page of a user's workouts, newest first, with their
+    /// exercises and sets.
+    ///
+    /// Filters pick which workouts are included; a matching workout is always
+    /// returned whole. Everything is read with a single query: the page of
+    /// workouts is chosen in a CTE and joined down to exercises and sets.
+    pub fn get_history_data(&self, user_id: u32, query: &HistoryQuery) -> Result<HistoryPage> {
+        let clock = self.user_clock(user_id)?;
+        let unit = self.weight_unit(user_id)?;
+        let from = query.from.map(|date| clock.start_of_day(date));
+        let to = query.to.and_then(|date| date.succ_opt()).map(|date| clock.start_of_day(date));
+        let (cursor_time, cursor_id) = match &query.cursor {
+            Some(cursor) => (Some(cursor.start_time.as_str()), Some(cursor.workout_id)),
+            None => (None, None),
+        };
 
-                now.with_month(final_month as u32)
-                    .unwrap()
-                    .with_year(adjusted_year)
-                    .unwrap()
-                    .with_day(1)
-                    .unwrap()
-            } else {
-                now.with_month(now.month() - i as u32)
-                    .unwrap()
-                    .with_day(1)
-                    .unwrap()
-            };
+        let mut stmt = self.conn.prepare(
+            "WITH page AS (
+                 SELECT w.id, w.start_time, w.end_time, w.prs
+                 FROM workouts w
+                 WHERE w.user_id = ?1
+                   AND (?2 IS NULL OR w.start_time >= ?2)
+                   AND (?3 IS NULL OR w.start_time < ?3)
+                   AND (?4 IS NULL OR (w.start_time, w.id) < (?4, ?5))
+                   AND (?6 IS NULL OR EXISTS (
+                         SELECT 1 FROM workout_exercises fe
+                         WHERE fe.workout_id = w.id AND fe.exercise_id = ?6))
+                   AND (?7 IS NULL OR EXISTS (
+                         SELECT 1 FROM workout_exercises fe
+                         JOIN user_exercises fue ON fue.id = fe.exercise_id
+                         WHERE fe.workout_id = w.id AND fue.muscle_group = ?7 COLLATE NOCASE))
+                 ORDER BY w.start_time DESC, w.id DESC
+                 LIMIT ?8
+             )
+             SELECT p.id, p.start_time, p.end_time, p.prs, we.id, ue.name,
+                    (SELECT group_concat(record_type) FROM personal_records WHERE set_id = s.id),
+                    (SELECT group_concat(record_type) FROM personal_records
+                     WHERE workout_id = p.id AND exercise_id = we.exercise_id AND set_id IS NULL),
+                    s.id, s.reps, s.weight, s.set_type, s.rpe, s.rir, s.notes,
+                    s.duration_seconds, s.distance_meters, s.bodyweight, g.position, g.group_type, ue.kind
+             FROM page p
+             LEFT JOIN (workout_exercises we JOIN user_exercises ue ON ue.id = we.exercise_id)
+                    ON we.workout_id = p.id
+             LEFT JOIN workout_exercise_groups g ON g.id = we.group_id
+             LEFT JOIN sets s ON s.workout_exercise_id = we.id
+             ORDER BY p.start_time DESC, p.id DESC, we.id, s.set_number",
+        )?;
 
-            month_starts.push(month_start);
-        }
+        // One extra workout tells us whether there is another page.
+        let mut rows = stmt.query(params![
+            user_id,
+            from,
+            to,
+            cursor_time,
+            cursor_id,
+            query.exercise_id,
+            query.muscle_group,
+            query.limit.map_or(-1, |limit| i64::from(limit) + 1),
+        ])?;
+
+        let mut history_vec: Vec<HistoryData> = Vec::new();
+        let mut page_rows: Vec<WorkoutRow> = Vec::new();
+        let mut current_workout_exercise = None;
+
+        while let Some(row) = rows.next()? {
+            let workout_id: u32 = row.get(0)?;
+            if page_rows.last().map(|w| w.workout_id) != Some(workout_id) {
+                let workout = WorkoutRow {
+                    workout_id,
+                    start_time: row.get(1)?,
+                    end_time: row.get(2)?,
+                    prs: row.get(3)?,
+                };
+                if Some(page_rows.len() as u32) == query.limit {
+                    page_rows.push(workout);
+                    break;
+                }
 
-        let mut highest_weights = vec![0.0; 8]; 
-        let mut previous_max_weight = 0.0; 
+                let duration = compute_duration(&workout.start_time, &workout.end_time)
+                    .unwrap_or_else(|| "Unknown".to_string());
+
+                history_vec.push(HistoryData {
+                    id: workout_id,
+                    date: clock
+                        .format(&workout.start_time, "%A, %d %b")
+                        .unwrap_or_else(|| "Unknown".to_string()),
+                    date_epoch: user_time::from_storage(&workout.start_time)
+                        .map_or(0, |time| time.timestamp().max(0) as u64),
+                    duration,
+                    prs: workout.prs,
+                    total_volume: 0,
+                    exercises: Vec::new(),
+                });
+                page_rows.push(workout);
+                current_workout_exercise = None;
+            }
 
-        
-        for (i, month_start) in month_starts.iter().enumerate() {
-            
-            let month_end = if month_start.month() == 12 {
-                Local.with_ymd_and_hms(month_start.year() + 1, 1, 1, 0, 0, 0).single()
-            } else {
-                Local.with_ymd_and_hms(month_start.year(), month_start.month() + 1, 1,0,0,0).single()
+            let Some(history) = history_vec.last_mut() else {
+                continue;
             };
 
-            let month_end = match month_end {
-                Some(dt) => dt,
-                None => {
-                    println!("Error: Could not calculate month_end");
-                    return Err(rusqlite::Error::QueryReturnedNoRows);
-                }
+            let Some(workout_exercise_id) = row.get::<_, Option<u32>>(4)? else {
+                continue;
             };
+            if current_workout_exercise != Some(workout_exercise_id) {
+                current_workout_exercise = Some(workout_exercise_id);
+                history.exercises.push(HistoryExercise {
+                    name: row.get(5)?,
+                    kind: row.get::<_, String>(20)?.parse().unwrap_or_default(),
+                    group: group_from_row(row, 18)?,
+                    sets: Vec::new(),
+                    records: split_records(row.get(7)?),
+                });
+            }
 
-            let start_of_month_str = month_start.format("%Y-%m-%d").to_string();
-            let end_of_month_str = month_end.format("%Y-%m-%d").to_string();
+            if row.get::<_, Option<u32>>(8)?.is_some() {
+                let set = set_from_row(row, 9)?;
+                if let Some(exercise) = history.exercises.last_mut() {
+                    if set.set_type != SetType::Warmup {
+                        history.total_volume += unit.convert_kg(exercise.kind.volume(&set)) as u64;
+                    }
+                    let set = set.in_unit(unit);
+                    exercise.sets.push(HistorySet {
+                        reps: set.reps.to_string(),
+                        weight: set.weight.to_string(),
+                        plate_weight: unit.round_to_plates(set.weight).to_string(),
+                        unit,
+                        set_type: set.set_type,
+                        rpe: set.rpe,
+                        rir: set.rir,
+                        notes: set.notes,
+                        duration_seconds: set.duration_seconds,
+                        distance_meters: set.distance_meters,
+                        bodyweight: set.bodyweight,
+                        records: split_records(row.get(6)?),
+                    });
+                }
+            }
+        }
 
-            let query = "SELECT MAX(weight) 
-                     FROM sets 
-                     JOIN workout_exercises we ON sets.workout_exercise_id = we.id
-                     JOIN workouts w ON we.workout_id = w.id
-                     WHERE we.exercise_id = ?1 
-                     AND w.user_id = ?2
-                     AND w.start_time >= ?3 
-                     AND w.start_time < ?4";
+        // The extra row only exists to signal more data; resume after the last one we return.
+        let next_cursor = match query.limit {
+            Some(limit) if page_rows.len() as u32 > limit => page_rows
+                .get(limit as usize - 1)
+                .map(|last| HistoryCursor { start_time: last.start_time.clone(), workout_id: last.workout_id }),
+            _ => None,
+        };
 
-            
-            let mut stmt_sets = self.conn.prepare(query)?;
+        Ok(HistoryPage { workouts: history_vec, next_cursor })
+    }
 
-            println!("Executing SQL query:\n{}", query);
-            println!("With parameters: exercise_id = {}, user_id = {}, start_of_month = {}, end_of_month = {}", 
-                 exercise_id, user_id, start_of_month_str, end_of_month_str);
+    /// Number of workouts started in each bucket of the range.
+    pub fn get_workouts_per_week(&self, user_id: u32, range: &TimeRange) -> Result<WorkoutsPerWeek> {
+        let clock = self.user_clock(user_id)?;
+        let (range_start, range_end) = range.sql_bounds(&clock);
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} AS bucket, COUNT(*)
+             FROM workouts
+             WHERE user_id = ?1 AND start_time >= ?2 AND start_time < ?3
+             GROUP BY bucket",
+            range.bucket.sql_key("local_time(start_time, ?4)"),
+        ))?;
+        let counts = stmt
+            .query_map(params![user_id, range_start, range_end, clock.timezone.name()], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, u32>(1)?))
+            })?
+            .collect::<Result<HashMap<_, _>>>()?;
 
-            
-            let current_month_max_weight: f64 = stmt_sets
-                .query_row(
-                    params![exercise_id, user_id, start_of_month_str, end_of_month_str],
-                    |row| row.get(0),
-                )
-                .unwrap_or(0.0);
-
-            
-            if current_month_max_weight > previous_max_weight {
-                highest_weights[i] = current_month_max_weight;
-                previous_max_weight = current_month_max_weight; 
-            } else {
-                highest_weights[i] = previous_max_weight; 
-            }
-        }
+        let starts = range.bucket_starts();
+        Ok(WorkoutsPerWeek {
+            labels: starts.iter().map(|start| range.bucket.label(*start, clock.locale)).collect(),
+            data: starts
+                .iter()
+                .map(|start| counts.get(&start.to_string()).copied().unwrap_or(0))
+                .collect(),
+            bucket: range.bucket,
+            starts: starts.iter().map(|start| start.to_string()).collect(),
+        })
+    }
 
-        
-        let labels: Vec<String> = month_starts
-            .iter()
-            .map(|date| date.format("%b").to_string()) 
+    /// Best estimated one-rep max in each bucket of the range, along with
+    /// the set each point came from. Sets over `MAX_RELIABLE_REPS` are ignored.
+    pub fn get_1_rep_maxes(
+        &self,
+        user_id: u32,
+        exercise_id: u32,
+        formula: OneRepMaxFormula,
+        range: &TimeRange,
+    ) -> Result<OneRepMaxes> {
+        let clock = self.user_clock(user_id)?;
+        let (range_start, range_end) = range.sql_bounds(&clock);
+
+        // With a single MAX() aggregate SQLite takes the bare columns from
+        // the row holding the maximum, which gives us the source set.
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} AS bucket,
+                    MAX(e1rm(?5, set_load(ue.kind, s.weight, s.bodyweight), s.reps, COALESCE(s.rpe, 10 - s.rir))) AS estimate,
+                    s.id, w.id, w.start_time, s.weight, s.reps, COALESCE(s.rpe, 10 - s.rir)
+             FROM sets s
+             JOIN workout_exercises we ON s.workout_exercise_id = we.id
+             JOIN user_exercises ue ON ue.id = we.exercise_id
+             JOIN workouts w ON we.workout_id = w.id
+             WHERE we.exercise_id = ?1 AND w.user_id = ?2
+               AND w.start_time >= ?3 AND w.start_time < ?4
+               AND s.set_type != 'warmup'
+               AND e1rm(?5, set_load(ue.kind, s.weight, s.bodyweight), s.reps, COALESCE(s.rpe, 10 - s.rir)) IS NOT NULL
+             GROUP BY bucket",
+            range.bucket.sql_key("local_time(w.start_time, ?6)"),
+        ))?;
+        let best_per_bucket = stmt
+            .query_map(
+                params![exercise_id, user_id, range_start, range_end, formula.as_str(), clock.timezone.name()],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        OneRepMaxSet {
+                            estimate: row.get(1)?,
+                            set_id: row.get(2)?,
+                            workout_id: row.get(3)?,
+                            date: row.get(4)?,
+                            weight: row.get(5)?,
+                            reps: row.get(6)?,
+                            rpe: row.get(7)?,
+                        },
+                    ))
+                },
+            )?
+            .collect::<Result<HashMap<_, _>>>()?;
+
+        let unit = self.weight_unit(user_id)?;
+        let best_per_bucket: HashMap<String, OneRepMaxSet> = best_per_bucket
+            .into_iter()
+            .map(|(bucket, mut set)| {
+                set.date = clock.format_rfc3339(&set.date).unwrap_or(set.date);
+                set.weight = unit.convert_kg(set.weight);
+                set.estimate = unit.convert_kg(set.estimate);
+                (bucket, set)
+            })
             .collect();
 
+        // Carried forward so the line never dips.
+        let starts = range.bucket_starts();
+        let mut points: Vec<Option<OneRepMaxSet>> = Vec::with_capacity(starts.len());
+        for start in &starts {
+            let best = best_per_bucket.get(&start.to_string()).cloned();
+            let previous = points.last().cloned().flatten();
+            points.push(match (best, previous) {
+                (Some(best), Some(previous)) if previous.estimate >= best.estimate => Some(previous),
+                (Some(best), _) => Some(best),
+                (None, previous) => previous,
+            });
+        }
+
         Ok(OneRepMaxes {
-            labels,
-            data: highest_weights.into_iter().collect(), 
+            labels: starts.iter().map(|start| range.bucket.label(*start, clock.locale)).collect(),
+            data: points
+                .iter()
+                .map(|point| point.as_ref().map_or(0.0, |p| (p.estimate * 10.0).round() / 10.0))
+                .collect(),
+            formula,
+            unit,
+            bucket: range.bucket,
+            starts: starts.iter().map(|start| start.to_string()).collect(),
+            sets: points,
         })
     }
 
+    pub fn get_preferences(&self, user_id: u32) -> Result<UserPreferences> {
+        self.conn.query_row(
+            "SELECT one_rep_max_formula, timezone, locale, weight_unit FROM users WHERE id = ?1",
+            params![user_id],
+            |row| {
+                let formula: String = row.get(0)?;
+                Ok(UserPreferences {
+                    one_rep_max_formula: formula.parse().unwrap_or_default(),
+                    timezone: row.get(1)?,
+                    locale: row.get(2)?,
+                    weight_unit: row.get::<_, String>(3)?.parse().unwrap_or_default(),
+                })
+            },
+        )
+    }
+
+    /// Callers validate names first; see `user_time::is_valid_timezone`.
+    /// Setting a timezone also converts workouts still held as wall clock
+    /// times (see 0018_legacy_local_times.sql) to UTC.
+    pub fn update_preferences(&self, user_id: u32, update: &PreferencesUpdate) -> Result<UserPreferences> {
+        let tx = Transaction::new_unchecked(&self.conn, TransactionBehavior::Immediate)?;
+        tx.execute(
+            "UPDATE users
+             SET one_rep_max_formula = COALESCE(?1, one_rep_max_formula),
+                 timezone = COALESCE(?2, timezone),
+                 locale = COALESCE(?3, locale),
+                 weight_unit = COALESCE(?4, weight_unit)
+             WHERE id = ?5",
+            params![
+                update.one_rep_max_formula.map(|f| f.as_str()),
+                update.timezone,
+                update.locale,
+                update.weight_unit.map(|unit| unit.as_str()),
+                user_id,
+            ],
+        )?;
+        if update.timezone.is_some() {
+            convert_local_times(&tx, user_id, &self.user_clock(user_id)?)?;
+        }
+        tx.commit()?;
+        self.get_preferences(user_id)
+    }
+
+    pub fn user_clock(&self, user_id: u32) -> Result<UserClock> {
+        let clock = self
+            .conn
+            .query_row(
+                "SELECT timezone, locale FROM users WHERE id = ?1",
+                params![user_id],
+                |row| Ok(UserClock::new(&row.get::<_, String>(0)?, &row.get::<_, String>(1)?)),
+            )
+            .optional()?;
+        Ok(clock.unwrap_or_default())
+    }
+
+    /// Unit the user sees weights in; kg if they have no preference row.
+    pub fn weight_unit(&self, user_id: u32) -> Result<WeightUnit> {
+        let unit: Option<String> = self
+            .conn
+            .query_row("SELECT weight_unit FROM users WHERE id = ?1", params![user_id], |row| row.get(0))
+            .optional()?;
+        Ok(unit.and_then(|unit| unit.parse().ok()).unwrap_or_default())
+    }
+
+    /// Sets from the most recent workout with this exercise, in the user's unit.
     pub fn get_previous_sets(&self, user_id: u32, exercise_id: u32) -> Result<Vec<Set>> {
+        let unit = self.weight_unit(user_id)?;
         let query = "
             WITH subquery AS (
-                SELECT w.start_time, s.reps, s.weight
+                SELECT w.start_time, s.set_number, s.reps, s.weight, s.set_type, s.rpe, s.rir, s.notes,
+                       s.duration_seconds, s.distance_meters, s.bodyweight
                 FROM workouts w
                 JOIN workout_exercises we ON w.id = we.workout_id
                 JOIN sets s ON we.id = s.workout_exercise_id
                 WHERE w.user_id = ? AND we.exercise_id = ?
-                ORDER BY w.start_time DESC
-            ) 
-            SELECT reps, weight
+            )
+            SELECT reps, weight, set_type, rpe, rir, notes, duration_seconds, distance_meters, bodyweight
             FROM subquery
-            WHERE start_time = (SELECT MAX(start_time) FROM subquery);
+            WHERE start_time = (SELECT MAX(start_time) FROM subquery)
+            ORDER BY set_number;
         ";
 
         let mut stmt = self.conn.prepare(query)?;
         let sets = stmt
-            .query_map(params![user_id, exercise_id], |row| {
-                Ok(Set {
-                    reps: row.get(0)?,
-                    weight: row.get(1)?,
-                })
-            })?
+            .query_map(params![user_id, exercise_id], |row| Ok(set_from_row(row, 0)?.in_unit(unit)))?
             .collect::<Result<Vec<_>, _>>()?;
 
         Ok(sets)
     }
 
+    /// Sets from the last workout each of a template's exercises was done in,
+    /// keyed by exercise, as `get_previous_sets` returns them.
+    fn template_previous_sets(&self, user_id: u32, template_id: u32, unit: WeightUnit) -> Result<HashMap<u32, Vec<Set>>> {
+        let mut stmt = self.conn.prepare(
+            "WITH latest AS (
+                 SELECT we.exercise_id, MAX(w.start_time) AS start_time
+                 FROM workouts w
+                 JOIN workout_exercises we ON w.id = we.workout_id
+                 JOIN sets s ON we.id = s.workout_exercise_id
+                 WHERE w.user_id = ?1
+                   AND we.exercise_id IN (SELECT exercise_id FROM template_exercises WHERE template_id = ?2)
+                 GROUP BY we.exercise_id
+             )
+             SELECT we.exercise_id, s.reps, s.weight, s.set_type, s.rpe, s.rir, s.notes, s.duration_seconds,
+                    s.distance_meters, s.bodyweight
+             FROM latest l
+             JOIN workouts w ON w.user_id = ?1 AND w.start_time = l.start_time
+             JOIN workout_exercises we ON we.workout_id = w.id AND we.exercise_id = l.exercise_id
+             JOIN sets s ON we.id = s.workout_exercise_id
+             ORDER BY we.exercise_id, s.set_number",
+        )?;
+        let mut previous: HashMap<u32, Vec<Set>> = HashMap::new();
+        let rows = stmt.query_map(params![user_id, template_id], |row| {
+            Ok((row.get::<_, u32>(0)?, set_from_row(row, 1)?.in_unit(unit)))
+        })?;
+        for row in rows {
+            let (exercise_id, set) = row?;
+            previous.entry(exercise_id).or_default().push(set);
+        }
+        Ok(previous)
+    }
+
     pub fn get_templates(&self, user_id: u32) -> Result<Vec<Template>> {
+        self.templates(user_id, None)
+    }
+
+    /// The user's templates, or just the one with `template_id`.
+    fn templates(&self, user_id: u32, template_id: Option<u32>) -> Result<Vec<Template>> {
+        let unit = self.weight_unit(user_id)?;
+        let one_rep_maxes = self.estimated_one_rep_maxes(user_id, template_id)?;
+        let training_maxes = self.training_maxes(user_id)?;
+        let mut targets = template_targets(&self.conn, user_id, template_id)?;
         let query = "
             SELECT 
                 t.id AS template_id,
@@ -492,16 +989,23 @@ impl DatabaseHandler {
                 te.exercise_id,
                 ue.name AS exercise_name,
                 ue.muscle_group,
-                te.sets
+                te.sets,
+                g.position,
+                g.group_type,
+                ue.kind,
+                te.position,
+                te.id,
+                te.progression_rule
             FROM templates t
             LEFT JOIN template_exercises te ON t.id = te.template_id
             LEFT JOIN user_exercises ue ON te.exercise_id = ue.id
-            WHERE t.user_id = ?1
-            ORDER BY t.id, te.id
+            LEFT JOIN template_exercise_groups g ON g.id = te.group_id
+            WHERE t.user_id = ?1 AND (?2 IS NULL OR t.id = ?2)
+            ORDER BY t.id, te.position
         ";
         
         let mut stmt = self.conn.prepare(query)?;
-        let template_iter = stmt.query_map(params![user_id], |row| {
+        let template_iter = stmt.query_map(params![user_id, template_id], |row| {
             Ok(TemplateRow {
                 template_id: row.get(0)?,
                 template_name: row.get(1)?,
@@ -510,6 +1014,14 @@ impl DatabaseHandler {
                 exercise_name: row.get(4)?,
                 muscle_group: row.get(5)?,
                 sets: row.get(6)?,
+                group: group_from_row(row, 7)?,
+                kind: row.get::<_, Option<String>>(9)?.and_then(|kind| kind.parse().ok()).unwrap_or_default(),
+                position: row.get::<_, Option<u32>>(10)?.unwrap_or_default(),
+                template_exercise_id: row.get(11)?,
+                progression: row
+                    .get::<_, Option<String>>(12)?
+                    .and_then(|rule| serde_json::from_str::<ProgressionRule>(&rule).ok())
+                    .map(|rule| rule.in_unit(unit)),
             })
         })?;
     
@@ -518,6 +1030,15 @@ impl DatabaseHandler {
     
         for row_result in template_iter {
             let row = row_result?;
+            let one_rep_max = row.exercise_id.and_then(|id| one_rep_maxes.get(&id).copied());
+            let training_max = row.exercise_id.and_then(|id| training_maxes.get(&id).copied());
+            let row_targets: Vec<SetTarget> = row
+                .template_exercise_id
+                .and_then(|id| targets.remove(&id))
+                .unwrap_or_default()
+                .into_iter()
+                .map(|target| target_in_unit(target, unit, one_rep_max, training_max))
+                .collect();
     
             match current_template.as_mut() {
                 Some(template) if template.id == row.template_id => {
@@ -532,6 +1053,11 @@ impl DatabaseHandler {
                             name: ex_name,
                             muscle_group: muscle,
                             sets: row.sets,
+                            kind: row.kind,
+                            group: row.group,
+                            position: row.position,
+                            targets: row_targets.clone(),
+                            progression: row.progression,
                         });
                     }
                 }
@@ -559,6 +1085,11 @@ impl DatabaseHandler {
                             name: ex_name,
                             muscle_group: muscle,
                             sets: row.sets,
+                            kind: row.kind,
+                            group: row.group,
+                            position: row.position,
+                            targets: row_targets,
+                            progression: row.progression,
                         });
                     }
     
@@ -575,27 +1106,325 @@ impl DatabaseHandler {
         Ok(templates)
     }
 
+    /// Builds a workout to log from one of the user's templates, each set
+    /// pre-filled from the template's targets and the last time the exercise
+    /// was done. Returns None if the template isn't the user's.
+    pub fn start_template(&self, user_id: u32, template_id: u32) -> Result<Option<WorkoutDraft>> {
+        let Some(template) = self.templates(user_id, Some(template_id))?.pop() else {
+            return Ok(None);
+        };
+        let unit = self.weight_unit(user_id)?;
+        let previous_sets = self.template_previous_sets(user_id, template_id, unit)?;
+
+        let mut exercises = Vec::with_capacity(template.exercises.len());
+        for exercise in template.exercises {
+            let previous = previous_sets.get(&exercise.exercise_id).cloned().unwrap_or_default();
+            let count = if exercise.sets > 0 { exercise.sets as usize } else { previous.len().max(1) };
+            let sets = (0..count)
+                .map(|index| draft_set(exercise.targets.get(index), previous.get(index).or(previous.last()), unit))
+                .collect();
+            exercises.push(DraftExercise {
+                exercise_id: exercise.exercise_id,
+                name: exercise.name,
+                muscle_group: exercise.muscle_group,
+                kind: exercise.kind,
+                group: exercise.group,
+                sets,
+                targets: exercise.targets,
+                previous,
+            });
+        }
+
+        Ok(Some(WorkoutDraft { template_id, name: template.name, unit, exercises }))
+    }
+
+    /// How progression rules judged each session of one of the user's templates.
+    pub fn progression_log(&self, user_id: u32, template_id: u32) -> Result<Vec<ProgressionLogEntry>> {
+        let unit = self.weight_unit(user_id)?;
+        let clock = self.user_clock(user_id)?;
+        progression::log(&self.conn, user_id, template_id, unit, &clock)
+    }
+
+    /// Training max in kg of each exercise the user has set one for.
+    pub fn training_maxes(&self, user_id: u32) -> Result<HashMap<u32, f64>> {
+        let mut stmt = self.conn.prepare("SELECT exercise_id, weight FROM training_maxes WHERE user_id = ?1")?;
+        let rows = stmt.query_map(params![user_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect()
+    }
+
+    /// The user's training maxes with exercise names, in their unit.
+    pub fn get_training_maxes(&self, user_id: u32) -> Result<Vec<TrainingMax>> {
+        let unit = self.weight_unit(user_id)?;
+        let mut stmt = self.conn.prepare(
+            "SELECT tm.exercise_id, ue.name, tm.weight
+             FROM training_maxes tm
+             JOIN user_exercises ue ON ue.id = tm.exercise_id
+             WHERE tm.user_id = ?1
+             ORDER BY ue.name",
+        )?;
+        let rows = stmt.query_map(params![user_id], |row| {
+            Ok(TrainingMax {
+                exercise_id: row.get(0)?,
+                name: row.get(1)?,
+                weight: unit.convert_kg(row.get(2)?),
+                unit,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Sets the training max, in kg, for one of the user's exercises.
+    /// Returns false if the exercise isn't theirs.
+    pub fn set_training_max(&self, user_id: u32, exercise_id: u32, weight: f64) -> Result<bool> {
+        if !self.owns_exercises(user_id, &[exercise_id])? {
+            return Ok(false);
+        }
+        self.conn.execute(
+            "INSERT INTO training_maxes (user_id, exercise_id, weight, updated_at)
+             VALUES (?1, ?2, ?3, datetime('now'))
+             ON CONFLICT (user_id, exercise_id) DO UPDATE SET weight = excluded.weight, updated_at = excluded.updated_at",
+            params![user_id, exercise_id, weight],
+        )?;
+        Ok(true)
+    }
+
+    /// Creates a program from the user's templates. Returns None if any of
+    /// the templates isn't theirs.
+    pub fn create_program(&self, user_id: u32, request: &ProgramRequest) -> Result<Option<u32>> {
+        let tx = Transaction::new_unchecked(&self.conn, TransactionBehavior::Immediate)?;
+        for template_id in request.weeks.iter().flat_map(|week| &week.days) {
+            if !owns_template(&tx, user_id, *template_id)? {
+                return Ok(None);
+            }
+        }
+
+        tx.execute(
+            "INSERT INTO programs (user_id, name, deload_percent, created_at) VALUES (?1, trim(?2), ?3, datetime('now'))",
+            params![user_id, request.name, request.deload_percent],
+        )?;
+        let program_id = tx.last_insert_rowid() as u32;
+        for (week_index, week) in request.weeks.iter().enumerate() {
+            tx.execute(
+                "INSERT INTO program_weeks (program_id, week, deload) VALUES (?1, ?2, ?3)",
+                params![program_id, week_index + 1, week.deload],
+            )?;
+            for (day_index, template_id) in week.days.iter().enumerate() {
+                tx.execute(
+                    "INSERT INTO program_days (program_id, week, day, template_id) VALUES (?1, ?2, ?3, ?4)",
+                    params![program_id, week_index + 1, day_index + 1, template_id],
+                )?;
+            }
+        }
+
+        tx.commit()?;
+        Ok(Some(program_id))
+    }
+
+    pub fn get_programs(&self, user_id: u32) -> Result<Vec<Program>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, name, deload_percent, created_at FROM programs WHERE user_id = ?1 ORDER BY id",
+        )?;
+        let mut programs = stmt
+            .query_map(params![user_id], |row| {
+                Ok(Program {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    deload_percent: row.get(2)?,
+                    created_at: row.get(3)?,
+                    weeks: Vec::new(),
+                })
+            })?
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut weeks = self.conn.prepare("SELECT deload FROM program_weeks WHERE program_id = ?1 ORDER BY week")?;
+        let mut days = self
+            .conn
+            .prepare("SELECT week, template_id FROM program_days WHERE program_id = ?1 ORDER BY week, day")?;
+        for program in &mut programs {
+            program.weeks = weeks
+                .query_map(params![program.id], |row| Ok(ProgramWeek { deload: row.get(0)?, days: Vec::new() }))?
+                .collect::<Result<Vec<_>>>()?;
+            let rows = days.query_map(params![program.id], |row| Ok((row.get::<_, usize>(0)?, row.get::<_, u32>(1)?)))?;
+            for row in rows {
+                let (week, template_id) = row?;
+                if let Some(week) = program.weeks.get_mut(week - 1) {
+                    week.days.push(template_id);
+                }
+            }
+        }
+
+        Ok(programs)
+    }
+
+    /// Deletes a program with its weeks and days, taking anyone following it
+    /// off it. Done by hand as foreign keys are off.
+    pub fn delete_program(&self, user_id: u32, program_id: u32) -> Result<bool> {
+        let tx = Transaction::new_unchecked(&self.conn, TransactionBehavior::Immediate)?;
+        let deleted = tx.execute("DELETE FROM programs WHERE id = ?1 AND user_id = ?2", params![program_id, user_id])?;
+        if deleted == 0 {
+            return Ok(false);
+        }
+
+        tx.execute("DELETE FROM program_days WHERE program_id = ?1", params![program_id])?;
+        tx.execute("DELETE FROM program_weeks WHERE program_id = ?1", params![program_id])?;
+        tx.execute("DELETE FROM user_programs WHERE program_id = ?1", params![program_id])?;
+
+        tx.commit()?;
+        Ok(true)
+    }
+
+    /// Puts the user on the first day of a program, replacing any program
+    /// they were following. Returns false if the program isn't theirs.
+    pub fn start_program(&self, user_id: u32, program_id: u32) -> Result<bool> {
+        let started = self.conn.execute(
+            "INSERT OR REPLACE INTO user_programs (user_id, program_id, cycle, week, day, started_at)
+             SELECT ?1, id, 1, 1, 1, datetime('now') FROM programs WHERE id = ?2 AND user_id = ?1",
+            params![user_id, program_id],
+        )?;
+        Ok(started > 0)
+    }
+
+    /// The next workout in the user's program, built like `start_template`
+    /// with deload weeks scaled down. None if they aren't following one.
+    pub fn todays_workout(&self, user_id: u32) -> Result<Option<TodaysWorkout>> {
+        let Some(position) = program_position(&self.conn, user_id)? else {
+            return Ok(None);
+        };
+        let Some(mut workout) = self.start_template(user_id, position.template_id)? else {
+            return Ok(None);
+        };
+
+        if position.deload {
+            let deload_percent: f64 = self.conn.query_row(
+                "SELECT deload_percent FROM programs WHERE id = ?1",
+                params![position.program_id],
+                |row| row.get(0),
+            )?;
+            let unit = workout.unit;
+            let deload = |weight: f64| unit.round_to_plates(weight * deload_percent / 100.0);
+            for exercise in &mut workout.exercises {
+                for set in &mut exercise.sets {
+                    set.weight = deload(set.weight);
+                }
+                for target in &mut exercise.targets {
+                    target.suggested_weight = target.suggested_weight.map(deload);
+                }
+            }
+        }
+
+        Ok(Some(TodaysWorkout { position, workout }))
+    }
+
     pub fn save_template(&self, request: TemplateRequest) -> Result<u32> {
 
         let user_id = self.get_user_id_from_token(&request.user_id)?;
+        let unit = self.weight_unit(user_id)?;
+        let tx = Transaction::new_unchecked(&self.conn, TransactionBehavior::Immediate)?;
 
-        
-        self.conn.execute(
+        tx.execute(
             "INSERT INTO templates (user_id, name, created_at) VALUES (?1, ?2, datetime('now'))",
             params![user_id, request.name],
         )?;
-        let template_id = self.conn.last_insert_rowid() as u32;
+        let template_id = tx.last_insert_rowid() as u32;
+        insert_template_exercises(&tx, template_id, &request.exercises, unit)?;
 
-        
-        for exercise in request.exercises {
-            self.conn.execute(
-                "INSERT INTO template_exercises (template_id, exercise_id, sets) VALUES (?1, ?2, ?3)",
-                params![template_id, exercise.exercise_id, exercise.sets],
-            )?;
+        tx.commit()?;
+        Ok(template_id)
+    }
+
+    /// Renames a template and/or replaces its whole exercise list in one go.
+    /// Returns false if the template isn't the user's.
+    pub fn update_template(&self, user_id: u32, template_id: u32, update: &TemplateUpdate) -> Result<bool> {
+        let unit = self.weight_unit(user_id)?;
+        let tx = Transaction::new_unchecked(&self.conn, TransactionBehavior::Immediate)?;
+        if !owns_template(&tx, user_id, template_id)? {
+            return Ok(false);
         }
 
-        
-        Ok(template_id)
+        if let Some(name) = &update.name {
+            tx.execute("UPDATE templates SET name = trim(?1) WHERE id = ?2", params![name, template_id])?;
+        }
+        if let Some(exercises) = &update.exercises {
+            let failed_sessions = template_exercise_rows(&tx, template_id, "failed_sessions")?;
+            delete_template_children(&tx, template_id)?;
+            insert_template_exercises(&tx, template_id, exercises, unit)?;
+            restore_failed_sessions(&tx, template_id, failed_sessions)?;
+        }
+
+        tx.commit()?;
+        Ok(true)
+    }
+
+    pub fn delete_template(&self, user_id: u32, template_id: u32) -> Result<bool> {
+        let tx = Transaction::new_unchecked(&self.conn, TransactionBehavior::Immediate)?;
+        if !owns_template(&tx, user_id, template_id)? {
+            return Ok(false);
+        }
+
+        delete_template_children(&tx, template_id)?;
+        // Workouts keep their history; programs and progression lose the template.
+        tx.execute(
+            "DELETE FROM progression_changes WHERE workout_id IN (SELECT id FROM workouts WHERE template_id = ?1)",
+            params![template_id],
+        )?;
+        tx.execute("UPDATE workouts SET template_id = NULL WHERE template_id = ?1", params![template_id])?;
+        tx.execute("DELETE FROM program_days WHERE template_id = ?1", params![template_id])?;
+        tx.execute("DELETE FROM progression_log WHERE template_id = ?1", params![template_id])?;
+        tx.execute("DELETE FROM templates WHERE id = ?1", params![template_id])?;
+
+        tx.commit()?;
+        Ok(true)
+    }
+
+    /// Copies a template with its exercises and groups, named `name` or
+    /// "<original> (copy)". Returns the new template's id, or None if the
+    /// template isn't the user's.
+    pub fn duplicate_template(&self, user_id: u32, template_id: u32, name: Option<&str>) -> Result<Option<u32>> {
+        let tx = Transaction::new_unchecked(&self.conn, TransactionBehavior::Immediate)?;
+        let original: Option<String> = tx
+            .query_row(
+                "SELECT name FROM templates WHERE id = ?1 AND user_id = ?2",
+                params![template_id, user_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let Some(original) = original else {
+            return Ok(None);
+        };
+
+        let mut targets = template_targets(&tx, user_id, Some(template_id))?;
+        let mut stmt = tx.prepare(
+            "SELECT te.exercise_id, te.sets, g.position, g.group_type, te.id, te.progression_rule
+             FROM template_exercises te
+             LEFT JOIN template_exercise_groups g ON g.id = te.group_id
+             WHERE te.template_id = ?1
+             ORDER BY te.position",
+        )?;
+        let exercises = stmt
+            .query_map(params![template_id], |row| {
+                Ok(TemplateExerciseRequest {
+                    exercise_id: row.get(0)?,
+                    sets: row.get(1)?,
+                    group: group_from_row(row, 2)?,
+                    targets: targets.remove(&row.get(4)?).unwrap_or_default(),
+                    progression: row
+                        .get::<_, Option<String>>(5)?
+                        .and_then(|rule| serde_json::from_str(&rule).ok()),
+                })
+            })?
+            .collect::<Result<Vec<_>>>()?;
+        drop(stmt);
+
+        let name = name.map(str::trim).map_or_else(|| format!("{} (copy)", original), str::to_string);
+        tx.execute(
+            "INSERT INTO templates (user_id, name, created_at) VALUES (?1, ?2, datetime('now'))",
+            params![user_id, name],
+        )?;
+        let copy_id = tx.last_insert_rowid() as u32;
+        insert_template_exercises(&tx, copy_id, &exercises, WeightUnit::Kg)?;
+
+        tx.commit()?;
+        Ok(Some(copy_id))
     }
 
     pub fn get_user_id_from_token(&self, token: &str) -> Result<u32> {
@@ -671,7 +1500,7 @@ impl DatabaseHandler {
             .collect();
 
         
-        let expires_at = Utc::now() + Duration::hours(24); 
+        let expires_at = Utc::now() + self.session_lifetime;
         let expires_at_formatted = expires_at.format("%Y-%m-%d %H:%M:%S").to_string(); 
 
         
@@ -685,6 +1514,54 @@ impl DatabaseHandler {
     }
 }
 
+/// Filters and position for a page of `/history`. `from` and `to` are
+/// inclusive dates in the user's timezone.
+pub struct HistoryQuery {
+    /// Workouts per page; None returns every matching workout.
+    pub limit: Option<u32>,
+    pub cursor: Option<HistoryCursor>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub exercise_id: Option<u32>,
+    pub muscle_group: Option<String>,
+}
+
+/// Position after the last workout of a page. Sent to clients as an opaque
+/// `<yyyymmddhhmmss>_<workout id>` string.
+#[derive(Debug, PartialEq)]
+pub struct HistoryCursor {
+    pub start_time: String,
+    pub workout_id: u32,
+}
+
+impl HistoryCursor {
+    pub fn encode(&self) -> String {
+        let digits: String = self.start_time.chars().filter(char::is_ascii_digit).collect();
+        format!("{}_{}", digits, self.workout_id)
+    }
+
+    pub fn decode(cursor: &str) -> Option<HistoryCursor> {
+        let (time, id) = cursor.split_once('_')?;
+        let start_time = NaiveDateTime::parse_from_str(time, "%Y%m%d%H%M%S").ok()?;
+        Some(HistoryCursor {
+            start_time: start_time.format("%Y-%m-%d %H:%M:%S").to_string(),
+            workout_id: id.parse().ok()?,
+        })
+    }
+}
+
+pub struct HistoryPage {
+    pub workouts: Vec<HistoryData>,
+    pub next_cursor: Option<HistoryCursor>,
+}
+
+pub struct SavedWorkout {
+    pub workout_id: u32,
+    pub sets_saved: u32,
+    /// True when this was a retry of a workout that had already been saved.
+    pub replayed: bool,
+}
+
 struct WorkoutRow {
     workout_id: u32,
     start_time: String,
@@ -694,12 +1571,17 @@ struct WorkoutRow {
 
 struct TemplateRow {
     template_id: u32,
+    progression: Option<ProgressionRule>,
+    template_exercise_id: Option<u32>,
+    position: u32,
     template_name: String,
     created_at: String,
     exercise_id: Option<u32>,
     exercise_name: Option<String>,
     muscle_group: Option<String>,
     sets: u32,
+    group: Option<ExerciseGroup>,
+    kind: ExerciseKind,
 }
 
 #[derive(Debug, Serialize)]
@@ -716,24 +1598,46 @@ pub struct TemplateExercise {
     pub name: String,
     pub muscle_group: String,
     pub sets: u32,
-}
-
-
-struct ExerciseRow {
-    workout_exercise_id: u32,
-    name: String,
+    pub kind: ExerciseKind,
+    pub group: Option<ExerciseGroup>,
+    /// Place in the template, starting at 1.
+    pub position: u32,
+    /// One per set when the template prescribes them, otherwise empty.
+    pub targets: Vec<SetTarget>,
+    pub progression: Option<ProgressionRule>,
 }
 
 #[derive(Serialize)]
 pub struct WorkoutsPerWeek {
     labels: Vec<String>,
     data: Vec<u32>,
+    bucket: Bucket,
+    /// First day of each bucket, `YYYY-MM-DD`.
+    starts: Vec<String>,
 }
 
 #[derive(Serialize)]
 pub struct OneRepMaxes {
     labels: Vec<String>,
     data: Vec<f64>,
+    formula: OneRepMaxFormula,
+    unit: WeightUnit,
+    bucket: Bucket,
+    /// First day of each bucket, `YYYY-MM-DD`.
+    starts: Vec<String>,
+    /// The set behind each point in `data`, `None` before the first usable set.
+    sets: Vec<Option<OneRepMaxSet>>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct OneRepMaxSet {
+    pub set_id: u32,
+    pub workout_id: u32,
+    pub date: String,
+    pub weight: f64,
+    pub reps: u32,
+    pub rpe: Option<f64>,
+    pub estimate: f64,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -741,21 +1645,518 @@ pub struct ExerciseRequest {
     pub user_id: String,
     pub name: String,
     pub body_part: String,
+    #[serde(default)]
+    pub kind: ExerciseKind,
+}
+
+/// Inserts the exercises and their sets for a workout, keeping the given
+/// order, and returns how many sets were written. Weights are converted to kg
+/// from the set's unit, or `default_unit` if it has none.
+fn insert_workout_exercises(
+    conn: &Connection,
+    workout_id: u32,
+    exercises: &[ExerciseRecord],
+    default_unit: WeightUnit,
+) -> Result<u32> {
+    let mut total_sets_saved = 0;
+
+    let groups: Vec<Option<ExerciseGroup>> = exercises.iter().map(|e| e.group).collect();
+    let group_ids = insert_groups(conn, "workout_exercise_groups", "workout_id", workout_id, &groups)?;
+    for (exercise, group_id) in exercises.iter().zip(group_ids) {
+        conn.execute(
+            "INSERT INTO workout_exercises (workout_id, exercise_id, group_id) VALUES (?1, ?2, ?3)",
+            params![workout_id, exercise.exercise_id, group_id],
+        )?;
+        let workout_exercise_id = conn.last_insert_rowid() as u32;
+
+        for (set_index, set) in exercise.sets.iter().enumerate() {
+            let unit = set.unit.unwrap_or(default_unit);
+            conn.execute(
+                "INSERT INTO sets (workout_exercise_id, set_number, weight, reps, set_type, rpe, rir, notes,
+                                   duration_seconds, distance_meters, bodyweight, weight_unit)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+                params![
+                    workout_exercise_id,
+                    set_index + 1,
+                    unit.to_kg(set.weight),
+                    set.reps,
+                    set.set_type.as_str(),
+                    set.rpe,
+                    set.rir,
+                    set.notes,
+                    set.duration_seconds,
+                    set.distance_meters,
+                    set.bodyweight.map(|bodyweight| unit.to_kg(bodyweight)),
+                    unit.as_str(),
+                ],
+            )?;
+            total_sets_saved += 1;
+        }
+    }
+
+    Ok(total_sets_saved)
+}
+
+/// Stored start time of one of the user's workouts; None if it isn't theirs.
+fn workout_start_time(conn: &Connection, user_id: u32, workout_id: u32) -> Result<Option<String>> {
+    conn.query_row(
+        "SELECT start_time FROM workouts WHERE id = ?1 AND user_id = ?2",
+        params![workout_id, user_id],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+fn workout_exercise_ids(conn: &Connection, workout_id: u32) -> Result<Vec<u32>> {
+    let mut stmt = conn.prepare("SELECT exercise_id FROM workout_exercises WHERE workout_id = ?1")?;
+    let ids = stmt.query_map(params![workout_id], |row| row.get(0))?;
+    ids.collect()
+}
+
+/// Rewrites the user's workouts flagged as wall clock times to UTC, reading
+/// them in the timezone of `clock`.
+fn convert_local_times(conn: &Connection, user_id: u32, clock: &UserClock) -> Result<()> {
+    let mut stmt =
+        conn.prepare("SELECT id, start_time, end_time FROM workouts WHERE user_id = ?1 AND local_time = 1")?;
+    let workouts = stmt
+        .query_map(params![user_id], |row| {
+            Ok((row.get::<_, u32>(0)?, row.get::<_, String>(1)?, row.get::<_, Option<String>>(2)?))
+        })?
+        .collect::<Result<Vec<_>>>()?;
+
+    let to_utc = |time: String| clock.parse_client_time(&time).map(user_time::to_storage).unwrap_or(time);
+    for (workout_id, start_time, end_time) in workouts {
+        conn.execute(
+            "UPDATE workouts SET start_time = ?1, end_time = ?2, local_time = 0 WHERE id = ?3",
+            params![to_utc(start_time), end_time.map(to_utc), workout_id],
+        )?;
+    }
+    Ok(())
+}
+
+/// Removes a workout's sets and exercises. Done by hand rather than relying on
+/// `ON DELETE CASCADE`, which only fires when foreign keys are switched on.
+fn delete_workout_children(conn: &Connection, workout_id: u32) -> Result<()> {
+    conn.execute(
+        "DELETE FROM personal_records WHERE set_id IN
+             (SELECT s.id FROM sets s JOIN workout_exercises we ON s.workout_exercise_id = we.id
+              WHERE we.workout_id = ?1)",
+        params![workout_id],
+    )?;
+    conn.execute(
+        "DELETE FROM sets WHERE workout_exercise_id IN
+             (SELECT id FROM workout_exercises WHERE workout_id = ?1)",
+        params![workout_id],
+    )?;
+    conn.execute("DELETE FROM workout_exercises WHERE workout_id = ?1", params![workout_id])?;
+    conn.execute("DELETE FROM workout_exercise_groups WHERE workout_id = ?1", params![workout_id])?;
+    Ok(())
+}
+
+/// Where the user is in the program they follow. Days whose template has
+/// since been deleted are skipped over.
+fn program_position(conn: &Connection, user_id: u32) -> Result<Option<ProgramPosition>> {
+    conn.query_row(
+        "SELECT p.id, p.name, up.cycle, pd.week, pd.day, COALESCE(pw.deload, 0), pd.template_id
+         FROM user_programs up
+         JOIN programs p ON p.id = up.program_id
+         JOIN program_days pd ON pd.program_id = p.id
+         JOIN templates t ON t.id = pd.template_id
+         LEFT JOIN program_weeks pw ON pw.program_id = p.id AND pw.week = pd.week
+         WHERE up.user_id = ?1
+         ORDER BY (pd.week, pd.day) < (up.week, up.day), pd.week, pd.day
+         LIMIT 1",
+        params![user_id],
+        |row| {
+            Ok(ProgramPosition {
+                program_id: row.get(0)?,
+                program_name: row.get(1)?,
+                cycle: row.get(2)?,
+                week: row.get(3)?,
+                day: row.get(4)?,
+                deload: row.get(5)?,
+                template_id: row.get(6)?,
+            })
+        },
+    )
+    .optional()
+}
+
+/// Moves the user on to the next day of their program if `template_id` is
+/// the one that was due, starting a new cycle after the last day. The move is
+/// recorded against `workout_id` so deleting the workout can take it back.
+fn advance_program(conn: &Connection, user_id: u32, template_id: u32, workout_id: u32) -> Result<()> {
+    let Some(position) = program_position(conn, user_id)? else {
+        return Ok(());
+    };
+    if position.template_id != template_id {
+        return Ok(());
+    }
+
+    let next: Option<(u32, u32)> = conn
+        .query_row(
+            "SELECT pd.week, pd.day FROM program_days pd
+             JOIN templates t ON t.id = pd.template_id
+             WHERE pd.program_id = ?1 AND (pd.week, pd.day) > (?2, ?3)
+             ORDER BY pd.week, pd.day
+             LIMIT 1",
+            params![position.program_id, position.week, position.day],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+    let (cycle, week, day) = match next {
+        Some((week, day)) => (position.cycle, week, day),
+        None => (position.cycle + 1, 1, 1),
+    };
+    conn.execute(
+        "UPDATE user_programs SET cycle = ?1, week = ?2, day = ?3 WHERE user_id = ?4",
+        params![cycle, week, day, user_id],
+    )?;
+    conn.execute(
+        "INSERT INTO program_advances
+             (workout_id, user_id, program_id, cycle_before, week_before, day_before, cycle_after, week_after, day_after)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            workout_id,
+            user_id,
+            position.program_id,
+            position.cycle,
+            position.week,
+            position.day,
+            cycle,
+            week,
+            day,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Takes back the program day a deleted workout moved the user on from,
+/// unless they have moved on again or changed program since.
+fn revert_program_advance(conn: &Connection, workout_id: u32) -> Result<()> {
+    conn.execute(
+        "UPDATE user_programs
+         SET (cycle, week, day) =
+             (SELECT cycle_before, week_before, day_before FROM program_advances WHERE workout_id = ?1)
+         WHERE (user_id, program_id, cycle, week, day) =
+             (SELECT user_id, program_id, cycle_after, week_after, day_after FROM program_advances WHERE workout_id = ?1)",
+        params![workout_id],
+    )?;
+    conn.execute("DELETE FROM program_advances WHERE workout_id = ?1", params![workout_id])?;
+    Ok(())
+}
+
+fn owns_template(conn: &Connection, user_id: u32, template_id: u32) -> Result<bool> {
+    let count: u32 = conn.query_row(
+        "SELECT COUNT(*) FROM templates WHERE id = ?1 AND user_id = ?2",
+        params![template_id, user_id],
+        |row| row.get(0),
+    )?;
+    Ok(count > 0)
+}
+
+/// Inserts a template's exercises, their groups and set targets, positioned
+/// in list order. Target weights are in `unit`.
+fn insert_template_exercises(
+    conn: &Connection,
+    template_id: u32,
+    exercises: &[TemplateExerciseRequest],
+    unit: WeightUnit,
+) -> Result<()> {
+    let groups: Vec<Option<ExerciseGroup>> = exercises.iter().map(|e| e.group).collect();
+    let group_ids = insert_groups(conn, "template_exercise_groups", "template_id", template_id, &groups)?;
+    let mut exercise_stmt = conn.prepare(
+        "INSERT INTO template_exercises (template_id, exercise_id, sets, group_id, position, progression_rule)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+    )?;
+    let mut target_stmt = conn.prepare(
+        "INSERT INTO template_sets
+             (template_exercise_id, position, reps_min, reps_max, weight, percent_e1rm, percent_training_max,
+              rpe, rest_seconds)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+    )?;
+    for (position, (exercise, group_id)) in exercises.iter().zip(group_ids).enumerate() {
+        let template_exercise_id = exercise_stmt.insert(params![
+            template_id,
+            exercise.exercise_id,
+            exercise.set_count(),
+            group_id,
+            position + 1,
+            exercise
+                .progression
+                .map(|rule| serde_json::to_string(&rule.to_kg(unit)).unwrap_or_default()),
+        ])?;
+        for (set_position, target) in exercise.targets.iter().enumerate() {
+            target_stmt.execute(params![
+                template_exercise_id,
+                set_position + 1,
+                target.reps_min,
+                target.reps_max,
+                target.weight.map(|weight| unit.to_kg(weight)),
+                target.percent_e1rm,
+                target.percent_training_max,
+                target.rpe,
+                target.rest_seconds,
+            ])?;
+        }
+    }
+    Ok(())
+}
+
+/// A set to log, taking reps, weight and RPE from the template's target
+/// where it gives them and the rest from the matching set last time.
+fn draft_set(target: Option<&SetTarget>, previous: Option<&Set>, unit: WeightUnit) -> Set {
+    let target_reps = target.and_then(|target| target.reps_max.or(target.reps_min));
+    Set {
+        reps: target_reps.or(previous.map(|set| set.reps)).unwrap_or_default(),
+        weight: target
+            .and_then(|target| target.suggested_weight)
+            .or(previous.map(|set| set.weight))
+            .unwrap_or_default(),
+        rpe: target.and_then(|target| target.rpe),
+        duration_seconds: previous.and_then(|set| set.duration_seconds),
+        distance_meters: previous.and_then(|set| set.distance_meters),
+        bodyweight: previous.and_then(|set| set.bodyweight),
+        unit: Some(unit),
+        ..Default::default()
+    }
+}
+
+/// Set targets of the user's templates, or just one of them, keyed by
+/// template exercise and with weights in kg.
+fn template_targets(conn: &Connection, user_id: u32, template_id: Option<u32>) -> Result<HashMap<u32, Vec<SetTarget>>> {
+    let mut stmt = conn.prepare(
+        "SELECT ts.template_exercise_id, ts.reps_min, ts.reps_max, ts.weight, ts.percent_e1rm, ts.rpe, ts.rest_seconds,
+                ts.percent_training_max
+         FROM template_sets ts
+         JOIN template_exercises te ON te.id = ts.template_exercise_id
+         JOIN templates t ON t.id = te.template_id
+         WHERE t.user_id = ?1 AND (?2 IS NULL OR t.id = ?2)
+         ORDER BY ts.template_exercise_id, ts.position",
+    )?;
+    let mut targets: HashMap<u32, Vec<SetTarget>> = HashMap::new();
+    let rows = stmt.query_map(params![user_id, template_id], |row| {
+        Ok((
+            row.get::<_, u32>(0)?,
+            SetTarget {
+                reps_min: row.get(1)?,
+                reps_max: row.get(2)?,
+                weight: row.get(3)?,
+                percent_e1rm: row.get(4)?,
+                rpe: row.get(5)?,
+                rest_seconds: row.get(6)?,
+                percent_training_max: row.get(7)?,
+                suggested_weight: None,
+            },
+        ))
+    })?;
+    for row in rows {
+        let (template_exercise_id, target) = row?;
+        targets.entry(template_exercise_id).or_default().push(target);
+    }
+    Ok(targets)
+}
+
+/// A stored target with its weight shown in `unit` and the weight to load
+/// worked out from it, `one_rep_max` or `training_max` (both in kg).
+fn target_in_unit(
+    target: SetTarget,
+    unit: WeightUnit,
+    one_rep_max: Option<f64>,
+    training_max: Option<f64>,
+) -> SetTarget {
+    let percent_of = |percent: Option<f64>, max: Option<f64>| {
+        Some(unit.round_to_plates(unit.convert_kg(max? * percent? / 100.0)))
+    };
+    let suggested_weight = match target.weight {
+        Some(weight) => Some(unit.convert_kg(weight)),
+        None => percent_of(target.percent_training_max, training_max)
+            .or_else(|| percent_of(target.percent_e1rm, one_rep_max)),
+    };
+    SetTarget {
+        weight: target.weight.map(|weight| unit.convert_kg(weight)),
+        suggested_weight,
+        ..target
+    }
 }
 
-pub fn parse_as_date_time_utc(datetime_str: &str) -> DateTime<Utc> {
-    let naive_dt = NaiveDateTime::parse_from_str(datetime_str, "%Y-%m-%d %H:%M:%S").unwrap();
+/// `(exercise_id, column)` for each of a template's exercises, in order.
+fn template_exercise_rows(conn: &Connection, template_id: u32, column: &str) -> Result<Vec<(u32, u32)>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT exercise_id, {} FROM template_exercises WHERE template_id = ?1 ORDER BY position",
+        column
+    ))?;
+    let rows = stmt.query_map(params![template_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    rows.collect()
+}
 
-    let datetime_utc: DateTime<Utc> = Utc.from_utc_datetime(&naive_dt);
+/// Carries failed session counts over to a template's re-inserted exercises,
+/// so editing a template doesn't restart the count towards a deload. An
+/// exercise listed twice keeps its counts in order.
+fn restore_failed_sessions(conn: &Connection, template_id: u32, mut previous: Vec<(u32, u32)>) -> Result<()> {
+    for (exercise_id, template_exercise_id) in template_exercise_rows(conn, template_id, "id")? {
+        let Some(index) = previous.iter().position(|&(previous_id, _)| previous_id == exercise_id) else {
+            continue;
+        };
+        let (_, failed_sessions) = previous.remove(index);
+        conn.execute(
+            "UPDATE template_exercises SET failed_sessions = ?1 WHERE id = ?2",
+            params![failed_sessions, template_exercise_id],
+        )?;
+    }
+    Ok(())
+}
 
-    return datetime_utc;
+fn delete_template_children(conn: &Connection, template_id: u32) -> Result<()> {
+    conn.execute(
+        "DELETE FROM template_sets WHERE template_exercise_id IN
+             (SELECT id FROM template_exercises WHERE template_id = ?1)",
+        params![template_id],
+    )?;
+    conn.execute("DELETE FROM template_exercises WHERE template_id = ?1", params![template_id])?;
+    conn.execute("DELETE FROM template_exercise_groups WHERE template_id = ?1", params![template_id])?;
+    Ok(())
 }
 
+/// Creates a row in `table` for each distinct group, numbered in the order
+/// they first appear, and returns the row id for each exercise in turn.
+fn insert_groups(
+    conn: &Connection,
+    table: &str,
+    parent_column: &str,
+    parent_id: u32,
+    groups: &[Option<ExerciseGroup>],
+) -> Result<Vec<Option<i64>>> {
+    let mut stmt = conn.prepare(&format!(
+        "INSERT INTO {} ({}, position, group_type) VALUES (?1, ?2, ?3)",
+        table, parent_column
+    ))?;
+    let mut row_ids: HashMap<u32, i64> = HashMap::new();
+    groups
+        .iter()
+        .map(|group| {
+            let Some(group) = group else {
+                return Ok(None);
+            };
+            if let Some(row_id) = row_ids.get(&group.id) {
+                return Ok(Some(*row_id));
+            }
+            let row_id = stmt.insert(params![parent_id, row_ids.len() + 1, group.group_type.as_str()])?;
+            row_ids.insert(group.id, row_id);
+            Ok(Some(row_id))
+        })
+        .collect()
+}
+
+/// Reads a group's `position, group_type` starting at column `first`.
+fn group_from_row(row: &Row, first: usize) -> Result<Option<ExerciseGroup>> {
+    let Some(position) = row.get::<_, Option<u32>>(first)? else {
+        return Ok(None);
+    };
+    let group_type = row.get::<_, String>(first + 1)?;
+    Ok(group_type.parse().ok().map(|group_type| ExerciseGroup { id: position, group_type }))
+}
+
+/// Adds the SQL functions queries rely on to a connection:
+/// `e1rm(formula, weight, reps, rpe)` estimates a one-rep max, or NULL when
+/// the set can't be estimated from, and `local_time(stored, timezone)` turns
+/// a stored UTC timestamp into wall clock time in that zone.
+/// `set_load(kind, weight, bodyweight)` and `set_score(kind, weight, reps,
+/// duration, distance, bodyweight)` are [`ExerciseKind::load`] and
+/// [`ExerciseKind::score`].
+fn register_functions(conn: &Connection) -> Result<()> {
+    let kind_of = |ctx: &rusqlite::functions::Context, index| -> Result<ExerciseKind> {
+        ctx.get::<String>(index)?
+            .parse()
+            .map_err(|e: String| Error::UserFunctionError(e.into()))
+    };
+
+    conn.create_scalar_function(
+        "set_load",
+        3,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        move |ctx| Ok(kind_of(ctx, 0)?.load(ctx.get(1)?, ctx.get(2)?)),
+    )?;
+
+    conn.create_scalar_function(
+        "set_score",
+        6,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        move |ctx| {
+            // Exercises with no sets are LEFT JOINed in with NULLs.
+            let (Some(weight), Some(reps)) = (ctx.get::<Option<f64>>(1)?, ctx.get::<Option<i64>>(2)?) else {
+                return Ok(None);
+            };
+            let set = Set {
+                weight,
+                reps: reps.max(0) as u32,
+                duration_seconds: ctx.get(3)?,
+                distance_meters: ctx.get(4)?,
+                bodyweight: ctx.get(5)?,
+                ..Default::default()
+            };
+            Ok(kind_of(ctx, 0)?.score(&set))
+        },
+    )?;
+
+    conn.create_scalar_function(
+        "local_time",
+        2,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| {
+            let stored: Option<String> = ctx.get(0)?;
+            let clock = UserClock::new(&ctx.get::<String>(1)?, user_time::DEFAULT_LOCALE);
+            Ok(stored
+                .and_then(|stored| clock.local_time(&stored))
+                .map(|time| time.format(user_time::STORAGE_FORMAT).to_string()))
+        },
+    )?;
+
+    conn.create_scalar_function(
+        "e1rm",
+        4,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| {
+            let formula: OneRepMaxFormula = ctx
+                .get::<String>(0)?
+                .parse()
+                .map_err(|e: String| Error::UserFunctionError(e.into()))?;
+            let reps: i64 = ctx.get(2)?;
+            let Some(weight) = ctx.get::<Option<f64>>(1)? else {
+                return Ok(None);
+            };
+            Ok(formula.estimate(weight, reps.max(0) as u32, ctx.get(3)?))
+        },
+    )
+}
+
+/// Reads `reps, weight, set_type, rpe, rir, notes, duration_seconds,
+/// distance_meters, bodyweight` starting at column `first`. Weights are as
+/// stored, in kg; see [`Set::in_unit`].
+fn set_from_row(row: &Row, first: usize) -> Result<Set> {
+    Ok(Set {
+        reps: row.get(first)?,
+        weight: row.get(first + 1)?,
+        set_type: row.get::<_, String>(first + 2)?.parse().unwrap_or_default(),
+        rpe: row.get(first + 3)?,
+        rir: row.get(first + 4)?,
+        notes: row.get(first + 5)?,
+        duration_seconds: row.get(first + 6)?,
+        distance_meters: row.get(first + 7)?,
+        bodyweight: row.get(first + 8)?,
+        unit: Some(WeightUnit::Kg),
+    })
+}
+
+fn split_records(records: Option<String>) -> Vec<String> {
+    records
+        .map(|records| records.split(',').map(str::to_string).collect())
+        .unwrap_or_default()
+}
 
 fn compute_duration(start_time: &str, end_time: &str) -> Option<String> {
-    
-    let start: DateTime<Utc> = parse_as_date_time_utc(start_time);
-    let end: DateTime<Utc> = parse_as_date_time_utc(end_time);
+    let start = user_time::from_storage(start_time)?;
+    let end = user_time::from_storage(end_time)?;
     let duration = end.signed_duration_since(start);
     let hours = duration.num_hours();
     let minutes = (duration.num_minutes() % 60).abs();
@@ -770,8 +2171,60 @@ pub struct TemplateRequest {
     pub exercises: Vec<TemplateExerciseRequest>,
 }
 
+/// Changes to a template; fields left out stay as they are. `exercises`
+/// replaces the whole list.
+#[derive(Debug, Default, Deserialize)]
+pub struct TemplateUpdate {
+    pub name: Option<String>,
+    pub exercises: Option<Vec<TemplateExerciseRequest>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct TemplateCopy {
+    pub name: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TemplateExerciseRequest {
     pub exercise_id: u32,
+    /// May be left out when `targets` are given, one per set.
+    #[serde(default)]
     pub sets: u32,
+    #[serde(default)]
+    pub group: Option<ExerciseGroup>,
+    #[serde(default)]
+    pub targets: Vec<SetTarget>,
+    /// Applied to `targets` after each workout logged from the template.
+    #[serde(default)]
+    pub progression: Option<ProgressionRule>,
+}
+
+impl TemplateExerciseRequest {
+    pub fn set_count(&self) -> u32 {
+        if self.targets.is_empty() {
+            self.sets
+        } else {
+            self.targets.len() as u32
+        }
+    }
+
+    /// Checks the targets, that they agree with `sets` if both are given, and
+    /// that a progression rule has a weight or training max to move.
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.targets.is_empty() && self.sets != 0 && self.sets as usize != self.targets.len() {
+            return Err(format!("{} sets given with {} targets", self.sets, self.targets.len()));
+        }
+        self.targets.iter().try_for_each(SetTarget::validate)?;
+        if let Some(rule) = &self.progression {
+            rule.validate()?;
+            let movable = self
+                .targets
+                .iter()
+                .any(|target| target.weight.is_some() || target.percent_training_max.is_some());
+            if !movable {
+                return Err("A progression rule needs targets with a weight or percent_training_max".to_string());
+            }
+        }
+        Ok(())
+    }
 }
\ No newline at end of file
@@ -0,0 +1,83 @@
+use std::{
+    ops::Deref,
+    sync::{Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+use rusqlite::Result;
+
+use crate::database_handler::DatabaseHandler;
+
+/// Fixed-size pool of SQLite connections.
+///
+/// Callers check out a `DatabaseHandler` for the duration of a single piece
+/// of work; it goes back to the pool when the guard is dropped.
+pub struct DbPool {
+    idle: Mutex<Vec<DatabaseHandler>>,
+    returned: Condvar,
+    checkout_timeout: Duration,
+}
+
+pub struct PooledHandler<'a> {
+    pool: &'a DbPool,
+    handler: Option<DatabaseHandler>,
+}
+
+impl DbPool {
+    pub fn open<F>(size: usize, checkout_timeout: Duration, connect: F) -> Result<Self>
+    where
+        F: Fn() -> Result<DatabaseHandler>,
+    {
+        let handlers = (0..size.max(1))
+            .map(|_| connect())
+            .collect::<Result<Vec<_>>>()?;
+        Ok(DbPool::from_handlers(handlers, checkout_timeout))
+    }
+
+    pub fn from_handlers(handlers: Vec<DatabaseHandler>, checkout_timeout: Duration) -> Self {
+        DbPool {
+            idle: Mutex::new(handlers),
+            returned: Condvar::new(),
+            checkout_timeout,
+        }
+    }
+
+    /// Waits up to the checkout timeout for a free connection.
+    pub fn get(&self) -> Option<PooledHandler<'_>> {
+        let deadline = Instant::now() + self.checkout_timeout;
+        let mut idle = self.idle.lock().unwrap_or_else(|e| e.into_inner());
+
+        loop {
+            if let Some(handler) = idle.pop() {
+                return Some(PooledHandler { pool: self, handler: Some(handler) });
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return None;
+            }
+            idle = self
+                .returned
+                .wait_timeout(idle, remaining)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+    }
+}
+
+impl Deref for PooledHandler<'_> {
+    type Target = DatabaseHandler;
+
+    fn deref(&self) -> &DatabaseHandler {
+        self.handler.as_ref().unwrap()
+    }
+}
+
+impl Drop for PooledHandler<'_> {
+    fn drop(&mut self) {
+        if let Some(handler) = self.handler.take() {
+            self.pool.idle.lock().unwrap_or_else(|e| e.into_inner()).push(handler);
+            self.pool.returned.notify_one();
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::wt_types::Set;
+
+/// How an exercise's sets are measured. The kind decides what a set's
+/// `weight` means and how sets are compared and added up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExerciseKind {
+    /// Reps with `weight` being the load lifted.
+    #[default]
+    WeightReps,
+    /// Reps moving your own body, `weight` being anything added on top.
+    Bodyweight,
+    /// Reps with a machine or band helping, `weight` being the assistance.
+    Assisted,
+    /// Held for `duration_seconds`, optionally with added `weight`.
+    Timed,
+    /// `distance_meters` covered in `duration_seconds`.
+    Cardio,
+}
+
+impl ExerciseKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExerciseKind::WeightReps => "weight_reps",
+            ExerciseKind::Bodyweight => "bodyweight",
+            ExerciseKind::Assisted => "assisted",
+            ExerciseKind::Timed => "timed",
+            ExerciseKind::Cardio => "cardio",
+        }
+    }
+
+    /// Whether sets are measured by time and distance rather than reps.
+    pub fn is_timed(&self) -> bool {
+        matches!(self, ExerciseKind::Timed | ExerciseKind::Cardio)
+    }
+
+    /// Weight actually moved on each rep. Bodyweight and assisted sets need
+    /// the lifter's bodyweight to know it; timed and cardio sets have none.
+    pub fn load(&self, weight: f64, bodyweight: Option<f64>) -> Option<f64> {
+        match self {
+            ExerciseKind::WeightReps => Some(weight),
+            ExerciseKind::Bodyweight => bodyweight.map(|bodyweight| bodyweight + weight),
+            ExerciseKind::Assisted => bodyweight.map(|bodyweight| (bodyweight - weight).max(0.0)),
+            ExerciseKind::Timed | ExerciseKind::Cardio => None,
+        }
+    }
+
+    /// Weight times reps, counted towards a workout's total volume.
+    pub fn volume(&self, set: &Set) -> f64 {
+        self.load(set.weight, set.bodyweight)
+            .map_or(0.0, |load| load * set.reps as f64)
+    }
+
+    /// How good a set is, for picking an exercise's best set: volume for
+    /// lifts (reps when the lifter's bodyweight is unknown), time held for
+    /// timed sets and distance, or failing that time, for cardio.
+    pub fn score(&self, set: &Set) -> Option<f64> {
+        match self {
+            ExerciseKind::WeightReps => Some(self.volume(set)),
+            ExerciseKind::Bodyweight | ExerciseKind::Assisted => match self.load(set.weight, set.bodyweight) {
+                Some(load) => Some(load * set.reps as f64),
+                None => Some(set.reps as f64),
+            },
+            ExerciseKind::Timed => set.duration_seconds.map(f64::from),
+            ExerciseKind::Cardio => set.distance_meters.or(set.duration_seconds.map(f64::from)),
+        }
+    }
+
+    /// A set as shown in the exercise list, e.g. `8 x +10kg` or `5 km in 25:00`,
+    /// with weights in the set's unit.
+    pub fn describe(&self, set: &Set) -> String {
+        let duration = set.duration_seconds.map(format_duration);
+        let unit = set.unit.unwrap_or_default();
+        match self {
+            ExerciseKind::WeightReps => format!("{} x {}{}", set.reps, set.weight, unit),
+            ExerciseKind::Bodyweight if set.weight > 0.0 => format!("{} x +{}{}", set.reps, set.weight, unit),
+            ExerciseKind::Bodyweight => format!("{} reps", set.reps),
+            ExerciseKind::Assisted => format!("{} x -{}{}", set.reps, set.weight, unit),
+            ExerciseKind::Timed => match duration {
+                Some(duration) if set.weight > 0.0 => format!("{} +{}{}", duration, set.weight, unit),
+                Some(duration) => duration,
+                None => format!("{} reps", set.reps),
+            },
+            ExerciseKind::Cardio => match (set.distance_meters, duration) {
+                (Some(distance), Some(duration)) => format!("{} in {}", format_distance(distance), duration),
+                (Some(distance), None) => format_distance(distance),
+                (None, Some(duration)) => duration,
+                (None, None) => String::new(),
+            },
+        }
+    }
+
+    /// Checks a set records what this kind is measured by. Timed sets may
+    /// still be logged as reps by clients that predate durations.
+    pub fn validate(&self, set: &Set) -> Result<(), String> {
+        match self {
+            ExerciseKind::Timed if set.duration_seconds.is_none() && set.reps == 0 => {
+                Err("Timed sets need a duration_seconds".to_string())
+            }
+            ExerciseKind::Cardio if set.duration_seconds.is_none() && set.distance_meters.is_none() => {
+                Err("Cardio sets need a duration_seconds or distance_meters".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn format_duration(seconds: u32) -> String {
+    match seconds / 3600 {
+        0 => format!("{}:{:02}", seconds / 60, seconds % 60),
+        hours => format!("{}:{:02}:{:02}", hours, seconds / 60 % 60, seconds % 60),
+    }
+}
+
+fn format_distance(meters: f64) -> String {
+    if meters >= 1000.0 {
+        format!("{} km", (meters / 10.0).round() / 100.0)
+    } else {
+        format!("{} m", meters)
+    }
+}
+
+impl FromStr for ExerciseKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "weight_reps" => Ok(ExerciseKind::WeightReps),
+            "bodyweight" => Ok(ExerciseKind::Bodyweight),
+            "assisted" => Ok(ExerciseKind::Assisted),
+            "timed" => Ok(ExerciseKind::Timed),
+            "cardio" => Ok(ExerciseKind::Cardio),
+            _ => Err(format!("Unknown exercise kind: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for ExerciseKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
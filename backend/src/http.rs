@@ -0,0 +1,298 @@
+use std::{
+    collections::HashMap,
+    io::{self, prelude::*},
+    net::{IpAddr, TcpStream},
+    time::{Duration, Instant},
+};
+
+pub struct Request {
+    pub method: String,
+    pub version: String,
+    pub path: String,
+    pub query_params: HashMap<String, String>,
+    pub headers: HashMap<String, String>,
+    pub params: HashMap<String, String>,
+    pub body: Vec<u8>,
+    pub remote_addr: Option<IpAddr>,
+    pub scheme: &'static str,
+}
+
+/// What we know about the connection a request arrived on.
+pub struct ConnectionInfo<'a> {
+    pub peer: Option<IpAddr>,
+    pub scheme: &'static str,
+    pub max_body: usize,
+    pub trusted_proxies: &'a [IpAddr],
+}
+
+impl Request {
+    /// Header lookup is case-insensitive; names are stored lowercased.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(&name.to_ascii_lowercase()).map(|s| s.as_str())
+    }
+
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params.get(name).map(|s| s.as_str())
+    }
+
+    /// Fills in `remote_addr` and `scheme`, honouring `X-Forwarded-For` and
+    /// `X-Forwarded-Proto` only when the peer is one of our trusted proxies.
+    ///
+    /// The forwarded chain is walked from the right, skipping trusted hops,
+    /// so a client cannot spoof its address by sending its own header.
+    pub fn resolve_client(&mut self, info: &ConnectionInfo) {
+        self.remote_addr = info.peer;
+        self.scheme = info.scheme;
+
+        let is_trusted = |ip: &IpAddr| info.trusted_proxies.contains(ip);
+        if !info.peer.as_ref().is_some_and(is_trusted) {
+            return;
+        }
+
+        if let Some(forwarded_for) = self.header("X-Forwarded-For") {
+            let hops: Vec<IpAddr> = forwarded_for
+                .split(',')
+                .filter_map(|hop| hop.trim().parse().ok())
+                .collect();
+            let client = hops
+                .iter()
+                .rev()
+                .find(|ip| !is_trusted(ip))
+                .or(hops.first())
+                .copied();
+            if client.is_some() {
+                self.remote_addr = client;
+            }
+        }
+
+        let forwarded_proto = self
+            .header("X-Forwarded-Proto")
+            .and_then(|proto| proto.split(',').next())
+            .map(|proto| proto.trim().to_ascii_lowercase());
+        match forwarded_proto.as_deref() {
+            Some("https") => self.scheme = "https",
+            Some("http") => self.scheme = "http",
+            _ => {}
+        }
+    }
+
+    /// HTTP/1.1 connections persist unless the client asks to close;
+    /// HTTP/1.0 clients have to opt in.
+    pub fn wants_keep_alive(&self) -> bool {
+        let connection = self.header("Connection").map(|c| c.to_ascii_lowercase());
+        match connection.as_deref() {
+            Some(c) if c.contains("close") => false,
+            Some(c) if c.contains("keep-alive") => true,
+            _ => self.version != "HTTP/1.0",
+        }
+    }
+}
+
+pub struct Response {
+    pub status: &'static str,
+    pub content_type: &'static str,
+    pub headers: Vec<(&'static str, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn new(status: &'static str, body: impl Into<Vec<u8>>, content_type: &'static str) -> Self {
+        Response {
+            status,
+            content_type,
+            headers: Vec::new(),
+            body: body.into(),
+        }
+    }
+
+    pub fn with_header(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.headers.push((name, value.into()));
+        self
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut head = format!(
+            "{}\r\n\
+             Access-Control-Allow-Origin: *\r\n\
+             Content-Type: {}\r\n\
+             Content-Length: {}\r\n",
+            self.status,
+            self.content_type,
+            self.body.len()
+        );
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str("\r\n");
+
+        let mut bytes = head.into_bytes();
+        bytes.extend_from_slice(&self.body);
+        bytes
+    }
+}
+
+impl From<(&'static str, String, &'static str)> for Response {
+    fn from((status, body, content_type): (&'static str, String, &'static str)) -> Self {
+        Response::new(status, body, content_type)
+    }
+}
+
+#[derive(Debug)]
+pub enum ReadError {
+    Malformed,
+    BodyTooLarge,
+    Io(io::Error),
+}
+
+impl From<io::Error> for ReadError {
+    fn from(e: io::Error) -> Self {
+        ReadError::Io(e)
+    }
+}
+
+/// Reads one request (head and body) from the stream.
+///
+/// Returns `Ok(None)` if the peer closed the connection before sending anything.
+/// Bodies larger than `max_body` are refused before any of it is read.
+pub fn read_request<R: BufRead>(reader: &mut R, max_body: usize) -> Result<Option<Request>, ReadError> {
+    // Tolerate stray CRLFs some clients send between pipelined requests.
+    let mut request_line = String::new();
+    while request_line.trim().is_empty() {
+        request_line.clear();
+        if reader.read_line(&mut request_line)? == 0 {
+            return Ok(None);
+        }
+    }
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+        }
+    }
+
+    let parts: Vec<&str> = request_line.split_whitespace().collect();
+    if parts.len() < 3 {
+        return Err(ReadError::Malformed);
+    }
+
+    let (path, query_string) = parts[1].split_once('?').unwrap_or((parts[1], ""));
+    let query_params = parse_query_string(query_string);
+
+    let content_length = headers
+        .get("content-length")
+        .and_then(|len: &String| len.parse::<usize>().ok())
+        .unwrap_or(0);
+    if content_length > max_body {
+        return Err(ReadError::BodyTooLarge);
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    Ok(Some(Request {
+        method: parts[0].to_ascii_uppercase(),
+        version: parts[2].to_ascii_uppercase(),
+        path: path.to_string(),
+        query_params,
+        headers,
+        params: HashMap::new(),
+        body,
+        remote_addr: None,
+        scheme: "http",
+    }))
+}
+
+fn parse_query_string(query_string: &str) -> HashMap<String, String> {
+    query_string
+        .split('&')
+        .filter(|s| !s.is_empty())
+        .filter_map(|s: &str| {
+            let mut kv = s.splitn(2, '=');
+            Some((percent_decode(kv.next()?), percent_decode(kv.next().unwrap_or(""))))
+        })
+        .collect()
+}
+
+/// Decodes `%XX` escapes and `+` in a query string component. Malformed
+/// escapes are kept as they are.
+fn percent_decode(component: &str) -> String {
+    let bytes = component.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+                match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Socket wrapper that bounds how long a peer may take to deliver a request.
+///
+/// Each read is limited by both the idle timeout and whatever is left of the
+/// read deadline, so a client trickling one byte at a time cannot hold a
+/// worker forever. The deadline restarts whenever we write, i.e. once a
+/// response (or TLS handshake flight) has been sent.
+#[derive(Debug)]
+pub struct DeadlineStream {
+    inner: TcpStream,
+    idle_timeout: Duration,
+    read_deadline: Duration,
+    deadline: Instant,
+}
+
+impl DeadlineStream {
+    pub fn new(inner: TcpStream, idle_timeout: Duration, read_deadline: Duration) -> Self {
+        DeadlineStream {
+            inner,
+            idle_timeout,
+            read_deadline,
+            deadline: Instant::now() + read_deadline,
+        }
+    }
+}
+
+impl Read for DeadlineStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "Read deadline exceeded"));
+        }
+        self.inner.set_read_timeout(Some(remaining.min(self.idle_timeout)))?;
+        self.inner.read(buf)
+    }
+}
+
+impl Write for DeadlineStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.deadline = Instant::now() + self.read_deadline;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
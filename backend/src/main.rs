@@ -1,214 +1,457 @@
 use std::{
-    collections::HashMap,
-    fs::{self, remove_file},
-    io::{prelude::*, BufReader},
-    net::TcpListener,
-    sync::{Arc, Mutex}, thread, time::{Duration, SystemTime},
+    fs,
+    io::{prelude::*, BufReader, ErrorKind},
+    net::{TcpListener, TcpStream},
+    thread, time::{Duration, SystemTime},
 };
 
 mod tracker;
 mod routes;
+mod router;
+use router::Router;
+mod http;
+use http::{ConnectionInfo, DeadlineStream, ReadError, Response};
+mod thread_pool;
+use thread_pool::ThreadPool;
 mod database_handler;
 use database_handler::DatabaseHandler;
+mod db_pool;
+use db_pool::DbPool;
+mod migrations;
+mod catalogue;
+mod personal_records;
+mod progression;
+mod exercise_kind;
+mod one_rep_max;
+mod time_buckets;
+mod user_time;
+mod units;
+mod config;
+use config::Config;
 mod wt_types;
 mod tests;
 
 use openssl::ssl::{SslMethod, SslAcceptor, SslFiletype};
 
 fn main() {
-    
-    let db_handler = Arc::new(Mutex::new(
-        DatabaseHandler::new("workout_tracker.db")
-            .expect("Failed to open database")
-    ));
-
-    let mut acceptor_builder = SslAcceptor::mozilla_intermediate(SslMethod::tls())
-        .expect("Failed to create SSL Acceptor");
-    acceptor_builder
-        .set_private_key_file("ssl/server.key", SslFiletype::PEM)
-        .expect("Failed to set private key");
-    acceptor_builder
-        .set_certificate_chain_file("ssl/server.crt")
-        .expect("Failed to set certificate chain");
-    let acceptor = acceptor_builder.build();
-
-    let listener = TcpListener::bind("0.0.0.0:25561").unwrap();
-
-    fs::create_dir_all("./uploads").expect("Failed to create upload directory");
-    fs::create_dir_all("./processed").expect("Failed to create upload directory");
-
-    thread::spawn(|| {
-        loop {
-            cleanup_old_files();
-            thread::sleep(Duration::from_secs(600));
-        }
-    });
+    let config = Config::load().unwrap_or_else(|e| panic!("Failed to load configuration: {}", e));
 
-    for stream in listener.incoming() {
-        let stream = stream.unwrap();
-        let acceptor = acceptor.clone();
-        let db_handler = Arc::clone(&db_handler);
-        std::thread::spawn(move || {
-            
-            let ssl_stream = match acceptor.accept(stream) {
-                Ok(ssl_stream) => ssl_stream,
-                Err(e) => {
-                    eprintln!("Failed to establish TLS connection: {:?}", e);
-                    return;
-                }
-            };
-    
-            handle_connection(ssl_stream, &db_handler);
+    let applied = DatabaseHandler::new(&config.database_path)
+        .and_then(|db| db.migrate())
+        .expect("Failed to migrate database");
+    println!("Database schema at version {} ({} migrations applied)", migrations::latest_version(), applied);
+
+    let session_lifetime = chrono::Duration::hours(config.session_lifetime_hours);
+    let state = AppState {
+        db: DbPool::open(config.db_pool_size, DB_CHECKOUT_TIMEOUT, || {
+            DatabaseHandler::new(&config.database_path)
+                .map(|db| db.with_session_lifetime(session_lifetime))
+        })
+        .expect("Failed to open database"),
+        config: config.clone(),
+    };
+
+    let acceptor = if config.tls {
+        let mut acceptor_builder = SslAcceptor::mozilla_intermediate(SslMethod::tls())
+            .expect("Failed to create SSL Acceptor");
+        acceptor_builder
+            .set_private_key_file(&config.tls_key_path, SslFiletype::PEM)
+            .expect("Failed to set private key");
+        acceptor_builder
+            .set_certificate_chain_file(&config.tls_cert_path)
+            .expect("Failed to set certificate chain");
+        Some(acceptor_builder.build())
+    } else {
+        None
+    };
+
+    let listener = TcpListener::bind(&config.listen_addr).unwrap();
+
+    fs::create_dir_all(&config.upload_dir).expect("Failed to create upload directory");
+    fs::create_dir_all(&config.processed_dir).expect("Failed to create processed directory");
+
+    {
+        let config = config.clone();
+        thread::spawn(move || {
+            loop {
+                cleanup_old_files(&config);
+                thread::sleep(Duration::from_secs(config.cleanup_interval_secs));
+            }
         });
     }
+
+    println!(
+        "Listening on {} ({}) with {} workers, accept queue of {}",
+        config.listen_addr,
+        if config.tls { "https" } else { "plain http" },
+        config.workers,
+        config.queue_depth
+    );
+
+    serve(listener, acceptor, &config, build_router(), state);
 }
 
+/// Accepts connections forever, handing them to a bounded worker pool.
+///
+/// Without an acceptor the listener speaks plain HTTP, which is how the
+/// server runs behind a TLS-terminating proxy and in tests.
+fn serve<S: Send + Sync + 'static>(
+    listener: TcpListener,
+    acceptor: Option<SslAcceptor>,
+    config: &Config,
+    router: Router<S>,
+    state: S,
+) {
+    let scheme = if acceptor.is_some() { "https" } else { "http" };
+    let max_body = config.max_upload_bytes;
+    let trusted_proxies = config.trusted_proxies.clone();
 
-fn handle_connection<T: Read + Write>(mut stream: T, db_handler: &Arc<Mutex<DatabaseHandler>>) {
-    println!("New connection");
+    let pool = {
+        let acceptor = acceptor.clone();
+        ThreadPool::new(config.workers, config.queue_depth, move |stream: TcpStream| {
+            let info = ConnectionInfo {
+                peer: stream.peer_addr().ok().map(|addr| addr.ip()),
+                scheme,
+                max_body,
+                trusted_proxies: &trusted_proxies,
+            };
+            let stream = DeadlineStream::new(stream, KEEP_ALIVE_TIMEOUT, READ_DEADLINE);
 
-    
-    let mut buf_reader = BufReader::new(&mut stream);
-    let mut request_content = Vec::new();
-    let mut content_length: usize = 0;
-    let mut content_type: Option<String> = None;
+            match &acceptor {
+                Some(acceptor) => match acceptor.accept(stream) {
+                    Ok(ssl_stream) => handle_connection(ssl_stream, &info, &router, &state),
+                    Err(e) => eprintln!("Failed to establish TLS connection: {:?}", e),
+                },
+                None => handle_connection(stream, &info, &router, &state),
+            }
+        })
+    };
 
-    
-    for line in buf_reader.by_ref().lines() {
-        let line = match line {
-            Ok(l) => l,
-            Err(_) => break,
-        };
-        if line.is_empty() {
-            break;
-        }
-        if let Some(len) = line.strip_prefix("Content-Length: ") {
-            content_length = len.parse::<usize>().unwrap_or(0);
+    // Overflow connections get a quick 503 from a single dedicated thread so
+    // the accept loop itself never blocks on a handshake.
+    let rejector = ThreadPool::new(1, config.queue_depth, move |stream: TcpStream| {
+        let response = Response::new(
+            "HTTP/1.1 503 SERVICE UNAVAILABLE",
+            r#"{"error": "Server is busy, try again shortly"}"#,
+            "application/json",
+        )
+        .with_header("Retry-After", RETRY_AFTER_SECS.to_string())
+        .with_header("Connection", "close")
+        .to_bytes();
+
+        let stream = DeadlineStream::new(stream, REJECT_TIMEOUT, REJECT_TIMEOUT);
+        match &acceptor {
+            Some(acceptor) => {
+                if let Ok(mut ssl_stream) = acceptor.accept(stream) {
+                    let _ = ssl_stream.write_all(&response);
+                }
+            }
+            None => {
+                let mut stream = stream;
+                let _ = stream.write_all(&response);
+            }
         }
-        if let Some(ct) = line.strip_prefix("Content-Type: ") {
-            content_type = Some(ct.to_string());
+    });
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("Failed to accept connection: {}", e);
+                continue;
+            }
+        };
+
+        if let Err(stream) = pool.try_submit(stream) {
+            eprintln!("Worker pool saturated, rejecting connection");
+            // If even the rejector is backed up the connection is simply dropped.
+            let _ = rejector.try_submit(stream);
         }
-        request_content.push(line);
     }
+}
 
-    let request_line = request_content.get(0).unwrap_or(&"".to_string()).clone();
-    let parts: Vec<&str> = request_line.split_whitespace().collect();
-    if parts.len() < 3 {
-        let response =
-            build_response("HTTP/1.1 400 BAD REQUEST", "400 BAD REQUEST", "text/html");
-        stream.write_all(response.as_bytes()).unwrap();
-        return;
+const DB_CHECKOUT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Total time a client gets to deliver a request once we start waiting for it.
+const READ_DEADLINE: Duration = Duration::from_secs(30);
+const REJECT_TIMEOUT: Duration = Duration::from_secs(2);
+const RETRY_AFTER_SECS: u64 = 5;
+
+pub struct AppState {
+    pub db: DbPool,
+    pub config: Config,
+}
+
+/// Runs `f` with a pooled connection, held only for the duration of the call.
+fn with_db<F, T>(state: &AppState, f: F) -> Response
+where
+    F: FnOnce(&DatabaseHandler) -> T,
+    T: Into<Response>,
+{
+    match state.db.get() {
+        Some(db) => f(&db).into(),
+        None => Response::new(
+            "HTTP/1.1 503 SERVICE UNAVAILABLE",
+            r#"{"error": "Database is busy, try again shortly"}"#,
+            "application/json",
+        )
+        .with_header("Retry-After", RETRY_AFTER_SECS.to_string()),
     }
+}
 
-    let path_with_query = parts[1];
-    let path_parts: Vec<&str> = path_with_query.splitn(2, '?').collect();
-    let path = path_parts[0];
-    let query_string = if path_parts.len() > 1 { path_parts[1] } else { "" };
-
-    println!("Path: {}", path);
-
-    if path.starts_with("/processed/") {
-        println!("Trying to download video!");
-        let file_path = format!("./processed/{}", path.trim_start_matches("/processed/"));
-        match fs::read(&file_path) {
-            Ok(bytes) => {
-                let mime_type = if file_path.ends_with(".mp4") {
-                    "video/mp4"
-                } else {
-                    "application/octet-stream"
-                };
-                let response = format!(
-                    "HTTP/1.1 200 OK\r\n\
-                     Access-Control-Allow-Origin: *\r\n\
-                     Content-Type: {}\r\n\
-                     Content-Length: {}\r\n\r\n",
-                    mime_type,
-                    bytes.len()
-                );
-                stream.write_all(response.as_bytes()).unwrap();
-                stream.write_all(&bytes).unwrap();
-
-                let _ = remove_file(file_path);
-                return;
+fn build_router() -> Router<AppState> {
+    Router::<AppState>::new()
+        .get("/exercises", |req, state| {
+            with_db(state, |db| routes::handle_exercises_route(req.query_params.clone(), db))
+        })
+        .post("/login", |req, state| {
+            with_db(state, |db| routes::handle_login_route(&req.body[..], db, req.body.len()))
+        })
+        .post("/register", |req, state| {
+            with_db(state, |db| routes::handle_register_route(&req.body[..], db, req.body.len()))
+        })
+        .get("/history", |req, state| {
+            with_db(state, |db| routes::handle_history_route(req.query_params.clone(), db))
+        })
+        .get("/workouts_per_week", |req, state| {
+            with_db(state, |db| routes::handle_workouts_per_week_route(req.query_params.clone(), db))
+        })
+        .get("/previous_sets", |req, state| {
+            with_db(state, |db| routes::handle_previous_sets_route(req.query_params.clone(), db))
+        })
+        .get("/one_rep_max", |req, state| {
+            with_db(state, |db| routes::handle_one_rep_max_route(req.query_params.clone(), db))
+        })
+        .get("/templates", |req, state| {
+            with_db(state, |db| routes::handle_templates_route(req.query_params.clone(), db))
+        })
+        .post("/save_template", |req, state| {
+            with_db(state, |db| routes::handle_save_template_route(&req.body[..], db, req.body.len()))
+        })
+        .put("/templates/{id}", |req, state| {
+            with_db(state, |db| {
+                routes::handle_update_template_route(
+                    &req.body[..],
+                    req.query_params.clone(),
+                    req.param("id").unwrap_or_default(),
+                    db,
+                    req.body.len(),
+                )
+            })
+        })
+        .delete("/templates/{id}", |req, state| {
+            with_db(state, |db| {
+                routes::handle_delete_template_route(req.query_params.clone(), req.param("id").unwrap_or_default(), db)
+            })
+        })
+        .get("/templates/{id}/progression", |req, state| {
+            with_db(state, |db| {
+                routes::handle_progression_log_route(req.query_params.clone(), req.param("id").unwrap_or_default(), db)
+            })
+        })
+        .post("/templates/{id}/start", |req, state| {
+            with_db(state, |db| {
+                routes::handle_start_template_route(req.query_params.clone(), req.param("id").unwrap_or_default(), db)
+            })
+        })
+        .post("/templates/{id}/duplicate", |req, state| {
+            with_db(state, |db| {
+                routes::handle_duplicate_template_route(
+                    &req.body[..],
+                    req.query_params.clone(),
+                    req.param("id").unwrap_or_default(),
+                    db,
+                    req.body.len(),
+                )
+            })
+        })
+        .get("/programs", |req, state| {
+            with_db(state, |db| routes::handle_programs_route(req.query_params.clone(), db))
+        })
+        .post("/programs", |req, state| {
+            with_db(state, |db| {
+                routes::handle_create_program_route(&req.body[..], req.query_params.clone(), db, req.body.len())
+            })
+        })
+        .delete("/programs/{id}", |req, state| {
+            with_db(state, |db| {
+                routes::handle_delete_program_route(req.query_params.clone(), req.param("id").unwrap_or_default(), db)
+            })
+        })
+        .post("/programs/{id}/start", |req, state| {
+            with_db(state, |db| {
+                routes::handle_start_program_route(req.query_params.clone(), req.param("id").unwrap_or_default(), db)
+            })
+        })
+        .get("/today", |req, state| {
+            with_db(state, |db| routes::handle_today_route(req.query_params.clone(), db))
+        })
+        .get("/training_maxes", |req, state| {
+            with_db(state, |db| routes::handle_training_maxes_route(req.query_params.clone(), db))
+        })
+        .put("/training_maxes/{id}", |req, state| {
+            with_db(state, |db| {
+                routes::handle_update_training_max_route(
+                    &req.body[..],
+                    req.query_params.clone(),
+                    req.param("id").unwrap_or_default(),
+                    db,
+                    req.body.len(),
+                )
+            })
+        })
+        .post("/workout", |req, state| {
+            with_db(state, |db| {
+                routes::handle_workout_route(
+                    &req.body[..],
+                    req.query_params.clone(),
+                    req.header("Idempotency-Key"),
+                    db,
+                    req.body.len(),
+                )
+            })
+        })
+        .get("/workouts/{id}", |req, state| {
+            with_db(state, |db| {
+                routes::handle_get_workout_route(req.query_params.clone(), req.param("id").unwrap_or_default(), db)
+            })
+        })
+        .put("/workouts/{id}", |req, state| {
+            with_db(state, |db| {
+                routes::handle_update_workout_route(
+                    &req.body[..],
+                    req.query_params.clone(),
+                    req.param("id").unwrap_or_default(),
+                    db,
+                    req.body.len(),
+                )
+            })
+        })
+        .delete("/workouts/{id}", |req, state| {
+            with_db(state, |db| {
+                routes::handle_delete_workout_route(req.query_params.clone(), req.param("id").unwrap_or_default(), db)
+            })
+        })
+        .get("/preferences", |req, state| {
+            with_db(state, |db| routes::handle_get_preferences_route(req.query_params.clone(), db))
+        })
+        .put("/preferences", |req, state| {
+            with_db(state, |db| {
+                routes::handle_update_preferences_route(&req.body[..], req.query_params.clone(), db, req.body.len())
+            })
+        })
+        .post("/add_exercise", |req, state| {
+            with_db(state, |db| routes::handle_add_exercise_route(&req.body[..], db, req.body.len()))
+        })
+        .put("/exercises/{id}", |req, state| {
+            with_db(state, |db| {
+                routes::handle_update_exercise_route(
+                    &req.body[..],
+                    req.query_params.clone(),
+                    req.param("id").unwrap_or_default(),
+                    db,
+                    req.body.len(),
+                )
+            })
+        })
+        .post("/exercises/{id}/merge", |req, state| {
+            with_db(state, |db| {
+                routes::handle_merge_exercise_route(
+                    &req.body[..],
+                    req.query_params.clone(),
+                    req.param("id").unwrap_or_default(),
+                    db,
+                    req.body.len(),
+                )
+            })
+        })
+        .post("/upload/metadata", |req, state| {
+            routes::handle_metadata_upload(&req.body[..], req.body.len(), &state.config.processed_dir).into()
+        })
+        .post("/upload/video", |req, state| match req.header("Content-Type") {
+            Some(ct) => routes::handle_video_upload(&req.body[..], req.body.len(), ct.to_string(), &state.config.upload_dir).into(),
+            None => Response::new("HTTP/1.1 400 BAD REQUEST", "Missing Content-Type", "text/html"),
+        })
+        .get("/processed/{file}", |req, state| {
+            routes::handle_processed_download(&state.config.processed_dir, req.param("file").unwrap_or_default())
+        })
+}
+
+/// How long an idle keep-alive connection is held open waiting for the next request.
+const KEEP_ALIVE_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_REQUESTS_PER_CONNECTION: usize = 100;
+
+fn handle_connection<T: Read + Write, S>(stream: T, info: &ConnectionInfo, router: &Router<S>, state: &S) {
+    let max_body = info.max_body;
+
+    // The reader is kept across requests so pipelined requests already
+    // buffered from the socket are not lost between iterations.
+    let mut buf_reader = BufReader::new(stream);
+
+    for served in 1..=MAX_REQUESTS_PER_CONNECTION {
+        let (response, keep_alive) = match http::read_request(&mut buf_reader, max_body) {
+            Ok(Some(mut request)) => {
+                request.resolve_client(info);
+                let client = request.remote_addr.map(|ip| ip.to_string()).unwrap_or_default();
+                println!("{} {} from {} ({})", request.method, request.path, client, request.scheme);
+                let keep_alive = request.wants_keep_alive() && served < MAX_REQUESTS_PER_CONNECTION;
+                (router.dispatch(&mut request, state), keep_alive)
             }
-            Err(_) => {
-                let response = build_response(
-                    "HTTP/1.1 404 NOT FOUND",
-                    "404 NOT FOUND",
-                    "text/html",
-                );
-                stream.write_all(response.as_bytes()).unwrap();
+            Ok(None) => return,
+            Err(ReadError::Malformed) => (
+                Response::new("HTTP/1.1 400 BAD REQUEST", "400 BAD REQUEST", "text/html"),
+                false,
+            ),
+            // The unread body is still on the wire, so the connection can't be reused.
+            Err(ReadError::BodyTooLarge) => (
+                Response::new(
+                    "HTTP/1.1 413 PAYLOAD TOO LARGE",
+                    format!(r#"{{"error": "Request body exceeds {} bytes"}}"#, max_body),
+                    "application/json",
+                ),
+                false,
+            ),
+            // Idle timeout, reset or truncated request: nothing sensible to answer.
+            Err(ReadError::Io(e)) => {
+                if !matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::UnexpectedEof) {
+                    eprintln!("Connection error: {}", e);
+                }
                 return;
             }
-        }
-    }
+        };
 
-    let query_params: HashMap<_, _> = query_string
-        .split('&')
-        .filter_map(|s: &str| {
-            let mut kv = s.splitn(2, '=');
-            Some((kv.next()?.to_string(), kv.next().unwrap_or("").to_string()))
-        })
-        .collect();
-
-    
-    let mut db = db_handler.lock().unwrap();
-
-    
-    
-    let (status_line, contents, content_type) = match path {
-        "/exercises" => routes::handle_exercises_route(query_params, &mut db),
-        "/login" => routes::handle_login_route(buf_reader, &mut db, content_length),
-        "/register" => routes::handle_register_route(buf_reader, &mut db, content_length),
-        "/history" => routes::handle_history_route(query_params, &mut db),
-        "/workouts_per_week" => routes::handle_workouts_per_week_route(query_params, &mut db),
-        "/previous_sets" => routes::handle_previous_sets_route(query_params, &mut db),
-        "/one_rep_max" => routes::handle_one_rep_max_route(query_params, &mut db),
-        "/templates" => routes::handle_templates_route(query_params, &mut db),
-        "/save_template" => routes::handle_save_template_route(buf_reader, &mut db, content_length),
-        "/workout" => routes::handle_workout_route(buf_reader, query_params, &mut db, content_length),
-        "/add_exercise" => routes::handle_add_exercise_route(buf_reader, &mut db, content_length),
-        "/upload/metadata" => routes::handle_metadata_upload(buf_reader, content_length),
-        "/upload/video" => {
-            if let Some(ct) = content_type {
-                routes::handle_video_upload(buf_reader, content_length, ct)
-            } else {
-                ("HTTP/1.1 400 BAD REQUEST", "Missing Content-Type".to_string(), "text/html")
-            }
+        let response = if keep_alive {
+            response
+                .with_header("Connection", "keep-alive")
+                .with_header(
+                    "Keep-Alive",
+                    format!(
+                        "timeout={}, max={}",
+                        KEEP_ALIVE_TIMEOUT.as_secs(),
+                        MAX_REQUESTS_PER_CONNECTION - served
+                    ),
+                )
+        } else {
+            response.with_header("Connection", "close")
+        };
+
+        let stream = buf_reader.get_mut();
+        if stream.write_all(&response.to_bytes()).and_then(|_| stream.flush()).is_err() {
+            return;
         }
-        
-        _ => (
-            "HTTP/1.1 404 NOT FOUND",
-            "<html><body>404 NOT FOUND</body></html>".to_string(),
-            "text/html",
-        ),
-    };
 
-    if status_line.is_empty() && contents.is_empty() && content_type.is_empty() {
-        return;
+        if !keep_alive {
+            return;
+        }
     }
-
-    let response = build_response(status_line, &contents, content_type);
-    stream.write_all(response.as_bytes()).unwrap();
-}
-
-fn build_response(status: &str, body: &str, content_type: &str) -> String {
-    format!(
-        "{status}\r\n\
-         Access-Control-Allow-Origin: *\r\n\
-         Content-Type: {content_type}\r\n\
-         Content-Length: {}\r\n\r\n{body}",
-        body.len()
-    )
 }
 
-fn cleanup_old_files() {
-    const MAX_AGE: Duration = Duration::from_secs(3600);
-    let dirs = ["./processed", "./uploads"];
+fn cleanup_old_files(config: &Config) {
+    let max_age = Duration::from_secs(config.file_max_age_secs);
+    let dirs = [&config.processed_dir, &config.upload_dir];
     let now = SystemTime::now();
 
     for dir in dirs {
-        if let Err(e) = cleanup_directory(dir, now, MAX_AGE) {
+        if let Err(e) = cleanup_directory(dir, now, max_age) {
             eprintln!("Directory cleanup failed for {}: {}", dir, e);
         }
     }
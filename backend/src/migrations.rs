@@ -0,0 +1,80 @@
+use rusqlite::{Connection, Result};
+
+use crate::{catalogue, personal_records};
+
+/// A schema change, either plain SQL or Rust code for data that SQL alone
+/// cannot derive.
+enum Migration {
+    Sql(&'static str),
+    Code(fn(&Connection) -> Result<()>),
+    /// Data rebuilt with the application's own, current queries. These read
+    /// columns that later migrations add, so rather than at its position in
+    /// the list it runs once the last pending migration has been applied, in
+    /// that migration's transaction.
+    Backfill(fn(&Connection) -> Result<()>),
+}
+
+/// Ordered schema migrations. The database's `PRAGMA user_version` records how
+/// many of them have been applied, so new migrations are only ever appended.
+///
+/// Migration 1 is the original schema and is written to be idempotent, so
+/// databases created before migrations existed upgrade cleanly.
+///
+/// Code steps take a version number too, so from version 6 on a file's number
+/// is behind the version it brings the database to; each entry is marked
+/// with its version.
+const MIGRATIONS: &[Migration] = &[
+    Migration::Sql(include_str!("../migrations/0001_initial.sql")), // 1
+    Migration::Sql(include_str!("../migrations/0002_workout_client_id.sql")), // 2
+    Migration::Sql(include_str!("../migrations/0003_history_indexes.sql")), // 3
+    Migration::Sql(include_str!("../migrations/0004_personal_records.sql")), // 4
+    Migration::Backfill(personal_records::rebuild_all), // 5
+    Migration::Sql(include_str!("../migrations/0005_user_preferences.sql")), // 6
+    Migration::Sql(include_str!("../migrations/0006_user_timezone.sql")), // 7
+    Migration::Sql(include_str!("../migrations/0007_set_types.sql")), // 8
+    Migration::Sql(include_str!("../migrations/0008_exercise_groups.sql")), // 9
+    Migration::Sql(include_str!("../migrations/0009_exercise_kinds.sql")), // 10
+    Migration::Sql(include_str!("../migrations/0010_weight_units.sql")), // 11
+    Migration::Sql(include_str!("../migrations/0011_exercise_archive.sql")), // 12
+    Migration::Sql(include_str!("../migrations/0012_master_catalogue.sql")), // 13
+    Migration::Code(catalogue::seed), // 14
+    Migration::Sql(include_str!("../migrations/0013_template_positions.sql")), // 15
+    Migration::Sql(include_str!("../migrations/0014_template_sets.sql")), // 16
+    Migration::Sql(include_str!("../migrations/0015_workout_template.sql")), // 17
+    Migration::Sql(include_str!("../migrations/0016_programs.sql")), // 18
+    Migration::Sql(include_str!("../migrations/0017_progression.sql")), // 19
+    Migration::Sql(include_str!("../migrations/0018_legacy_local_times.sql")), // 20
+    Migration::Sql(include_str!("../migrations/0019_progression_undo.sql")), // 21
+];
+
+pub fn latest_version() -> u32 {
+    MIGRATIONS.len() as u32
+}
+
+/// Applies any pending migrations, each in its own transaction, and returns
+/// how many were applied.
+pub fn run(conn: &Connection) -> Result<u32> {
+    let current: u32 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    let mut applied = 0;
+    let mut backfills = Vec::new();
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(current as usize) {
+        let version = index as u32 + 1;
+        let tx = conn.unchecked_transaction()?;
+        match migration {
+            Migration::Sql(sql) => tx.execute_batch(sql)?,
+            Migration::Code(apply) => apply(&tx)?,
+            Migration::Backfill(backfill) => backfills.push(backfill),
+        }
+        if version == latest_version() {
+            for backfill in &backfills {
+                backfill(&tx)?;
+            }
+        }
+        tx.pragma_update(None, "user_version", version)?;
+        tx.commit()?;
+        applied += 1;
+    }
+
+    Ok(applied)
+}
@@ -0,0 +1,93 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+/// Sets with more reps than this say more about endurance than strength, so
+/// no formula is trusted to turn them into a one-rep max.
+pub const MAX_RELIABLE_REPS: u32 = 12;
+
+/// Percentage of one-rep max that can be lifted for 1..=12 reps to failure
+/// (RPE 10), from the RTS chart. Lower RPEs read further along the row, one
+/// rep per RPE point.
+const RPE_10_PERCENTAGES: [f64; 12] = [
+    100.0, 95.5, 92.2, 89.2, 86.3, 83.7, 81.1, 78.6, 76.2, 73.9, 70.7, 68.0,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OneRepMaxFormula {
+    #[default]
+    Epley,
+    Brzycki,
+    Lombardi,
+    /// RPE chart lookup; sets logged without an RPE are treated as RPE 10.
+    Rpe,
+}
+
+impl OneRepMaxFormula {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OneRepMaxFormula::Epley => "epley",
+            OneRepMaxFormula::Brzycki => "brzycki",
+            OneRepMaxFormula::Lombardi => "lombardi",
+            OneRepMaxFormula::Rpe => "rpe",
+        }
+    }
+
+    /// Estimated one-rep max for a set, or `None` if the set has no reps or
+    /// too many to estimate from.
+    pub fn estimate(&self, weight: f64, reps: u32, rpe: Option<f64>) -> Option<f64> {
+        if reps == 0 || reps > MAX_RELIABLE_REPS {
+            return None;
+        }
+        if reps == 1 && *self != OneRepMaxFormula::Rpe {
+            return Some(weight);
+        }
+
+        let reps_f = reps as f64;
+        let estimate = match self {
+            OneRepMaxFormula::Epley => weight * (1.0 + reps_f / 30.0),
+            OneRepMaxFormula::Brzycki => weight * 36.0 / (37.0 - reps_f),
+            OneRepMaxFormula::Lombardi => weight * reps_f.powf(0.10),
+            OneRepMaxFormula::Rpe => {
+                let reps_in_reserve = 10.0 - rpe.unwrap_or(10.0).clamp(6.0, 10.0);
+                let effective_reps = reps_f + reps_in_reserve;
+                if effective_reps > MAX_RELIABLE_REPS as f64 {
+                    return None;
+                }
+                weight * 100.0 / rpe_percentage(effective_reps)
+            }
+        };
+        Some(estimate)
+    }
+}
+
+/// Interpolates the RPE 10 row for fractional rep counts (half-point RPEs).
+fn rpe_percentage(effective_reps: f64) -> f64 {
+    let lower = (effective_reps.floor() as usize).clamp(1, RPE_10_PERCENTAGES.len());
+    let upper = (lower + 1).min(RPE_10_PERCENTAGES.len());
+    let fraction = effective_reps - lower as f64;
+    let low = RPE_10_PERCENTAGES[lower - 1];
+    let high = RPE_10_PERCENTAGES[upper - 1];
+    low + (high - low) * fraction
+}
+
+impl FromStr for OneRepMaxFormula {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "epley" => Ok(OneRepMaxFormula::Epley),
+            "brzycki" => Ok(OneRepMaxFormula::Brzycki),
+            "lombardi" => Ok(OneRepMaxFormula::Lombardi),
+            "rpe" => Ok(OneRepMaxFormula::Rpe),
+            _ => Err(format!("Unknown one rep max formula: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for OneRepMaxFormula {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
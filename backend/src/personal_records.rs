@@ -0,0 +1,325 @@
+use std::collections::HashMap;
+
+use rusqlite::{params, Connection, OptionalExtension, Result};
+
+use crate::exercise_kind::ExerciseKind;
+use crate::one_rep_max::OneRepMaxFormula;
+
+pub const HEAVIEST_WEIGHT: &str = "heaviest_weight";
+pub const ESTIMATED_1RM: &str = "estimated_1rm";
+pub const REPS_AT_WEIGHT: &str = "reps_at_weight";
+pub const SET_VOLUME: &str = "set_volume";
+pub const SESSION_VOLUME: &str = "session_volume";
+/// Timed and cardio exercises only.
+pub const LONGEST_DURATION: &str = "longest_duration";
+/// Cardio exercises only.
+pub const LONGEST_DISTANCE: &str = "longest_distance";
+
+/// Recomputes the records for the given exercises and refreshes the
+/// per-workout PR counts. Call after any change to an exercise's sets.
+///
+/// With `since`, the stored start time of the earliest workout that changed,
+/// only workouts from then on are walked again; the bests set before it are
+/// read back with a couple of aggregate queries. That path needs the SQL
+/// functions `DatabaseHandler` registers. Without it the whole history is
+/// rebuilt.
+pub fn rebuild_for_exercises(conn: &Connection, user_id: u32, exercise_ids: &[u32], since: Option<&str>) -> Result<()> {
+    let mut exercise_ids = exercise_ids.to_vec();
+    exercise_ids.sort_unstable();
+    exercise_ids.dedup();
+
+    for exercise_id in exercise_ids {
+        rebuild_exercise(conn, user_id, exercise_id, since)?;
+    }
+
+    conn.execute(
+        "UPDATE workouts
+         SET prs = (SELECT COUNT(*) FROM personal_records pr WHERE pr.workout_id = workouts.id)
+         WHERE user_id = ?1 AND (?2 IS NULL OR start_time >= ?2)",
+        params![user_id, since],
+    )?;
+    Ok(())
+}
+
+/// Recomputes every user's records, used to backfill existing workouts.
+pub fn rebuild_all(conn: &Connection) -> Result<()> {
+    let mut stmt = conn.prepare(
+        "SELECT DISTINCT w.user_id, we.exercise_id
+         FROM workouts w
+         JOIN workout_exercises we ON we.workout_id = w.id
+         ORDER BY w.user_id",
+    )?;
+    let pairs = stmt
+        .query_map([], |row| Ok((row.get::<_, u32>(0)?, row.get::<_, u32>(1)?)))?
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut by_user: HashMap<u32, Vec<u32>> = HashMap::new();
+    for (user_id, exercise_id) in pairs {
+        by_user.entry(user_id).or_default().push(exercise_id);
+    }
+    for (user_id, exercise_ids) in by_user {
+        rebuild_for_exercises(conn, user_id, &exercise_ids, None)?;
+    }
+    Ok(())
+}
+
+struct SetRow {
+    workout_id: u32,
+    set_id: u32,
+    /// As entered, so for assisted sets the assistance.
+    weight: f64,
+    reps: u32,
+    /// Weight moved per rep, see [`ExerciseKind::load`]. `None` for sets
+    /// without reps or whose load isn't known.
+    load: Option<f64>,
+    duration: Option<f64>,
+    distance: Option<f64>,
+}
+
+struct Record {
+    workout_id: u32,
+    set_id: Option<u32>,
+    record_type: &'static str,
+    value: f64,
+    previous_value: f64,
+    weight: Option<f64>,
+}
+
+/// Best value seen so far for one kind of record.
+#[derive(Default)]
+struct Best {
+    value: Option<f64>,
+}
+
+/// Bests so far for every kind of record an exercise can hold.
+#[derive(Default)]
+struct Bests {
+    heaviest: Best,
+    one_rep_max: Best,
+    set_volume: Best,
+    session_volume: Best,
+    duration: Best,
+    distance: Best,
+    /// Keyed on the weight as entered, in grams so float weights can be
+    /// compared exactly; for assisted sets that is the assistance.
+    reps_at_weight: HashMap<i64, Best>,
+}
+
+/// Working sets of one exercise from the user's workouts before a start time,
+/// with their load as [`SetRow::load`] has it. Takes the user, exercise,
+/// exercise kind and start time as ?1 to ?4.
+const EARLIER_SETS: &str = "
+    SELECT w.id AS workout_id, s.weight, s.reps, s.duration_seconds, s.distance_meters,
+           CASE WHEN s.reps > 0 THEN set_load(?3, s.weight, s.bodyweight) END AS load
+    FROM sets s
+    JOIN workout_exercises we ON s.workout_exercise_id = we.id
+    JOIN workouts w ON we.workout_id = w.id
+    WHERE w.user_id = ?1 AND we.exercise_id = ?2 AND s.set_type != 'warmup' AND w.start_time < ?4";
+
+/// The bests an exercise's workouts before `since` leave behind, as walking
+/// them in [`rebuild_exercise`] would.
+fn earlier_bests(conn: &Connection, user_id: u32, exercise_id: u32, kind: ExerciseKind, since: &str) -> Result<Bests> {
+    let best = |value: Option<f64>| Best { value };
+    let params = params![user_id, exercise_id, kind.as_str(), since];
+    let mut bests = conn.query_row(
+        &format!(
+            "WITH earlier AS ({})
+             SELECT MAX(load), MAX(e1rm('epley', load, reps, NULL)), MAX(load * reps), MAX(duration_seconds),
+                    MAX(distance_meters),
+                    (SELECT MAX(volume) FROM
+                        (SELECT SUM(load * reps) AS volume FROM earlier WHERE load IS NOT NULL GROUP BY workout_id))
+             FROM earlier",
+            EARLIER_SETS
+        ),
+        params,
+        |row| {
+            Ok(Bests {
+                heaviest: best(row.get(0)?),
+                one_rep_max: best(row.get(1)?),
+                set_volume: best(row.get(2)?),
+                duration: best(row.get::<_, Option<f64>>(3)?.filter(|_| kind.is_timed())),
+                distance: best(row.get::<_, Option<f64>>(4)?.filter(|_| kind == ExerciseKind::Cardio)),
+                session_volume: best(row.get(5)?),
+                reps_at_weight: HashMap::new(),
+            })
+        },
+    )?;
+
+    let mut stmt = conn.prepare(&format!(
+        "WITH earlier AS ({})
+         SELECT CAST(round(weight * 1000) AS INTEGER), MAX(reps) FROM earlier WHERE load IS NOT NULL GROUP BY 1",
+        EARLIER_SETS
+    ))?;
+    let rows = stmt.query_map(params, |row| Ok((row.get::<_, i64>(0)?, row.get::<_, f64>(1)?)))?;
+    for row in rows {
+        let (key, reps) = row?;
+        bests.reps_at_weight.insert(key, best(Some(reps)));
+    }
+    Ok(bests)
+}
+
+impl Best {
+    /// Records a new best and returns the value it replaced, if it beat one.
+    /// The first value ever seen sets the baseline and is not a record.
+    fn beat(&mut self, value: f64) -> Option<f64> {
+        match self.value {
+            Some(previous) if value > previous + f64::EPSILON => {
+                self.value = Some(value);
+                Some(previous)
+            }
+            Some(_) => None,
+            None => {
+                self.value = Some(value);
+                None
+            }
+        }
+    }
+}
+
+/// Walks an exercise's sets in the order they were performed and stores a
+/// record for every workout that beat the best from earlier workouts. Within
+/// a workout only its best set counts, so three heavier sets in a row are one
+/// record rather than three. Warm-up sets never count.
+///
+/// Lifts are compared on the weight moved, including bodyweight where the
+/// exercise's kind calls for it; timed and cardio exercises on time and distance.
+fn rebuild_exercise(conn: &Connection, user_id: u32, exercise_id: u32, since: Option<&str>) -> Result<()> {
+    // Records from before `since` stay, unless their workout is gone.
+    conn.execute(
+        "DELETE FROM personal_records
+         WHERE user_id = ?1 AND exercise_id = ?2
+           AND (?3 IS NULL OR workout_id NOT IN (SELECT id FROM workouts WHERE user_id = ?1 AND start_time < ?3))",
+        params![user_id, exercise_id, since],
+    )?;
+
+    let kind: ExerciseKind = conn
+        .query_row("SELECT kind FROM user_exercises WHERE id = ?1", params![exercise_id], |row| {
+            row.get::<_, String>(0)
+        })
+        .optional()?
+        .and_then(|kind| kind.parse().ok())
+        .unwrap_or_default();
+
+    let mut stmt = conn.prepare(
+        "SELECT w.id, s.id, s.weight, s.reps, s.bodyweight, s.duration_seconds, s.distance_meters
+         FROM sets s
+         JOIN workout_exercises we ON s.workout_exercise_id = we.id
+         JOIN workouts w ON we.workout_id = w.id
+         WHERE w.user_id = ?1 AND we.exercise_id = ?2 AND s.set_type != 'warmup'
+           AND (?3 IS NULL OR w.start_time >= ?3)
+         ORDER BY w.start_time, w.id, we.id, s.set_number",
+    )?;
+    let sets = stmt
+        .query_map(params![user_id, exercise_id, since], |row| {
+            let weight = row.get(2)?;
+            let reps = row.get(3)?;
+            Ok(SetRow {
+                workout_id: row.get(0)?,
+                set_id: row.get(1)?,
+                weight,
+                reps,
+                load: kind.load(weight, row.get(4)?).filter(|_| reps > 0),
+                duration: row.get::<_, Option<u32>>(5)?.map(f64::from).filter(|_| kind.is_timed()),
+                distance: row.get::<_, Option<f64>>(6)?.filter(|_| kind == ExerciseKind::Cardio),
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut bests = match since {
+        Some(since) => earlier_bests(conn, user_id, exercise_id, kind, since)?,
+        None => Bests::default(),
+    };
+    let mut records = Vec::new();
+
+    for workout_sets in sets.chunk_by(|a, b| a.workout_id == b.workout_id) {
+        let workout_id = workout_sets[0].workout_id;
+
+        let mut set_record = |best: &mut Best, record_type, value_of: &dyn Fn(&SetRow) -> Option<f64>| {
+            let top = workout_sets
+                .iter()
+                .filter_map(|set| Some((set, value_of(set)?)))
+                .fold(None, |top: Option<(&SetRow, f64)>, (set, value)| match top {
+                    Some((_, top_value)) if top_value >= value => top,
+                    _ => Some((set, value)),
+                });
+            if let Some((top, value)) = top {
+                if let Some(previous_value) = best.beat(value) {
+                    records.push(Record {
+                        workout_id,
+                        set_id: Some(top.set_id),
+                        record_type,
+                        value,
+                        previous_value,
+                        weight: None,
+                    });
+                }
+            }
+        };
+        set_record(&mut bests.heaviest, HEAVIEST_WEIGHT, &|set| set.load);
+        // Records always use Epley so they don't shift when a user changes formula.
+        set_record(&mut bests.one_rep_max, ESTIMATED_1RM, &|set| {
+            OneRepMaxFormula::Epley.estimate(set.load?, set.reps, None)
+        });
+        set_record(&mut bests.set_volume, SET_VOLUME, &|set| Some(set.load? * set.reps as f64));
+        set_record(&mut bests.duration, LONGEST_DURATION, &|set| set.duration);
+        set_record(&mut bests.distance, LONGEST_DISTANCE, &|set| set.distance);
+
+        let lifts: Vec<&SetRow> = workout_sets.iter().filter(|set| set.load.is_some()).collect();
+        if lifts.is_empty() {
+            continue;
+        }
+
+        let mut most_reps: Vec<(i64, &SetRow)> = Vec::new();
+        for set in lifts.iter().copied() {
+            let key = (set.weight * 1000.0).round() as i64;
+            match most_reps.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, top)) if top.reps >= set.reps => {}
+                Some(entry) => entry.1 = set,
+                None => most_reps.push((key, set)),
+            }
+        }
+        for (key, top) in most_reps {
+            if let Some(previous_value) = bests.reps_at_weight.entry(key).or_default().beat(top.reps as f64) {
+                records.push(Record {
+                    workout_id,
+                    set_id: Some(top.set_id),
+                    record_type: REPS_AT_WEIGHT,
+                    value: top.reps as f64,
+                    previous_value,
+                    weight: Some(top.weight),
+                });
+            }
+        }
+
+        let volume: f64 = lifts.iter().filter_map(|set| Some(set.load? * set.reps as f64)).sum();
+        if let Some(previous_value) = bests.session_volume.beat(volume) {
+            records.push(Record {
+                workout_id,
+                set_id: None,
+                record_type: SESSION_VOLUME,
+                value: volume,
+                previous_value,
+                weight: None,
+            });
+        }
+    }
+
+    let mut insert = conn.prepare(
+        "INSERT INTO personal_records
+             (user_id, exercise_id, workout_id, set_id, record_type, value, previous_value, weight)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+    )?;
+    for record in records {
+        insert.execute(params![
+            user_id,
+            exercise_id,
+            record.workout_id,
+            record.set_id,
+            record.record_type,
+            record.value,
+            record.previous_value,
+            record.weight,
+        ])?;
+    }
+    Ok(())
+}
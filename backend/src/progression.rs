@@ -0,0 +1,438 @@
+use std::collections::HashMap;
+
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::units::WeightUnit;
+use crate::user_time::UserClock;
+
+/// How a template exercise's targets move on after each workout logged from
+/// the template.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct ProgressionRule {
+    pub scheme: ProgressionScheme,
+    /// Weight added after a successful session, in the user's unit.
+    pub increment: f64,
+    /// Failed sessions in a row before the weight is cut back.
+    #[serde(default)]
+    pub deload_after: Option<u32>,
+    /// How much a deload takes off, as a percentage of the weight.
+    #[serde(default = "default_deload_percent")]
+    pub deload_percent: f64,
+}
+
+fn default_deload_percent() -> f64 {
+    10.0
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProgressionScheme {
+    /// Add weight once every set hits its prescribed reps; fewer is a failure.
+    Linear,
+    /// Work up a rep range: add weight once every set reaches the top of its
+    /// range. Only a set below the bottom counts as a failure.
+    DoubleProgression,
+}
+
+impl ProgressionRule {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.increment <= 0.0 {
+            return Err("Progression increment must be positive".to_string());
+        }
+        if self.deload_after == Some(0) {
+            return Err("deload_after must be at least 1".to_string());
+        }
+        if !(self.deload_percent > 0.0 && self.deload_percent < 100.0) {
+            return Err("deload_percent must be between 0 and 100".to_string());
+        }
+        Ok(())
+    }
+
+    /// The rule as stored, with `increment` converted from `unit` to kg.
+    pub fn to_kg(self, unit: WeightUnit) -> ProgressionRule {
+        ProgressionRule { increment: unit.to_kg(self.increment), ..self }
+    }
+
+    /// A stored rule with `increment` shown in `unit`.
+    pub fn in_unit(self, unit: WeightUnit) -> ProgressionRule {
+        ProgressionRule { increment: unit.convert_kg(self.increment), ..self }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Progressed,
+    /// Within a double progression rep range; nothing changes.
+    Held,
+    Failed,
+    Deloaded,
+}
+
+impl Outcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Outcome::Progressed => "progressed",
+            Outcome::Held => "held",
+            Outcome::Failed => "failed",
+            Outcome::Deloaded => "deloaded",
+        }
+    }
+}
+
+/// What a progression change moved, as recorded in `progression_changes`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Changed {
+    TemplateSet,
+    TrainingMax,
+    FailedSessions,
+}
+
+impl Changed {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Changed::TemplateSet => "template_set",
+            Changed::TrainingMax => "training_max",
+            Changed::FailedSessions => "failed_sessions",
+        }
+    }
+
+    fn parse(target: &str) -> Option<Changed> {
+        match target {
+            "template_set" => Some(Changed::TemplateSet),
+            "training_max" => Some(Changed::TrainingMax),
+            "failed_sessions" => Some(Changed::FailedSessions),
+            _ => None,
+        }
+    }
+
+    /// Reads the current value, bound to the target's id as ?1.
+    fn select_sql(&self) -> &'static str {
+        match self {
+            Changed::TemplateSet => "SELECT weight FROM template_sets WHERE id = ?1",
+            Changed::TrainingMax => "SELECT weight FROM training_maxes WHERE exercise_id = ?1",
+            Changed::FailedSessions => "SELECT failed_sessions FROM template_exercises WHERE id = ?1",
+        }
+    }
+
+    /// Sets the value to ?2 on the target with id ?1.
+    fn update_sql(&self) -> &'static str {
+        match self {
+            Changed::TemplateSet => "UPDATE template_sets SET weight = ?2 WHERE id = ?1",
+            Changed::TrainingMax => {
+                "UPDATE training_maxes SET weight = ?2, updated_at = datetime('now') WHERE exercise_id = ?1"
+            }
+            Changed::FailedSessions => "UPDATE template_exercises SET failed_sessions = ?2 WHERE id = ?1",
+        }
+    }
+}
+
+/// A set target as the rule sees it, weight in kg.
+struct Target {
+    id: u32,
+    reps_min: Option<u32>,
+    reps_max: Option<u32>,
+    weight: Option<f64>,
+    percent_training_max: Option<f64>,
+}
+
+/// A working set as logged, weight in kg.
+struct LoggedSet {
+    reps: u32,
+    weight: f64,
+}
+
+/// Judges a session against the targets, before counting failures.
+fn judge(scheme: ProgressionScheme, targets: &[Target], sets: &[LoggedSet]) -> Outcome {
+    let mut at_top = true;
+    for (index, target) in targets.iter().enumerate() {
+        let Some(set) = sets.get(index) else {
+            return Outcome::Failed;
+        };
+        if target.weight.is_some_and(|weight| set.weight + 0.01 < weight) {
+            return Outcome::Failed;
+        }
+        let top = target.reps_max.or(target.reps_min).unwrap_or_default();
+        let bottom = match scheme {
+            ProgressionScheme::Linear => top,
+            ProgressionScheme::DoubleProgression => target.reps_min.unwrap_or(top),
+        };
+        if set.reps < bottom {
+            return Outcome::Failed;
+        }
+        at_top &= set.reps >= top;
+    }
+    if at_top {
+        Outcome::Progressed
+    } else {
+        Outcome::Held
+    }
+}
+
+/// `weight` (kg) cut by `percent` and rounded to a load in the user's unit.
+/// Light weights can round back up to where they started, so the deload
+/// always takes off at least one plate step.
+pub fn deloaded_weight(weight: f64, percent: f64, unit: WeightUnit) -> f64 {
+    let original = unit.convert_kg(weight);
+    let mut reduced = unit.round_to_plates(original * (1.0 - percent / 100.0));
+    if reduced > original - unit.plate_step() {
+        reduced = unit.round_to_plates(original) - unit.plate_step();
+    }
+    unit.to_kg(reduced.max(0.0))
+}
+
+/// Applies the progression rules of the template a workout was logged from:
+/// each exercise done in the workout is judged on its working sets, target
+/// weights (or the training max, for targets taken from it) are moved, and
+/// every outcome, including those that change nothing, is written to
+/// `progression_log`.
+pub fn apply(conn: &Connection, user_id: u32, template_id: u32, workout_id: u32, unit: WeightUnit) -> Result<()> {
+    let mut stmt = conn.prepare(
+        "SELECT te.id, te.exercise_id, te.progression_rule, te.failed_sessions
+         FROM template_exercises te
+         JOIN templates t ON t.id = te.template_id
+         WHERE te.template_id = ?1 AND t.user_id = ?2 AND te.progression_rule IS NOT NULL
+         ORDER BY te.position",
+    )?;
+    let ruled = stmt
+        .query_map(params![template_id, user_id], |row| {
+            Ok((row.get::<_, u32>(0)?, row.get::<_, u32>(1)?, row.get::<_, String>(2)?, row.get::<_, u32>(3)?))
+        })?
+        .collect::<Result<Vec<_>>>()?;
+    if ruled.is_empty() {
+        return Ok(());
+    }
+
+    let mut sets_by_exercise: HashMap<u32, Vec<LoggedSet>> = HashMap::new();
+    let mut sets = conn.prepare(
+        "SELECT we.exercise_id, s.reps, s.weight
+         FROM sets s
+         JOIN workout_exercises we ON s.workout_exercise_id = we.id
+         WHERE we.workout_id = ?1 AND s.set_type != 'warmup'
+         ORDER BY we.id, s.set_number",
+    )?;
+    let rows = sets.query_map(params![workout_id], |row| {
+        Ok((row.get::<_, u32>(0)?, LoggedSet { reps: row.get(1)?, weight: row.get(2)? }))
+    })?;
+    for row in rows {
+        let (exercise_id, set) = row?;
+        sets_by_exercise.entry(exercise_id).or_default().push(set);
+    }
+
+    let mut targets = conn.prepare(
+        "SELECT id, reps_min, reps_max, weight, percent_training_max
+         FROM template_sets WHERE template_exercise_id = ?1 ORDER BY position",
+    )?;
+    for (template_exercise_id, exercise_id, rule, previous_failed_sessions) in ruled {
+        let Ok(rule) = serde_json::from_str::<ProgressionRule>(&rule) else {
+            continue;
+        };
+        let Some(logged) = sets_by_exercise.get(&exercise_id) else {
+            continue;
+        };
+        let exercise_targets = targets
+            .query_map(params![template_exercise_id], |row| {
+                Ok(Target {
+                    id: row.get(0)?,
+                    reps_min: row.get(1)?,
+                    reps_max: row.get(2)?,
+                    weight: row.get(3)?,
+                    percent_training_max: row.get(4)?,
+                })
+            })?
+            .collect::<Result<Vec<_>>>()?;
+        if exercise_targets.is_empty() {
+            continue;
+        }
+
+        let mut outcome = judge(rule.scheme, &exercise_targets, logged);
+        // A held session neither adds to nor clears a run of failures.
+        let mut failed_sessions = match outcome {
+            Outcome::Progressed => 0,
+            Outcome::Held => previous_failed_sessions,
+            Outcome::Failed | Outcome::Deloaded => previous_failed_sessions + 1,
+        };
+        if outcome == Outcome::Failed && rule.deload_after.is_some_and(|after| failed_sessions >= after) {
+            outcome = Outcome::Deloaded;
+            failed_sessions = 0;
+        }
+        let change = |weight: f64| match outcome {
+            Outcome::Progressed => weight + rule.increment,
+            Outcome::Deloaded => deloaded_weight(weight, rule.deload_percent, unit),
+            Outcome::Held | Outcome::Failed => weight,
+        };
+
+        let moves = matches!(outcome, Outcome::Progressed | Outcome::Deloaded);
+        let heaviest = exercise_targets.iter().filter_map(|target| target.weight).reduce(f64::max);
+        for target in exercise_targets.iter().filter(|_| moves) {
+            if let Some(weight) = target.weight {
+                record_change(conn, workout_id, Changed::TemplateSet, target.id, weight, change(weight))?;
+            }
+        }
+        let training_max: Option<f64> = if exercise_targets.iter().any(|target| target.percent_training_max.is_some()) {
+            conn.query_row(
+                "SELECT weight FROM training_maxes WHERE user_id = ?1 AND exercise_id = ?2",
+                params![user_id, exercise_id],
+                |row| row.get(0),
+            )
+            .optional()?
+        } else {
+            None
+        };
+        if let Some(training_max) = training_max.filter(|_| moves) {
+            record_change(conn, workout_id, Changed::TrainingMax, exercise_id, training_max, change(training_max))?;
+        }
+        // Recorded even when unchanged, so every judged exercise leaves a change behind.
+        record_change(
+            conn,
+            workout_id,
+            Changed::FailedSessions,
+            template_exercise_id,
+            f64::from(previous_failed_sessions),
+            f64::from(failed_sessions),
+        )?;
+
+        let before = heaviest.or(training_max);
+        conn.execute(
+            "INSERT INTO progression_log
+                 (user_id, template_id, exercise_id, workout_id, outcome, target, weight_before, weight_after,
+                  failed_sessions, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, datetime('now'))",
+            params![
+                user_id,
+                template_id,
+                exercise_id,
+                workout_id,
+                outcome.as_str(),
+                if heaviest.is_some() { "target_weight" } else { "training_max" },
+                before,
+                before.map(change),
+                failed_sessions,
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+/// Moves a target to `after` and records where it was, so the workout that
+/// moved it can put it back.
+fn record_change(
+    conn: &Connection,
+    workout_id: u32,
+    changed: Changed,
+    target_id: u32,
+    before: f64,
+    after: f64,
+) -> Result<()> {
+    conn.execute(changed.update_sql(), params![target_id, after])?;
+    conn.execute(
+        "INSERT INTO progression_changes (workout_id, target, target_id, value_before, value_after)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![workout_id, changed.as_str(), target_id, before, after],
+    )?;
+    Ok(())
+}
+
+/// Puts back everything progression changed when `workout_id` was saved and
+/// drops its log entries. Nothing is touched, and false returned, if any of
+/// those targets has moved on since (a later session, an edit of the template
+/// or training max) or the workout was judged before changes were recorded.
+pub fn revert(conn: &Connection, workout_id: u32) -> Result<bool> {
+    let mut stmt = conn.prepare(
+        "SELECT target, target_id, value_before, value_after FROM progression_changes WHERE workout_id = ?1",
+    )?;
+    let changes = stmt
+        .query_map(params![workout_id], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, u32>(1)?, row.get::<_, f64>(2)?, row.get::<_, f64>(3)?))
+        })?
+        .collect::<Result<Vec<_>>>()?;
+    let logged: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM progression_log WHERE workout_id = ?1)",
+        params![workout_id],
+        |row| row.get(0),
+    )?;
+    if logged && changes.is_empty() {
+        return Ok(false);
+    }
+
+    let mut reverts = Vec::new();
+    for (target, target_id, before, after) in changes {
+        let Some(changed) = Changed::parse(&target) else {
+            return Ok(false);
+        };
+        let current: Option<f64> =
+            conn.query_row(changed.select_sql(), params![target_id], |row| row.get(0)).optional()?;
+        if current.is_none_or(|current| (current - after).abs() > 1e-9) {
+            return Ok(false);
+        }
+        reverts.push((changed, target_id, before));
+    }
+    for (changed, target_id, before) in reverts {
+        conn.execute(changed.update_sql(), params![target_id, before])?;
+    }
+    conn.execute("DELETE FROM progression_changes WHERE workout_id = ?1", params![workout_id])?;
+    conn.execute("DELETE FROM progression_log WHERE workout_id = ?1", params![workout_id])?;
+    Ok(true)
+}
+
+/// Drops what was recorded for a deleted workout whose changes stay: its log
+/// entries are kept without the workout.
+pub fn forget(conn: &Connection, workout_id: u32) -> Result<()> {
+    conn.execute("DELETE FROM progression_changes WHERE workout_id = ?1", params![workout_id])?;
+    conn.execute("UPDATE progression_log SET workout_id = NULL WHERE workout_id = ?1", params![workout_id])?;
+    Ok(())
+}
+
+/// One session judged by a progression rule, weights in the user's unit.
+#[derive(Serialize, Debug)]
+pub struct ProgressionLogEntry {
+    pub id: u32,
+    pub workout_id: Option<u32>,
+    pub exercise_id: u32,
+    pub exercise_name: String,
+    pub outcome: String,
+    /// `target_weight` or `training_max`, whichever was changed.
+    pub target: String,
+    pub weight_before: Option<f64>,
+    pub weight_after: Option<f64>,
+    /// Failed sessions in a row after this one.
+    pub failed_sessions: u32,
+    /// RFC 3339, in the user's timezone.
+    pub created_at: String,
+    pub unit: WeightUnit,
+}
+
+/// Progression outcomes for one of the user's templates, newest first.
+pub fn log(
+    conn: &Connection,
+    user_id: u32,
+    template_id: u32,
+    unit: WeightUnit,
+    clock: &UserClock,
+) -> Result<Vec<ProgressionLogEntry>> {
+    let mut stmt = conn.prepare(
+        "SELECT pl.id, pl.workout_id, pl.exercise_id, ue.name, pl.outcome, pl.target, pl.weight_before,
+                pl.weight_after, pl.failed_sessions, pl.created_at
+         FROM progression_log pl
+         JOIN user_exercises ue ON ue.id = pl.exercise_id
+         WHERE pl.user_id = ?1 AND pl.template_id = ?2
+         ORDER BY pl.id DESC",
+    )?;
+    let rows = stmt.query_map(params![user_id, template_id], |row| {
+        let created_at: String = row.get(9)?;
+        Ok(ProgressionLogEntry {
+            id: row.get(0)?,
+            workout_id: row.get(1)?,
+            exercise_id: row.get(2)?,
+            exercise_name: row.get(3)?,
+            outcome: row.get(4)?,
+            target: row.get(5)?,
+            weight_before: row.get::<_, Option<f64>>(6)?.map(|weight| unit.convert_kg(weight)),
+            weight_after: row.get::<_, Option<f64>>(7)?.map(|weight| unit.convert_kg(weight)),
+            failed_sessions: row.get(8)?,
+            created_at: clock.format_rfc3339(&created_at).unwrap_or(created_at),
+            unit,
+        })
+    })?;
+    rows.collect()
+}
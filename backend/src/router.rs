@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+
+use crate::http::{Request, Response};
+
+pub type Handler<S> = fn(&Request, &S) -> Response;
+
+enum Segment {
+    Literal(&'static str),
+    Param(&'static str),
+}
+
+struct Route<S> {
+    method: &'static str,
+    segments: Vec<Segment>,
+    handler: Handler<S>,
+}
+
+impl<S> Route<S> {
+    fn matches(&self, path_segments: &[&str]) -> Option<HashMap<String, String>> {
+        if self.segments.len() != path_segments.len() {
+            return None;
+        }
+
+        let mut params = HashMap::new();
+        for (segment, value) in self.segments.iter().zip(path_segments) {
+            match segment {
+                Segment::Literal(literal) if literal == value => {}
+                Segment::Param(name) if !value.is_empty() => {
+                    params.insert(name.to_string(), value.to_string());
+                }
+                _ => return None,
+            }
+        }
+        Some(params)
+    }
+}
+
+/// Dispatches requests on method and path pattern.
+///
+/// Patterns are `/`-separated; a segment written as `{name}` matches any single
+/// non-empty segment and is made available through `Request::param`.
+pub struct Router<S> {
+    routes: Vec<Route<S>>,
+}
+
+impl<S> Default for Router<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Router<S> {
+    pub fn new() -> Self {
+        Router { routes: Vec::new() }
+    }
+
+    pub fn route(mut self, method: &'static str, pattern: &'static str, handler: Handler<S>) -> Self {
+        let segments = split_path(pattern)
+            .into_iter()
+            .map(|segment| {
+                match segment.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+                    Some(name) => Segment::Param(name),
+                    None => Segment::Literal(segment),
+                }
+            })
+            .collect();
+
+        self.routes.push(Route { method, segments, handler });
+        self
+    }
+
+    pub fn get(self, pattern: &'static str, handler: Handler<S>) -> Self {
+        self.route("GET", pattern, handler)
+    }
+
+    pub fn post(self, pattern: &'static str, handler: Handler<S>) -> Self {
+        self.route("POST", pattern, handler)
+    }
+
+    pub fn put(self, pattern: &'static str, handler: Handler<S>) -> Self {
+        self.route("PUT", pattern, handler)
+    }
+
+    pub fn delete(self, pattern: &'static str, handler: Handler<S>) -> Self {
+        self.route("DELETE", pattern, handler)
+    }
+
+    pub fn dispatch(&self, request: &mut Request, state: &S) -> Response {
+        let path_segments = split_path(&request.path);
+
+        let mut allowed: Vec<&'static str> = Vec::new();
+        for route in &self.routes {
+            let Some(params) = route.matches(&path_segments) else {
+                continue;
+            };
+            if route.method == request.method {
+                request.params = params;
+                return (route.handler)(request, state);
+            }
+            if !allowed.contains(&route.method) {
+                allowed.push(route.method);
+            }
+        }
+
+        if allowed.is_empty() {
+            return Response::new(
+                "HTTP/1.1 404 NOT FOUND",
+                "<html><body>404 NOT FOUND</body></html>",
+                "text/html",
+            );
+        }
+
+        allowed.push("OPTIONS");
+        let allow = allowed.join(", ");
+
+        if request.method == "OPTIONS" {
+            let allow_headers = request
+                .header("Access-Control-Request-Headers")
+                .unwrap_or("Content-Type")
+                .to_string();
+            return Response::new("HTTP/1.1 204 NO CONTENT", "", "text/plain")
+                .with_header("Allow", allow.clone())
+                .with_header("Access-Control-Allow-Methods", allow)
+                .with_header("Access-Control-Allow-Headers", allow_headers)
+                .with_header("Access-Control-Max-Age", "86400");
+        }
+
+        Response::new(
+            "HTTP/1.1 405 METHOD NOT ALLOWED",
+            format!(r#"{{"error": "Method {} not allowed"}}"#, request.method),
+            "application/json",
+        )
+        .with_header("Allow", allow)
+    }
+}
+
+fn split_path(path: &str) -> Vec<&str> {
+    path.trim_matches('/').split('/').collect()
+}
@@ -1,11 +1,11 @@
 use std::{
-    collections::HashMap, fs::File, io::prelude::*, path::Path
+    collections::HashMap, fs::{self, File}, io::prelude::*, path::Path
 };
-use chrono::Utc;
+use chrono::{NaiveDate, Utc};
 use rand::Rng;
 use rusqlite::{params, OptionalExtension};
 use serde_json::json;
-use crate::{database_handler::{self, DatabaseHandler, ExerciseRequest, TemplateRequest}, tracker::{self, edit, extract_meta_data, Metadata}, wt_types::*};
+use crate::{database_handler::{self, DatabaseHandler, ExerciseRequest, HistoryCursor, HistoryQuery, TemplateCopy, TemplateExerciseRequest, TemplateRequest, TemplateUpdate}, http::Response, one_rep_max::OneRepMaxFormula, time_buckets::{Bucket, TimeRange}, tracker::{self, edit, extract_meta_data, Metadata}, user_time::{self, UserClock}, wt_types::*};
 
 fn convert_db_exercise(db_ex: &database_handler::Exercise) -> Exercise {
     let best_set_string = db_ex.best_set.clone().unwrap_or("".to_string());
@@ -13,49 +13,61 @@ fn convert_db_exercise(db_ex: &database_handler::Exercise) -> Exercise {
         id: db_ex.id,
         name: db_ex.name.clone(),
         body_part: db_ex.muscle_group.clone(),
-        best_set: best_set_string
+        description: db_ex.description.clone(),
+        best_set: best_set_string,
+        kind: db_ex.kind,
+        archived: db_ex.archived,
     }
 }
 
+/// e1RM chart for one exercise. Accepts `from`, `to` and `bucket`, defaulting
+/// to the last eight months. The formula comes from the `formula` query
+/// parameter, falling back to the user's saved preference.
 pub fn handle_one_rep_max_route(
     query_params: HashMap<String, String>,
     db_handler: &DatabaseHandler,
 ) -> (&'static str, String, &'static str) {
-    match (
-        query_params.get("userid"),
-        query_params.get("exercise_id").and_then(|s| s.parse::<u32>().ok()),
-    ) {
-        (Some(userid), Some(exercise_id)) => {
-            match db_handler.get_user_id_from_token(&userid) {
-                Ok(parsed_userid) => match db_handler.get_1_rep_maxes(parsed_userid, exercise_id) {
-                    Ok(one_rep_max_data) => {
-                        let json_contents = serde_json::to_string_pretty(&one_rep_max_data).unwrap();
-                        ("HTTP/1.1 200 OK", json_contents, "application/json")
-                    }
-                    Err(err) => {
-                        println!("Error fetching 1 rep max data: {}", err);
-                        (
-                            "HTTP/1.1 500 INTERNAL SERVER ERROR",
-                            format!(r#"{{"error": "{}"}}"#, err),
-                            "application/json",
-                        )
-                    }
-                },
-                Err(err) => {
-                    println!("Error getting user ID from token: {}", err);
-                    (
-                        "HTTP/1.1 401 UNAUTHORIZED",
-                        format!(r#"{{"error": "Invalid token: {}"}}"#, err),
-                        "application/json",
-                    )
-                }
+    let user_id = match authenticate(&query_params, db_handler) {
+        Ok(user_id) => user_id,
+        Err(response) => return response,
+    };
+    let Some(exercise_id) = query_params.get("exercise_id").and_then(|s| s.parse::<u32>().ok()) else {
+        return json_error("HTTP/1.1 400 BAD REQUEST", "Invalid or missing userid or exercise_id");
+    };
+
+    let formula = match query_params.get("formula") {
+        Some(formula) => match formula.parse::<OneRepMaxFormula>() {
+            Ok(formula) => formula,
+            Err(err) => return json_error("HTTP/1.1 400 BAD REQUEST", &err),
+        },
+        None => match db_handler.get_preferences(user_id) {
+            Ok(preferences) => preferences.one_rep_max_formula,
+            Err(err) => {
+                println!("Error fetching preferences: {}", err);
+                return json_error("HTTP/1.1 500 INTERNAL SERVER ERROR", &err.to_string());
             }
+        },
+    };
+
+    let today = match db_handler.user_clock(user_id) {
+        Ok(clock) => clock.today(),
+        Err(err) => return json_error("HTTP/1.1 500 INTERNAL SERVER ERROR", &err.to_string()),
+    };
+    let default_range = TimeRange::last(8, Bucket::Month, today);
+    let range = match TimeRange::from_query(&query_params, default_range) {
+        Ok(range) => range,
+        Err(err) => return json_error("HTTP/1.1 400 BAD REQUEST", &err),
+    };
+
+    match db_handler.get_1_rep_maxes(user_id, exercise_id, formula, &range) {
+        Ok(one_rep_max_data) => {
+            let json_contents = serde_json::to_string_pretty(&one_rep_max_data).unwrap();
+            ("HTTP/1.1 200 OK", json_contents, "application/json")
+        }
+        Err(err) => {
+            println!("Error fetching 1 rep max data: {}", err);
+            json_error("HTTP/1.1 500 INTERNAL SERVER ERROR", &err.to_string())
         }
-        _ => (
-            "HTTP/1.1 400 BAD REQUEST",
-            r#"{"error": "Invalid or missing userid or exercise_id"}"#.to_string(),
-            "application/json",
-        ),
     }
 }
 
@@ -101,37 +113,32 @@ pub fn handle_previous_sets_route(
     }
 }
 
+/// Workout counts per bucket. Accepts `from`, `to` and `bucket`; defaults to
+/// the last eight weeks.
 pub fn handle_workouts_per_week_route(
     query_params: HashMap<String, String>,
     db_handler: &DatabaseHandler,
 ) -> (&'static str, String, &'static str) {
-    match query_params.get("userid") {
-        Some(userid) => match db_handler.get_user_id_from_token(&userid) {
-            Ok(parsed_userid) => match db_handler.get_workouts_per_week(parsed_userid) {
-                Ok(history_data) => {
-                    let json_contents = serde_json::to_string_pretty(&history_data).unwrap();
-                    ("HTTP/1.1 200 OK", json_contents, "application/json")
-                }
-                Err(err) => (
-                    "HTTP/1.1 500 INTERNAL SERVER ERROR",
-                    format!(r#"{{"error": "{}"}}"#, err),
-                    "application/json",
-                ),
-            },
-            Err(err) => {
-                println!("Error getting user ID from token: {}", err);
-                (
-                    "HTTP/1.1 401 UNAUTHORIZED",
-                    format!(r#"{{"error": "Invalid token: {}"}}"#, err),
-                    "application/json",
-                )
-            }
-        },
-        None => (
-            "HTTP/1.1 400 BAD REQUEST",
-            r#"{"error": "Invalid or missing userid"}"#.to_string(),
-            "application/json",
-        ),
+    let user_id = match authenticate(&query_params, db_handler) {
+        Ok(user_id) => user_id,
+        Err(response) => return response,
+    };
+    let today = match db_handler.user_clock(user_id) {
+        Ok(clock) => clock.today(),
+        Err(err) => return json_error("HTTP/1.1 500 INTERNAL SERVER ERROR", &err.to_string()),
+    };
+    let default_range = TimeRange::last(8, Bucket::Week, today);
+    let range = match TimeRange::from_query(&query_params, default_range) {
+        Ok(range) => range,
+        Err(err) => return json_error("HTTP/1.1 400 BAD REQUEST", &err),
+    };
+
+    match db_handler.get_workouts_per_week(user_id, &range) {
+        Ok(history_data) => {
+            let json_contents = serde_json::to_string_pretty(&history_data).unwrap();
+            ("HTTP/1.1 200 OK", json_contents, "application/json")
+        }
+        Err(err) => json_error("HTTP/1.1 500 INTERNAL SERVER ERROR", &err.to_string()),
     }
 }
 
@@ -183,6 +190,14 @@ pub fn handle_add_exercise_route<R: BufRead>(
                     "application/json",
                 );
             }
+            match db_handler.exercise_name_taken(parsed_userid, &exercise_req.name, None) {
+                Ok(false) => {}
+                Ok(true) => return duplicate_exercise_name(),
+                Err(err) => {
+                    println!("Error checking exercise name: {}", err);
+                    return json_error("HTTP/1.1 500 INTERNAL SERVER ERROR", "Failed to add exercise");
+                }
+            }
             match db_handler.add_exercise_to_user(exercise_req) {
                 Ok(exercise_id) => (
                     "HTTP/1.1 201 CREATED",
@@ -210,50 +225,237 @@ pub fn handle_add_exercise_route<R: BufRead>(
     }
 }
 
-pub fn handle_history_route(
+fn duplicate_exercise_name() -> RouteResult {
+    json_error("HTTP/1.1 409 CONFLICT", "An exercise with that name already exists")
+}
+
+fn parse_exercise_id(exercise_id: &str) -> Result<u32, RouteResult> {
+    exercise_id
+        .parse()
+        .map_err(|_| json_error("HTTP/1.1 400 BAD REQUEST", "Invalid exercise id"))
+}
+
+fn exercise_not_found() -> RouteResult {
+    json_error("HTTP/1.1 404 NOT FOUND", "Exercise not found")
+}
+
+/// Renames an exercise, changes its muscle group or description, or archives
+/// it. Responds with the exercise as `/exercises` lists it.
+pub fn handle_update_exercise_route<R: BufRead>(
+    buf_reader: R,
     query_params: HashMap<String, String>,
+    exercise_id: &str,
     db_handler: &DatabaseHandler,
-) -> (&'static str, String, &'static str) {
-    match query_params.get("userid") {
-        Some(userid) => match db_handler.get_user_id_from_token(&userid) {
-            Ok(parsed_userid) => match db_handler.get_history_data(parsed_userid) {
-                Ok(history_data) => {
-                    let json_contents = serde_json::to_string_pretty(&history_data).unwrap();
-                    ("HTTP/1.1 200 OK", json_contents, "application/json")
-                }
-                Err(err) => {
-                    println!("Error fetching history data: {}", err);
-                    (
-                        "HTTP/1.1 500 INTERNAL SERVER ERROR",
-                        format!(r#"{{"error": "{}"}}"#, err),
-                        "application/json",
-                    )
-                },
-            },
+    content_length: usize,
+) -> RouteResult {
+    let (user_id, exercise_id) = match (authenticate(&query_params, db_handler), parse_exercise_id(exercise_id)) {
+        (Ok(user_id), Ok(exercise_id)) => (user_id, exercise_id),
+        (Err(response), _) | (_, Err(response)) => return response,
+    };
+
+    let mut body = String::new();
+    if let Err(err) = buf_reader.take(content_length as u64).read_to_string(&mut body) {
+        println!("Error reading request body: {}", err);
+        return json_error("HTTP/1.1 400 BAD REQUEST", "Failed to read request body");
+    }
+    let update: ExerciseUpdate = match serde_json::from_str(body.trim()) {
+        Ok(update) => update,
+        Err(err) => {
+            println!("Error deserializing JSON: {}", err);
+            return json_error("HTTP/1.1 400 BAD REQUEST", "Invalid JSON format");
+        }
+    };
+
+    if [&update.name, &update.body_part].into_iter().flatten().any(|value| value.trim().is_empty()) {
+        return json_error("HTTP/1.1 400 BAD REQUEST", "Name and body_part can't be empty");
+    }
+    if let Some(name) = &update.name {
+        match db_handler.exercise_name_taken(user_id, name, Some(exercise_id)) {
+            Ok(false) => {}
+            Ok(true) => return duplicate_exercise_name(),
             Err(err) => {
-                println!("Error getting user ID from token: {}", err);
-                (
-                    "HTTP/1.1 401 UNAUTHORIZED",
-                    format!(r#"{{"error": "Invalid token: {}"}}"#, err),
+                println!("Error checking exercise name: {}", err);
+                return json_error("HTTP/1.1 500 INTERNAL SERVER ERROR", "Failed to update exercise");
+            }
+        }
+    }
+
+    match db_handler.update_exercise(user_id, exercise_id, &update) {
+        Ok(true) => match db_handler.get_user_exercises(user_id, true) {
+            Ok(exercises) => match exercises.iter().find(|exercise| exercise.id == exercise_id) {
+                Some(exercise) => (
+                    "HTTP/1.1 200 OK",
+                    serde_json::to_string_pretty(&convert_db_exercise(exercise)).unwrap(),
                     "application/json",
-                )
+                ),
+                None => exercise_not_found(),
+            },
+            Err(err) => {
+                println!("Error fetching exercises: {}", err);
+                json_error("HTTP/1.1 500 INTERNAL SERVER ERROR", "Failed to fetch exercise")
             }
         },
-        None => (
-            "HTTP/1.1 400 BAD REQUEST",
-            r#"{"error": "Invalid or missing userid"}"#.to_string(),
+        Ok(false) => exercise_not_found(),
+        Err(err) => {
+            println!("Error updating exercise: {}", err);
+            json_error("HTTP/1.1 500 INTERNAL SERVER ERROR", "Failed to update exercise")
+        }
+    }
+}
+
+/// Merges the exercise in the path into another of the user's exercises of
+/// the same kind, moving its whole history and template entries across.
+pub fn handle_merge_exercise_route<R: BufRead>(
+    buf_reader: R,
+    query_params: HashMap<String, String>,
+    exercise_id: &str,
+    db_handler: &DatabaseHandler,
+    content_length: usize,
+) -> RouteResult {
+    let (user_id, source) = match (authenticate(&query_params, db_handler), parse_exercise_id(exercise_id)) {
+        (Ok(user_id), Ok(exercise_id)) => (user_id, exercise_id),
+        (Err(response), _) | (_, Err(response)) => return response,
+    };
+
+    let mut body = String::new();
+    if let Err(err) = buf_reader.take(content_length as u64).read_to_string(&mut body) {
+        println!("Error reading request body: {}", err);
+        return json_error("HTTP/1.1 400 BAD REQUEST", "Failed to read request body");
+    }
+    let merge: ExerciseMerge = match serde_json::from_str(body.trim()) {
+        Ok(merge) => merge,
+        Err(err) => {
+            println!("Error deserializing JSON: {}", err);
+            return json_error("HTTP/1.1 400 BAD REQUEST", "Invalid JSON format");
+        }
+    };
+    if merge.into == source {
+        return json_error("HTTP/1.1 400 BAD REQUEST", "Can't merge an exercise into itself");
+    }
+
+    match db_handler.exercise_kinds(user_id, &[source, merge.into]) {
+        Ok(kinds) => match (kinds.get(&source), kinds.get(&merge.into)) {
+            (Some(from), Some(into)) if from != into => {
+                return json_error(
+                    "HTTP/1.1 400 BAD REQUEST",
+                    &format!("Can't merge a {} exercise into a {} one", from, into),
+                );
+            }
+            (Some(_), Some(_)) => {}
+            _ => return exercise_not_found(),
+        },
+        Err(err) => {
+            println!("Error reading exercise kinds: {}", err);
+            return json_error("HTTP/1.1 500 INTERNAL SERVER ERROR", "Failed to merge exercises");
+        }
+    }
+
+    match db_handler.merge_exercises(user_id, source, merge.into) {
+        Ok(true) => (
+            "HTTP/1.1 200 OK",
+            json!({ "exercise_id": merge.into, "merged": source, "success": true }).to_string(),
             "application/json",
         ),
+        Ok(false) => exercise_not_found(),
+        Err(err) => {
+            println!("Error merging exercises: {}", err);
+            json_error("HTTP/1.1 500 INTERNAL SERVER ERROR", "Failed to merge exercises")
+        }
+    }
+}
+
+const DEFAULT_HISTORY_PAGE: u32 = 20;
+const MAX_HISTORY_PAGE: u32 = 100;
+
+/// Serves one page of workout history.
+///
+/// Accepts `limit`, `cursor`, `from` / `to` (inclusive `YYYY-MM-DD` dates),
+/// `exercise_id` and `muscle_group`. The body stays a plain array; when more
+/// workouts remain, the cursor for the next page is sent in `X-Next-Cursor`.
+/// Without `limit` or `cursor` every workout is returned, as clients written
+/// before paging expect.
+pub fn handle_history_route(
+    query_params: HashMap<String, String>,
+    db_handler: &DatabaseHandler,
+) -> Response {
+    let user_id = match authenticate(&query_params, db_handler) {
+        Ok(user_id) => user_id,
+        Err(response) => return response.into(),
+    };
+
+    let history_query = match parse_history_query(&query_params) {
+        Ok(history_query) => history_query,
+        Err(message) => return json_error("HTTP/1.1 400 BAD REQUEST", message).into(),
+    };
+
+    match db_handler.get_history_data(user_id, &history_query) {
+        Ok(page) => {
+            let json_contents = serde_json::to_string_pretty(&page.workouts).unwrap();
+            let response = Response::new("HTTP/1.1 200 OK", json_contents, "application/json")
+                .with_header("Access-Control-Expose-Headers", "X-Next-Cursor");
+            match page.next_cursor {
+                Some(cursor) => response.with_header("X-Next-Cursor", cursor.encode()),
+                None => response,
+            }
+        }
+        Err(err) => {
+            println!("Error fetching history data: {}", err);
+            json_error("HTTP/1.1 500 INTERNAL SERVER ERROR", &err.to_string()).into()
+        }
     }
 }
 
+fn parse_history_query(query_params: &HashMap<String, String>) -> Result<HistoryQuery, &'static str> {
+    let cursor = match query_params.get("cursor").filter(|c| !c.is_empty()) {
+        Some(cursor) => Some(HistoryCursor::decode(cursor).ok_or("Invalid cursor")?),
+        None => None,
+    };
+
+    let limit = match query_params.get("limit") {
+        Some(limit) => match limit.parse::<u32>() {
+            Ok(limit) if (1..=MAX_HISTORY_PAGE).contains(&limit) => Some(limit),
+            _ => return Err("limit must be between 1 and 100"),
+        },
+        None if cursor.is_some() => Some(DEFAULT_HISTORY_PAGE),
+        None => None,
+    };
+
+    let parse_date = |name: &str| -> Result<Option<NaiveDate>, &'static str> {
+        match query_params.get(name).filter(|d| !d.is_empty()) {
+            Some(date) => NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .map(Some)
+                .map_err(|_| "Dates must be formatted as YYYY-MM-DD"),
+            None => Ok(None),
+        }
+    };
+    let from = parse_date("from")?;
+    let to = parse_date("to")?;
+
+    let exercise_id = match query_params.get("exercise_id") {
+        Some(id) => Some(id.parse::<u32>().map_err(|_| "Invalid exercise_id")?),
+        None => None,
+    };
+
+    Ok(HistoryQuery {
+        limit,
+        cursor,
+        from,
+        to,
+        exercise_id,
+        muscle_group: query_params.get("muscle_group").filter(|m| !m.is_empty()).cloned(),
+    })
+}
+
 pub fn handle_exercises_route(
     query_params: HashMap<String, String>,
     db_handler: &DatabaseHandler,
 ) -> (&'static str, String, &'static str) {
     match query_params.get("userid") {
         Some(userid) => match db_handler.get_user_id_from_token(&userid) {
-            Ok(parsed_userid) => match db_handler.get_user_exercises(parsed_userid) {
+            Ok(parsed_userid) => match db_handler.get_user_exercises(
+                parsed_userid,
+                query_params.get("include_archived").is_some_and(|v| v == "true"),
+            ) {
                 Ok(db_exercises) => {
                     let exercises: Vec<Exercise> = db_exercises.iter().map(convert_db_exercise).collect();
                     let json_contents = serde_json::to_string_pretty(&exercises).unwrap();
@@ -288,32 +490,80 @@ pub fn handle_exercises_route(
 pub fn handle_workout_route<R: BufRead>(
     buf_reader: R,
     query_params: HashMap<String, String>,
+    idempotency_key: Option<&str>,
     db_handler: &DatabaseHandler,
     content_length: usize,
 ) -> (&'static str, String, &'static str) {
     match query_params.get("userid") {
-        Some(userid) => match db_handler.get_user_id_from_token(&userid) {
+        Some(userid) => match db_handler.get_user_id_from_token(userid) {
             Ok(parsed_userid) => {
                 let mut body = String::new();
                 if content_length > 0 {
                     let mut body_reader = buf_reader.take(content_length as u64);
-                    body_reader.read_to_string(&mut body).unwrap();
+                    if let Err(err) = body_reader.read_to_string(&mut body) {
+                        println!("Error reading request body: {}", err);
+                        return (
+                            "HTTP/1.1 400 BAD REQUEST",
+                            r#"{"error": "Failed to read request body", "success": false}"#.to_string(),
+                            "application/json",
+                        );
+                    }
                 }
-                let workout: Workout = serde_json::from_str(body.trim()).unwrap();
+                let mut workout: Workout = match serde_json::from_str(body.trim()) {
+                    Ok(workout) => workout,
+                    Err(err) => {
+                        println!("Error deserializing JSON: {}", err);
+                        return (
+                            "HTTP/1.1 400 BAD REQUEST",
+                            r#"{"error": "Invalid JSON format", "success": false}"#.to_string(),
+                            "application/json",
+                        );
+                    }
+                };
+
+                let clock = match db_handler.user_clock(parsed_userid) {
+                    Ok(clock) => clock,
+                    Err(err) => return json_error("HTTP/1.1 500 INTERNAL SERVER ERROR", &err.to_string()),
+                };
+                for time in [&mut workout.start_time, &mut workout.end_time] {
+                    match normalise_time(&clock, time) {
+                        Ok(normalised) => *time = normalised,
+                        Err(response) => return response,
+                    }
+                }
+
+                if let Err(response) = validate_exercises(db_handler, parsed_userid, &workout.exercises) {
+                    return response;
+                }
+
+                // The header wins over an id in the body so generic retry middleware works.
+                if let Some(key) = idempotency_key.filter(|key| !key.is_empty()) {
+                    workout.client_id = Some(key.to_string());
+                }
+
                 match db_handler.save_workout(workout, parsed_userid) {
-                    Ok(result) => {
-                        println!("Saved {} sets", result);
+                    Ok(saved) => {
+                        println!("Saved {} sets (replayed: {})", saved.sets_saved, saved.replayed);
                         (
                             "HTTP/1.1 200 OK",
-                            format!(r#"{{ "user_id": {}, "success": true }}"#, parsed_userid),
+                            json!({
+                                "user_id": parsed_userid,
+                                "success": true,
+                                "workout_id": saved.workout_id,
+                                "replayed": saved.replayed,
+                            })
+                            .to_string(),
+                            "application/json",
+                        )
+                    }
+                    Err(err) => {
+                        println!("Error saving workout: {}", err);
+                        (
+                            "HTTP/1.1 500 INTERNAL SERVER ERROR",
+                            r#"{"error": "Failed to save workout"}"#.to_string(),
                             "application/json",
                         )
                     }
-                    Err(_) => (
-                        "HTTP/1.1 500 INTERNAL SERVER ERROR",
-                        r#"{"error": "Failed to save workout"}"#.to_string(),
-                        "application/json",
-                    ),
                 }
             }
             Err(err) => {
@@ -333,10 +583,261 @@ pub fn handle_workout_route<R: BufRead>(
     }
 }
 
+type RouteResult = (&'static str, String, &'static str);
+
+fn json_error(status: &'static str, message: &str) -> RouteResult {
+    (
+        status,
+        json!({ "error": message, "success": false }).to_string(),
+        "application/json",
+    )
+}
+
+/// Resolves the `userid` session token, or produces the response to send back.
+fn authenticate(query_params: &HashMap<String, String>, db_handler: &DatabaseHandler) -> Result<u32, RouteResult> {
+    let Some(token) = query_params.get("userid") else {
+        return Err(json_error("HTTP/1.1 400 BAD REQUEST", "Invalid or missing userid"));
+    };
+    db_handler.get_user_id_from_token(token).map_err(|err| {
+        println!("Error getting user ID from token: {}", err);
+        json_error("HTTP/1.1 401 UNAUTHORIZED", &format!("Invalid token: {}", err))
+    })
+}
+
+/// Checks sets and groups, that every exercise is the user's and that each
+/// set fits its exercise's kind.
+fn validate_exercises(db_handler: &DatabaseHandler, user_id: u32, exercises: &[ExerciseRecord]) -> Result<(), RouteResult> {
+    let bad_request = |err: String| json_error("HTTP/1.1 400 BAD REQUEST", &err);
+    exercises
+        .iter()
+        .flat_map(|exercise| &exercise.sets)
+        .try_for_each(Set::validate)
+        .map_err(bad_request)?;
+    validate_groups(&exercises.iter().map(|exercise| exercise.group).collect::<Vec<_>>()).map_err(bad_request)?;
+
+    let exercise_ids: Vec<u32> = exercises.iter().map(|exercise| exercise.exercise_id).collect();
+    match db_handler.owns_exercises(user_id, &exercise_ids) {
+        Ok(true) => {}
+        Ok(false) => return Err(json_error("HTTP/1.1 400 BAD REQUEST", "Unknown exercise")),
+        Err(err) => {
+            println!("Error checking exercises: {}", err);
+            return Err(json_error("HTTP/1.1 500 INTERNAL SERVER ERROR", "Failed to read exercises"));
+        }
+    }
+    let kinds = db_handler.exercise_kinds(user_id, &exercise_ids).map_err(|err| {
+        println!("Error reading exercise kinds: {}", err);
+        json_error("HTTP/1.1 500 INTERNAL SERVER ERROR", "Failed to read exercises")
+    })?;
+    for exercise in exercises {
+        if let Some(kind) = kinds.get(&exercise.exercise_id) {
+            exercise.sets.iter().try_for_each(|set| kind.validate(set)).map_err(bad_request)?;
+        }
+    }
+    Ok(())
+}
+
+/// Converts a time sent by the user's device to the UTC form it is stored in.
+fn normalise_time(clock: &UserClock, time: &str) -> Result<String, RouteResult> {
+    clock.parse_client_time(time).map(user_time::to_storage).ok_or_else(|| {
+        json_error(
+            "HTTP/1.1 400 BAD REQUEST",
+            &format!("Invalid time {}, expected RFC 3339 or YYYY-MM-DD HH:MM:SS", time),
+        )
+    })
+}
+
+fn parse_workout_id(workout_id: &str) -> Result<u32, RouteResult> {
+    workout_id
+        .parse()
+        .map_err(|_| json_error("HTTP/1.1 400 BAD REQUEST", "Invalid workout id"))
+}
+
+fn workout_not_found() -> RouteResult {
+    json_error("HTTP/1.1 404 NOT FOUND", "Workout not found")
+}
+
+pub fn handle_get_workout_route(
+    query_params: HashMap<String, String>,
+    workout_id: &str,
+    db_handler: &DatabaseHandler,
+) -> RouteResult {
+    let (user_id, workout_id) = match (authenticate(&query_params, db_handler), parse_workout_id(workout_id)) {
+        (Ok(user_id), Ok(workout_id)) => (user_id, workout_id),
+        (Err(response), _) | (_, Err(response)) => return response,
+    };
+
+    match db_handler.get_workout(user_id, workout_id) {
+        Ok(Some(workout)) => (
+            "HTTP/1.1 200 OK",
+            serde_json::to_string_pretty(&workout).unwrap(),
+            "application/json",
+        ),
+        Ok(None) => workout_not_found(),
+        Err(err) => {
+            println!("Error fetching workout: {}", err);
+            json_error("HTTP/1.1 500 INTERNAL SERVER ERROR", "Failed to fetch workout")
+        }
+    }
+}
+
+pub fn handle_update_workout_route<R: BufRead>(
+    buf_reader: R,
+    query_params: HashMap<String, String>,
+    workout_id: &str,
+    db_handler: &DatabaseHandler,
+    content_length: usize,
+) -> RouteResult {
+    let (user_id, workout_id) = match (authenticate(&query_params, db_handler), parse_workout_id(workout_id)) {
+        (Ok(user_id), Ok(workout_id)) => (user_id, workout_id),
+        (Err(response), _) | (_, Err(response)) => return response,
+    };
+
+    let mut body = String::new();
+    if let Err(err) = buf_reader.take(content_length as u64).read_to_string(&mut body) {
+        println!("Error reading request body: {}", err);
+        return json_error("HTTP/1.1 400 BAD REQUEST", "Failed to read request body");
+    }
+    let mut update: WorkoutUpdate = match serde_json::from_str(body.trim()) {
+        Ok(update) => update,
+        Err(err) => {
+            println!("Error deserializing JSON: {}", err);
+            return json_error("HTTP/1.1 400 BAD REQUEST", "Invalid JSON format");
+        }
+    };
+
+    let clock = match db_handler.user_clock(user_id) {
+        Ok(clock) => clock,
+        Err(err) => return json_error("HTTP/1.1 500 INTERNAL SERVER ERROR", &err.to_string()),
+    };
+    for time in [&mut update.start_time, &mut update.end_time].into_iter().flatten() {
+        match normalise_time(&clock, time) {
+            Ok(normalised) => *time = normalised,
+            Err(response) => return response,
+        }
+    }
+
+    if let Some(exercises) = &update.exercises {
+        if let Err(response) = validate_exercises(db_handler, user_id, exercises) {
+            return response;
+        }
+    }
+
+    match db_handler.update_workout(user_id, workout_id, &update) {
+        Ok(true) => match db_handler.get_workout(user_id, workout_id) {
+            Ok(Some(workout)) => (
+                "HTTP/1.1 200 OK",
+                serde_json::to_string_pretty(&workout).unwrap(),
+                "application/json",
+            ),
+            Ok(None) => workout_not_found(),
+            Err(err) => {
+                println!("Error fetching workout: {}", err);
+                json_error("HTTP/1.1 500 INTERNAL SERVER ERROR", "Failed to fetch workout")
+            }
+        },
+        Ok(false) => workout_not_found(),
+        Err(err) => {
+            println!("Error updating workout: {}", err);
+            json_error("HTTP/1.1 500 INTERNAL SERVER ERROR", "Failed to update workout")
+        }
+    }
+}
+
+pub fn handle_delete_workout_route(
+    query_params: HashMap<String, String>,
+    workout_id: &str,
+    db_handler: &DatabaseHandler,
+) -> RouteResult {
+    let (user_id, workout_id) = match (authenticate(&query_params, db_handler), parse_workout_id(workout_id)) {
+        (Ok(user_id), Ok(workout_id)) => (user_id, workout_id),
+        (Err(response), _) | (_, Err(response)) => return response,
+    };
+
+    match db_handler.delete_workout(user_id, workout_id) {
+        Ok(true) => (
+            "HTTP/1.1 200 OK",
+            json!({ "workout_id": workout_id, "success": true }).to_string(),
+            "application/json",
+        ),
+        Ok(false) => workout_not_found(),
+        Err(err) => {
+            println!("Error deleting workout: {}", err);
+            json_error("HTTP/1.1 500 INTERNAL SERVER ERROR", "Failed to delete workout")
+        }
+    }
+}
+
+pub fn handle_get_preferences_route(
+    query_params: HashMap<String, String>,
+    db_handler: &DatabaseHandler,
+) -> RouteResult {
+    let user_id = match authenticate(&query_params, db_handler) {
+        Ok(user_id) => user_id,
+        Err(response) => return response,
+    };
+
+    match db_handler.get_preferences(user_id) {
+        Ok(preferences) => (
+            "HTTP/1.1 200 OK",
+            serde_json::to_string_pretty(&preferences).unwrap(),
+            "application/json",
+        ),
+        Err(err) => {
+            println!("Error fetching preferences: {}", err);
+            json_error("HTTP/1.1 500 INTERNAL SERVER ERROR", "Failed to fetch preferences")
+        }
+    }
+}
+
+pub fn handle_update_preferences_route<R: BufRead>(
+    buf_reader: R,
+    query_params: HashMap<String, String>,
+    db_handler: &DatabaseHandler,
+    content_length: usize,
+) -> RouteResult {
+    let user_id = match authenticate(&query_params, db_handler) {
+        Ok(user_id) => user_id,
+        Err(response) => return response,
+    };
+
+    let mut body = String::new();
+    if let Err(err) = buf_reader.take(content_length as u64).read_to_string(&mut body) {
+        println!("Error reading request body: {}", err);
+        return json_error("HTTP/1.1 400 BAD REQUEST", "Failed to read request body");
+    }
+    let update: PreferencesUpdate = match serde_json::from_str(body.trim()) {
+        Ok(update) => update,
+        Err(err) => {
+            println!("Error deserializing JSON: {}", err);
+            return json_error("HTTP/1.1 400 BAD REQUEST", &format!("Invalid preferences: {}", err));
+        }
+    };
+
+    if update.timezone.as_deref().is_some_and(|tz| !user_time::is_valid_timezone(tz)) {
+        return json_error("HTTP/1.1 400 BAD REQUEST", "Unknown timezone");
+    }
+    if update.locale.as_deref().is_some_and(|locale| !user_time::is_valid_locale(locale)) {
+        return json_error("HTTP/1.1 400 BAD REQUEST", "Unknown locale");
+    }
+
+    match db_handler.update_preferences(user_id, &update) {
+        Ok(preferences) => (
+            "HTTP/1.1 200 OK",
+            serde_json::to_string_pretty(&preferences).unwrap(),
+            "application/json",
+        ),
+        Err(err) => {
+            println!("Error updating preferences: {}", err);
+            json_error("HTTP/1.1 500 INTERNAL SERVER ERROR", "Failed to update preferences")
+        }
+    }
+}
+
 pub fn handle_video_upload<R: BufRead>(
     buf_reader: R,
     content_length: usize,
     content_type: String,
+    upload_dir: &str,
 ) -> (&'static str, String, &'static str) {
     let mut body = Vec::new();
     if content_length > 0 {
@@ -367,12 +868,12 @@ pub fn handle_video_upload<R: BufRead>(
             let file_data = extract_file_data_from_part(part);
             let timestamp = Utc::now().to_rfc3339().replace(":", "-");
             let filename = format!("{}_{}", timestamp, old_filename);
-            let file_path = format!("./uploads/{}", filename);
+            let file_path = format!("{}/{}", upload_dir, filename);
             let mut file = File::create(&file_path).unwrap();
             file.write_all(&file_data).unwrap();
             return (
                 "HTTP/1.1 200 OK",
-                format!(r#"{{"fileUrl": "./uploads/{}"}}"#, filename),
+                format!(r#"{{"fileUrl": "{}"}}"#, file_path),
                 "application/json",
             );
         }
@@ -426,18 +927,18 @@ pub fn extract_file_data_from_part(part: &[u8]) -> Vec<u8> {
     Vec::new()
 }
 
-fn get_random_processed_path() -> String {
-    let prefix = "processed/";
+fn get_random_processed_path(processed_dir: &str) -> String {
     let suffix = ".mp4";
     let random_string: String = (0..10)
         .map(|_| rand::thread_rng().gen_range(b'a'..b'z') as char)
         .collect();
-    format!("{}{}{}", prefix, random_string, suffix)
+    format!("{}/{}{}", processed_dir, random_string, suffix)
 }
 
 pub fn handle_metadata_upload<R: BufRead>(
     buf_reader: R,
     content_length: usize,
+    processed_dir: &str,
 ) -> (&'static str, String, &'static str) {
     let mut body = String::new();
     if content_length > 0 {
@@ -454,7 +955,7 @@ pub fn handle_metadata_upload<R: BufRead>(
     let height = md.barbell_area.height as i32;
 
     match edited_path {
-        Ok(path) => match tracker::track_video(path, get_random_processed_path(), x, y, width, height) {
+        Ok(path) => match tracker::track_video(path, get_random_processed_path(processed_dir), x, y, width, height) {
             Ok(video) => {
                 let video_url = format!("/processed/{}", Path::new(&video.new_path).file_name().unwrap().to_str().unwrap());
                 let response_json = json!({
@@ -482,6 +983,23 @@ pub fn handle_metadata_upload<R: BufRead>(
     }
 }
 
+pub fn handle_processed_download(processed_dir: &str, file_name: &str) -> Response {
+    println!("Trying to download video!");
+    let file_path = format!("{}/{}", processed_dir, file_name);
+    match fs::read(&file_path) {
+        Ok(bytes) => {
+            let mime_type = if file_path.ends_with(".mp4") {
+                "video/mp4"
+            } else {
+                "application/octet-stream"
+            };
+            let _ = fs::remove_file(file_path);
+            Response::new("HTTP/1.1 200 OK", bytes, mime_type)
+        }
+        Err(_) => Response::new("HTTP/1.1 404 NOT FOUND", "404 NOT FOUND", "text/html"),
+    }
+}
+
 pub fn handle_templates_route(
     query_params: HashMap<String, String>,
     db_handler: &DatabaseHandler,
@@ -557,6 +1075,14 @@ pub fn handle_save_template_route<R: BufRead>(
         );
     }
 
+    let groups: Vec<_> = template_request.exercises.iter().map(|exercise| exercise.group).collect();
+    if let Err(err) = validate_groups(&groups) {
+        return json_error("HTTP/1.1 400 BAD REQUEST", &err);
+    }
+    if let Err(err) = template_request.exercises.iter().try_for_each(TemplateExerciseRequest::validate) {
+        return json_error("HTTP/1.1 400 BAD REQUEST", &err);
+    }
+
     match db_handler.get_user_id_from_token(&template_request.user_id) {
         Ok(parsed_userid) => {
             if let Err(err) = db_handler.is_valid_user(parsed_userid) {
@@ -594,6 +1120,418 @@ pub fn handle_save_template_route<R: BufRead>(
     }
 }
 
+fn parse_template_id(template_id: &str) -> Result<u32, RouteResult> {
+    template_id
+        .parse()
+        .map_err(|_| json_error("HTTP/1.1 400 BAD REQUEST", "Invalid template id"))
+}
+
+fn template_not_found() -> RouteResult {
+    json_error("HTTP/1.1 404 NOT FOUND", "Template not found")
+}
+
+/// Checks a replacement exercise list: not empty, well-formed groups and
+/// set targets, and only the user's own exercises.
+fn validate_template_exercises(
+    db_handler: &DatabaseHandler,
+    user_id: u32,
+    exercises: &[TemplateExerciseRequest],
+) -> Result<(), RouteResult> {
+    if exercises.is_empty() {
+        return Err(json_error("HTTP/1.1 400 BAD REQUEST", "Template exercises are required"));
+    }
+    let groups: Vec<_> = exercises.iter().map(|exercise| exercise.group).collect();
+    validate_groups(&groups).map_err(|err| json_error("HTTP/1.1 400 BAD REQUEST", &err))?;
+    exercises
+        .iter()
+        .try_for_each(TemplateExerciseRequest::validate)
+        .map_err(|err| json_error("HTTP/1.1 400 BAD REQUEST", &err))?;
+
+    let exercise_ids: Vec<u32> = exercises.iter().map(|exercise| exercise.exercise_id).collect();
+    match db_handler.owns_exercises(user_id, &exercise_ids) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(exercise_not_found()),
+        Err(err) => {
+            println!("Error checking exercise ownership: {}", err);
+            Err(json_error("HTTP/1.1 500 INTERNAL SERVER ERROR", "Failed to update template"))
+        }
+    }
+}
+
+/// Renames a template and/or replaces its exercise list. Responds with the
+/// template as `/templates` lists it.
+pub fn handle_update_template_route<R: BufRead>(
+    buf_reader: R,
+    query_params: HashMap<String, String>,
+    template_id: &str,
+    db_handler: &DatabaseHandler,
+    content_length: usize,
+) -> RouteResult {
+    let (user_id, template_id) = match (authenticate(&query_params, db_handler), parse_template_id(template_id)) {
+        (Ok(user_id), Ok(template_id)) => (user_id, template_id),
+        (Err(response), _) | (_, Err(response)) => return response,
+    };
+
+    let mut body = String::new();
+    if let Err(err) = buf_reader.take(content_length as u64).read_to_string(&mut body) {
+        println!("Error reading request body: {}", err);
+        return json_error("HTTP/1.1 400 BAD REQUEST", "Failed to read request body");
+    }
+    let update: TemplateUpdate = match serde_json::from_str(body.trim()) {
+        Ok(update) => update,
+        Err(err) => {
+            println!("Error deserializing JSON: {}", err);
+            return json_error("HTTP/1.1 400 BAD REQUEST", "Invalid JSON format");
+        }
+    };
+
+    if update.name.as_deref().is_some_and(|name| name.trim().is_empty()) {
+        return json_error("HTTP/1.1 400 BAD REQUEST", "Template name can't be empty");
+    }
+    if let Some(exercises) = &update.exercises {
+        if let Err(response) = validate_template_exercises(db_handler, user_id, exercises) {
+            return response;
+        }
+    }
+
+    match db_handler.update_template(user_id, template_id, &update) {
+        Ok(true) => match db_handler.get_templates(user_id) {
+            Ok(templates) => match templates.iter().find(|template| template.id == template_id) {
+                Some(template) => ("HTTP/1.1 200 OK", serde_json::to_string_pretty(template).unwrap(), "application/json"),
+                None => template_not_found(),
+            },
+            Err(err) => {
+                println!("Error retrieving templates: {}", err);
+                json_error("HTTP/1.1 500 INTERNAL SERVER ERROR", "Failed to retrieve template")
+            }
+        },
+        Ok(false) => template_not_found(),
+        Err(err) => {
+            println!("Error updating template: {}", err);
+            json_error("HTTP/1.1 500 INTERNAL SERVER ERROR", "Failed to update template")
+        }
+    }
+}
+
+pub fn handle_delete_template_route(
+    query_params: HashMap<String, String>,
+    template_id: &str,
+    db_handler: &DatabaseHandler,
+) -> RouteResult {
+    let (user_id, template_id) = match (authenticate(&query_params, db_handler), parse_template_id(template_id)) {
+        (Ok(user_id), Ok(template_id)) => (user_id, template_id),
+        (Err(response), _) | (_, Err(response)) => return response,
+    };
+
+    match db_handler.delete_template(user_id, template_id) {
+        Ok(true) => (
+            "HTTP/1.1 200 OK",
+            json!({ "template_id": template_id, "success": true }).to_string(),
+            "application/json",
+        ),
+        Ok(false) => template_not_found(),
+        Err(err) => {
+            println!("Error deleting template: {}", err);
+            json_error("HTTP/1.1 500 INTERNAL SERVER ERROR", "Failed to delete template")
+        }
+    }
+}
+
+/// Copies a template. The body may give the copy a `name`; it is optional.
+pub fn handle_duplicate_template_route<R: BufRead>(
+    buf_reader: R,
+    query_params: HashMap<String, String>,
+    template_id: &str,
+    db_handler: &DatabaseHandler,
+    content_length: usize,
+) -> RouteResult {
+    let (user_id, template_id) = match (authenticate(&query_params, db_handler), parse_template_id(template_id)) {
+        (Ok(user_id), Ok(template_id)) => (user_id, template_id),
+        (Err(response), _) | (_, Err(response)) => return response,
+    };
+
+    let mut body = String::new();
+    if let Err(err) = buf_reader.take(content_length as u64).read_to_string(&mut body) {
+        println!("Error reading request body: {}", err);
+        return json_error("HTTP/1.1 400 BAD REQUEST", "Failed to read request body");
+    }
+    let copy: TemplateCopy = if body.trim().is_empty() {
+        TemplateCopy::default()
+    } else {
+        match serde_json::from_str(body.trim()) {
+            Ok(copy) => copy,
+            Err(err) => {
+                println!("Error deserializing JSON: {}", err);
+                return json_error("HTTP/1.1 400 BAD REQUEST", "Invalid JSON format");
+            }
+        }
+    };
+    if copy.name.as_deref().is_some_and(|name| name.trim().is_empty()) {
+        return json_error("HTTP/1.1 400 BAD REQUEST", "Template name can't be empty");
+    }
+
+    match db_handler.duplicate_template(user_id, template_id, copy.name.as_deref()) {
+        Ok(Some(copy_id)) => (
+            "HTTP/1.1 201 CREATED",
+            json!({ "template_id": copy_id, "success": true }).to_string(),
+            "application/json",
+        ),
+        Ok(None) => template_not_found(),
+        Err(err) => {
+            println!("Error duplicating template: {}", err);
+            json_error("HTTP/1.1 500 INTERNAL SERVER ERROR", "Failed to duplicate template")
+        }
+    }
+}
+
+/// Responds with a draft workout built from the template, ready to log.
+/// Nothing is saved; the draft's `template_id` goes back with the workout.
+pub fn handle_start_template_route(
+    query_params: HashMap<String, String>,
+    template_id: &str,
+    db_handler: &DatabaseHandler,
+) -> RouteResult {
+    let (user_id, template_id) = match (authenticate(&query_params, db_handler), parse_template_id(template_id)) {
+        (Ok(user_id), Ok(template_id)) => (user_id, template_id),
+        (Err(response), _) | (_, Err(response)) => return response,
+    };
+
+    match db_handler.start_template(user_id, template_id) {
+        Ok(Some(draft)) => ("HTTP/1.1 200 OK", serde_json::to_string_pretty(&draft).unwrap(), "application/json"),
+        Ok(None) => template_not_found(),
+        Err(err) => {
+            println!("Error starting template: {}", err);
+            json_error("HTTP/1.1 500 INTERNAL SERVER ERROR", "Failed to start workout from template")
+        }
+    }
+}
+
+fn parse_program_id(program_id: &str) -> Result<u32, RouteResult> {
+    program_id
+        .parse()
+        .map_err(|_| json_error("HTTP/1.1 400 BAD REQUEST", "Invalid program id"))
+}
+
+fn program_not_found() -> RouteResult {
+    json_error("HTTP/1.1 404 NOT FOUND", "Program not found")
+}
+
+pub fn handle_programs_route(query_params: HashMap<String, String>, db_handler: &DatabaseHandler) -> RouteResult {
+    let user_id = match authenticate(&query_params, db_handler) {
+        Ok(user_id) => user_id,
+        Err(response) => return response,
+    };
+
+    match db_handler.get_programs(user_id) {
+        Ok(programs) => ("HTTP/1.1 200 OK", serde_json::to_string_pretty(&programs).unwrap(), "application/json"),
+        Err(err) => {
+            println!("Error retrieving programs: {}", err);
+            json_error("HTTP/1.1 500 INTERNAL SERVER ERROR", "Failed to retrieve programs")
+        }
+    }
+}
+
+/// Creates a program of weeks and days from the user's templates.
+pub fn handle_create_program_route<R: BufRead>(
+    buf_reader: R,
+    query_params: HashMap<String, String>,
+    db_handler: &DatabaseHandler,
+    content_length: usize,
+) -> RouteResult {
+    let user_id = match authenticate(&query_params, db_handler) {
+        Ok(user_id) => user_id,
+        Err(response) => return response,
+    };
+
+    let mut body = String::new();
+    if let Err(err) = buf_reader.take(content_length as u64).read_to_string(&mut body) {
+        println!("Error reading request body: {}", err);
+        return json_error("HTTP/1.1 400 BAD REQUEST", "Failed to read request body");
+    }
+    let request: ProgramRequest = match serde_json::from_str(body.trim()) {
+        Ok(request) => request,
+        Err(err) => {
+            println!("Error deserializing JSON: {}", err);
+            return json_error("HTTP/1.1 400 BAD REQUEST", "Invalid JSON format");
+        }
+    };
+
+    if request.name.trim().is_empty() {
+        return json_error("HTTP/1.1 400 BAD REQUEST", "Program name can't be empty");
+    }
+    if request.weeks.is_empty() || request.weeks.iter().any(|week| week.days.is_empty()) {
+        return json_error("HTTP/1.1 400 BAD REQUEST", "Every program week needs at least one day");
+    }
+    if !(request.deload_percent > 0.0 && request.deload_percent <= 100.0) {
+        return json_error("HTTP/1.1 400 BAD REQUEST", "deload_percent must be above 0 and at most 100");
+    }
+
+    match db_handler.create_program(user_id, &request) {
+        Ok(Some(program_id)) => (
+            "HTTP/1.1 201 CREATED",
+            json!({ "program_id": program_id, "success": true }).to_string(),
+            "application/json",
+        ),
+        Ok(None) => template_not_found(),
+        Err(err) => {
+            println!("Error creating program: {}", err);
+            json_error("HTTP/1.1 500 INTERNAL SERVER ERROR", "Failed to create program")
+        }
+    }
+}
+
+pub fn handle_delete_program_route(
+    query_params: HashMap<String, String>,
+    program_id: &str,
+    db_handler: &DatabaseHandler,
+) -> RouteResult {
+    let (user_id, program_id) = match (authenticate(&query_params, db_handler), parse_program_id(program_id)) {
+        (Ok(user_id), Ok(program_id)) => (user_id, program_id),
+        (Err(response), _) | (_, Err(response)) => return response,
+    };
+
+    match db_handler.delete_program(user_id, program_id) {
+        Ok(true) => (
+            "HTTP/1.1 200 OK",
+            json!({ "program_id": program_id, "success": true }).to_string(),
+            "application/json",
+        ),
+        Ok(false) => program_not_found(),
+        Err(err) => {
+            println!("Error deleting program: {}", err);
+            json_error("HTTP/1.1 500 INTERNAL SERVER ERROR", "Failed to delete program")
+        }
+    }
+}
+
+/// Starts the user on the first day of a program, replacing the one they
+/// were following.
+pub fn handle_start_program_route(
+    query_params: HashMap<String, String>,
+    program_id: &str,
+    db_handler: &DatabaseHandler,
+) -> RouteResult {
+    let (user_id, program_id) = match (authenticate(&query_params, db_handler), parse_program_id(program_id)) {
+        (Ok(user_id), Ok(program_id)) => (user_id, program_id),
+        (Err(response), _) | (_, Err(response)) => return response,
+    };
+
+    match db_handler.start_program(user_id, program_id) {
+        Ok(true) => (
+            "HTTP/1.1 200 OK",
+            json!({ "program_id": program_id, "success": true }).to_string(),
+            "application/json",
+        ),
+        Ok(false) => program_not_found(),
+        Err(err) => {
+            println!("Error starting program: {}", err);
+            json_error("HTTP/1.1 500 INTERNAL SERVER ERROR", "Failed to start program")
+        }
+    }
+}
+
+/// The workout due next in the user's program, as a draft to log. Saving it
+/// with its `template_id` moves the program on a day.
+pub fn handle_today_route(query_params: HashMap<String, String>, db_handler: &DatabaseHandler) -> RouteResult {
+    let user_id = match authenticate(&query_params, db_handler) {
+        Ok(user_id) => user_id,
+        Err(response) => return response,
+    };
+
+    match db_handler.todays_workout(user_id) {
+        Ok(Some(today)) => ("HTTP/1.1 200 OK", serde_json::to_string_pretty(&today).unwrap(), "application/json"),
+        Ok(None) => json_error("HTTP/1.1 404 NOT FOUND", "Not following a program"),
+        Err(err) => {
+            println!("Error finding today's workout: {}", err);
+            json_error("HTTP/1.1 500 INTERNAL SERVER ERROR", "Failed to find today's workout")
+        }
+    }
+}
+
+pub fn handle_training_maxes_route(query_params: HashMap<String, String>, db_handler: &DatabaseHandler) -> RouteResult {
+    let user_id = match authenticate(&query_params, db_handler) {
+        Ok(user_id) => user_id,
+        Err(response) => return response,
+    };
+
+    match db_handler.get_training_maxes(user_id) {
+        Ok(maxes) => ("HTTP/1.1 200 OK", serde_json::to_string_pretty(&maxes).unwrap(), "application/json"),
+        Err(err) => {
+            println!("Error retrieving training maxes: {}", err);
+            json_error("HTTP/1.1 500 INTERNAL SERVER ERROR", "Failed to retrieve training maxes")
+        }
+    }
+}
+
+pub fn handle_update_training_max_route<R: BufRead>(
+    buf_reader: R,
+    query_params: HashMap<String, String>,
+    exercise_id: &str,
+    db_handler: &DatabaseHandler,
+    content_length: usize,
+) -> RouteResult {
+    let (user_id, exercise_id) = match (authenticate(&query_params, db_handler), parse_exercise_id(exercise_id)) {
+        (Ok(user_id), Ok(exercise_id)) => (user_id, exercise_id),
+        (Err(response), _) | (_, Err(response)) => return response,
+    };
+
+    let mut body = String::new();
+    if let Err(err) = buf_reader.take(content_length as u64).read_to_string(&mut body) {
+        println!("Error reading request body: {}", err);
+        return json_error("HTTP/1.1 400 BAD REQUEST", "Failed to read request body");
+    }
+    let update: TrainingMaxUpdate = match serde_json::from_str(body.trim()) {
+        Ok(update) => update,
+        Err(err) => {
+            println!("Error deserializing JSON: {}", err);
+            return json_error("HTTP/1.1 400 BAD REQUEST", "Invalid JSON format");
+        }
+    };
+    if update.weight <= 0.0 {
+        return json_error("HTTP/1.1 400 BAD REQUEST", "Training max must be positive");
+    }
+
+    let unit = match update.unit.map_or_else(|| db_handler.weight_unit(user_id), Ok) {
+        Ok(unit) => unit,
+        Err(err) => {
+            println!("Error reading weight unit: {}", err);
+            return json_error("HTTP/1.1 500 INTERNAL SERVER ERROR", "Failed to update training max");
+        }
+    };
+    match db_handler.set_training_max(user_id, exercise_id, unit.to_kg(update.weight)) {
+        Ok(true) => (
+            "HTTP/1.1 200 OK",
+            json!({ "exercise_id": exercise_id, "success": true }).to_string(),
+            "application/json",
+        ),
+        Ok(false) => exercise_not_found(),
+        Err(err) => {
+            println!("Error updating training max: {}", err);
+            json_error("HTTP/1.1 500 INTERNAL SERVER ERROR", "Failed to update training max")
+        }
+    }
+}
+
+/// How progression rules have judged each session of a template, newest first.
+pub fn handle_progression_log_route(
+    query_params: HashMap<String, String>,
+    template_id: &str,
+    db_handler: &DatabaseHandler,
+) -> RouteResult {
+    let (user_id, template_id) = match (authenticate(&query_params, db_handler), parse_template_id(template_id)) {
+        (Ok(user_id), Ok(template_id)) => (user_id, template_id),
+        (Err(response), _) | (_, Err(response)) => return response,
+    };
+
+    match db_handler.progression_log(user_id, template_id) {
+        Ok(entries) => ("HTTP/1.1 200 OK", serde_json::to_string_pretty(&entries).unwrap(), "application/json"),
+        Err(err) => {
+            println!("Error retrieving progression log: {}", err);
+            json_error("HTTP/1.1 500 INTERNAL SERVER ERROR", "Failed to retrieve progression log")
+        }
+    }
+}
+
 pub fn handle_login_route<R: BufRead>(
     buf_reader: R,
     db_handler: &DatabaseHandler,
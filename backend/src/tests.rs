@@ -1,104 +1,34 @@
 #[cfg(test)]
 mod tests {
     use super::super::database_handler::*;
+    use super::super::catalogue::{self, CATALOGUE};
+    use super::super::config::Config;
+    use super::super::db_pool::DbPool;
+    use super::super::exercise_kind::ExerciseKind;
+    use super::super::migrations;
+    use super::super::one_rep_max::OneRepMaxFormula;
+    use super::super::personal_records;
+    use super::super::progression;
+    use super::super::time_buckets::{Bucket, TimeRange};
+    use super::super::units::WeightUnit;
+    use super::super::user_time;
+    use super::super::http::{read_request, ConnectionInfo, Response};
+    use super::super::router::Router;
+    use super::super::routes;
+    use super::super::{handle_connection, serve};
+    use super::super::thread_pool::ThreadPool;
     use super::super::wt_types::*;
-    use chrono::{DateTime, Utc};
+    use chrono::NaiveDate;
     use rusqlite::Connection;
+    use std::collections::HashMap;
+    use std::io::{self, Read, Write};
+    use std::net::{IpAddr, TcpListener, TcpStream};
+    use std::sync::{mpsc, Mutex};
+    use std::time::Duration;
 
     fn setup_database() -> Connection {
         let conn = Connection::open_in_memory().unwrap();
-
-        // Create tables for testing
-        conn.execute(
-            "CREATE TABLE users (
-                id INTEGER PRIMARY KEY,
-                username TEXT NOT NULL UNIQUE,
-                password_hash TEXT NOT NULL
-            )",
-            [],
-        )
-        .unwrap();
-
-        conn.execute(
-            "CREATE TABLE sessions (
-                id INTEGER PRIMARY KEY,
-                user_id INTEGER NOT NULL,
-                session_token TEXT NOT NULL,
-                expires_at TEXT NOT NULL
-            )",
-            [],
-        )
-        .unwrap();
-
-        conn.execute(
-            "CREATE TABLE user_exercises (
-                id INTEGER PRIMARY KEY,
-                user_id INTEGER NOT NULL,
-                name TEXT NOT NULL,
-                description TEXT,
-                muscle_group TEXT NOT NULL
-            )",
-            [],
-        )
-        .unwrap();
-
-        conn.execute(
-            "CREATE TABLE workouts (
-                id INTEGER PRIMARY KEY,
-                user_id INTEGER NOT NULL,
-                start_time TEXT NOT NULL,
-                end_time TEXT,
-                notes TEXT,
-                prs INTEGER DEFAULT 0
-            )",
-            [],
-        )
-        .unwrap();
-
-        conn.execute(
-            "CREATE TABLE workout_exercises (
-                id INTEGER PRIMARY KEY,
-                workout_id INTEGER NOT NULL,
-                exercise_id INTEGER NOT NULL
-            )",
-            [],
-        )
-        .unwrap();
-
-        conn.execute(
-            "CREATE TABLE sets (
-                id INTEGER PRIMARY KEY,
-                workout_exercise_id INTEGER NOT NULL,
-                set_number INTEGER NOT NULL,
-                weight REAL NOT NULL,
-                reps INTEGER NOT NULL
-            )",
-            [],
-        )
-        .unwrap();
-
-        conn.execute(
-            "CREATE TABLE templates (
-                id INTEGER PRIMARY KEY,
-                user_id INTEGER NOT NULL,
-                name TEXT NOT NULL,
-                created_at TEXT NOT NULL
-            )",
-            [],
-        )
-        .unwrap();
-
-        conn.execute(
-            "CREATE TABLE template_exercises (
-                id INTEGER PRIMARY KEY,
-                template_id INTEGER NOT NULL,
-                exercise_id INTEGER NOT NULL,
-                sets INTEGER NOT NULL
-            )",
-            [],
-        )
-        .unwrap();
-
+        migrations::run(&conn).unwrap();
         conn
     }
 
@@ -118,7 +48,7 @@ mod tests {
     #[test]
     fn test_register_user() {
         let conn = setup_database();
-        let db_handler = DatabaseHandler { conn };
+        let db_handler = DatabaseHandler::from_connection(conn).unwrap();
 
         let username = "testuser";
         let password = "password123";
@@ -135,7 +65,7 @@ mod tests {
     #[test]
     fn test_login_valid_credentials() {
         let conn = setup_database();
-        let db_handler = DatabaseHandler { conn };
+        let db_handler = DatabaseHandler::from_connection(conn).unwrap();
 
         let username = "testuser";
         let password = "password123";
@@ -153,7 +83,7 @@ mod tests {
     #[test]
     fn test_login_invalid_credentials() {
         let conn = setup_database();
-        let db_handler = DatabaseHandler { conn };
+        let db_handler = DatabaseHandler::from_connection(conn).unwrap();
 
         let username = "testuser";
         let password = "password123";
@@ -167,7 +97,7 @@ mod tests {
     #[test]
     fn test_add_exercise_to_user() {
         let conn = setup_database();
-        let db_handler = DatabaseHandler { conn };
+        let db_handler = DatabaseHandler::from_connection(conn).unwrap();
 
         // Register and log in the user
         let (_, session_token) = register_and_login_user(&db_handler);
@@ -176,6 +106,7 @@ mod tests {
             user_id: session_token,
             name: "Bench Press".to_string(),
             body_part: "Chest".to_string(),
+            kind: ExerciseKind::WeightReps,
         };
 
         let exercise_id = db_handler.add_exercise_to_user(request).unwrap();
@@ -189,7 +120,7 @@ mod tests {
     #[test]
     fn test_get_user_exercises() {
         let conn = setup_database();
-        let db_handler = DatabaseHandler { conn };
+        let db_handler = DatabaseHandler::from_connection(conn).unwrap();
 
         // Register and log in the user
         let (user_id, session_token) = register_and_login_user(&db_handler);
@@ -198,13 +129,1606 @@ mod tests {
             user_id: session_token.clone(),
             name: "Bench Press".to_string(),
             body_part: "Chest".to_string(),
+            kind: ExerciseKind::WeightReps,
         };
 
         db_handler.add_exercise_to_user(request).unwrap();
 
-        let exercises = db_handler.get_user_exercises(user_id).unwrap();
-        assert_eq!(exercises.len(), 1);
-        assert_eq!(exercises[0].name, "Bench Press");
+        let exercises = db_handler.get_user_exercises(user_id, false).unwrap();
+        assert_eq!(exercises.len(), CATALOGUE.len() + 1);
+        assert!(exercises.iter().any(|e| e.name == "Bench Press"));
+    }
+
+    fn test_router() -> Router<()> {
+        Router::<()>::new()
+            .get("/workouts/{id}", |req, _| {
+                Response::new("HTTP/1.1 200 OK", req.param("id").unwrap_or_default().to_string(), "text/plain")
+            })
+            .post("/workout", |_, _| Response::new("HTTP/1.1 200 OK", "saved", "text/plain"))
+    }
+
+    fn dispatch_raw(router: &Router<()>, raw: &str) -> Response {
+        let mut request = read_request(&mut raw.as_bytes(), 1024).unwrap().unwrap();
+        router.dispatch(&mut request, &())
+    }
+
+    #[test]
+    fn test_router_matches_method_and_path_params() {
+        let router = test_router();
+
+        let response = dispatch_raw(&router, "GET /workouts/42?userid=abc HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status, "HTTP/1.1 200 OK");
+        assert_eq!(response.body, b"42");
+
+        let response = dispatch_raw(&router, "GET /workouts HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status, "HTTP/1.1 404 NOT FOUND");
+    }
+
+    #[test]
+    fn test_router_wrong_method_and_preflight() {
+        let router = test_router();
+
+        let response = dispatch_raw(&router, "GET /workout HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status, "HTTP/1.1 405 METHOD NOT ALLOWED");
+        assert!(response.headers.contains(&("Allow", "POST, OPTIONS".to_string())));
+
+        let response = dispatch_raw(
+            &router,
+            "OPTIONS /workout HTTP/1.1\r\nAccess-Control-Request-Headers: content-type\r\n\r\n",
+        );
+        assert_eq!(response.status, "HTTP/1.1 204 NO CONTENT");
+        assert!(response.headers.contains(&("Access-Control-Allow-Methods", "POST, OPTIONS".to_string())));
+        assert!(response.headers.contains(&("Access-Control-Allow-Headers", "content-type".to_string())));
+    }
+
+    struct MockStream {
+        input: io::Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for MockStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for MockStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn run_connection(router: &Router<()>, raw: &str) -> String {
+        let mut stream = MockStream { input: io::Cursor::new(raw.as_bytes().to_vec()), output: Vec::new() };
+        let info = ConnectionInfo { peer: None, scheme: "http", max_body: 1024, trusted_proxies: &[] };
+        handle_connection(&mut stream, &info, router, &());
+        String::from_utf8(stream.output).unwrap()
+    }
+
+    #[test]
+    fn test_keep_alive_serves_pipelined_requests() {
+        let router = test_router();
+        let output = run_connection(
+            &router,
+            "GET /workouts/1 HTTP/1.1\r\n\r\n\
+             POST /workout HTTP/1.1\r\nContent-Length: 2\r\n\r\n{}\
+             GET /workouts/3 HTTP/1.1\r\nConnection: close\r\n\r\n\
+             GET /workouts/4 HTTP/1.1\r\n\r\n",
+        );
+
+        assert_eq!(output.matches("HTTP/1.1 200 OK").count(), 3);
+        assert_eq!(output.matches("Connection: keep-alive").count(), 2);
+        assert!(output.ends_with("Connection: close\r\n\r\n3"));
+    }
+
+    #[test]
+    fn test_http_1_0_closes_by_default() {
+        let router = test_router();
+        let output = run_connection(&router, "GET /workouts/1 HTTP/1.0\r\n\r\nGET /workouts/2 HTTP/1.0\r\n\r\n");
+
+        assert_eq!(output.matches("HTTP/1.1 200 OK").count(), 1);
+        assert!(output.contains("Connection: close"));
+    }
+
+    #[test]
+    fn test_thread_pool_hands_back_work_when_saturated() {
+        let (started_tx, started_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let release_rx = Mutex::new(release_rx);
+
+        let pool = ThreadPool::new(1, 1, move |item: u32| {
+            started_tx.send(item).unwrap();
+            let _ = release_rx.lock().unwrap().recv();
+        });
+
+        assert!(pool.try_submit(1).is_ok());
+        assert_eq!(started_rx.recv().unwrap(), 1);
+
+        // The only worker is busy, so one item fits in the queue and the next is refused.
+        assert!(pool.try_submit(2).is_ok());
+        assert_eq!(pool.try_submit(3), Err(3));
+
+        release_tx.send(()).unwrap();
+        assert_eq!(started_rx.recv().unwrap(), 2);
+        release_tx.send(()).unwrap();
+    }
+
+    #[test]
+    fn test_db_pool_checkout_and_return() {
+        let pool = DbPool::from_handlers(
+            vec![DatabaseHandler::from_connection(setup_database()).unwrap()],
+            Duration::from_millis(50),
+        );
+
+        {
+            let db = pool.get().unwrap();
+            db.register_user("pooluser", "password123").unwrap();
+
+            // The only connection is checked out, so a second caller times out.
+            assert!(pool.get().is_none());
+        }
+
+        let db = pool.get().unwrap();
+        let count: u32 = db.conn.query_row("SELECT COUNT(*) FROM users", [], |row| row.get(0)).unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn test_file_database_uses_wal() {
+        let path = std::env::temp_dir().join(format!("wt_wal_test_{}.db", std::process::id()));
+        let db = DatabaseHandler::new(path.to_str().unwrap()).unwrap();
+
+        let mode: String = db.conn.query_row("PRAGMA journal_mode", [], |row| row.get(0)).unwrap();
+        assert_eq!(mode, "wal");
+
+        drop(db);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
+
+    #[test]
+    fn test_oversized_body_is_rejected() {
+        let router = test_router();
+        let output = run_connection(&router, "POST /workout HTTP/1.1\r\nContent-Length: 4096\r\n\r\n");
+
+        assert!(output.starts_with("HTTP/1.1 413 PAYLOAD TOO LARGE"));
+        assert!(output.contains("Connection: close"));
+    }
+
+    #[test]
+    fn test_config_layering() {
+        let path = std::env::temp_dir().join(format!("wt_config_test_{}.toml", std::process::id()));
+        std::fs::write(&path, "database_path = \"staging.db\"\nworkers = 4\nsession_lifetime_hours = 12\n").unwrap();
+
+        let args: Vec<String> = ["--config", path.to_str().unwrap(), "--workers=2"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let env = vec![
+            ("WT_WORKERS".to_string(), "3".to_string()),
+            ("WT_UPLOAD_DIR".to_string(), "/srv/uploads".to_string()),
+        ];
+        let config = Config::load_from(&args, &env).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(config.database_path, "staging.db");
+        assert_eq!(config.session_lifetime_hours, 12);
+        assert_eq!(config.upload_dir, "/srv/uploads");
+        assert_eq!(config.workers, 2);
+        assert_eq!(config.listen_addr, "0.0.0.0:25561");
+
+        assert!(Config::load_from(&["--no-such-setting".to_string(), "1".to_string()], &[]).is_err());
+        assert!(Config::load_from(&["--workers=0".to_string()], &[]).is_err());
+        assert!(Config::load_from(&[], &[("WT_QUEUE_DEPTH".to_string(), "0".to_string())]).is_err());
+    }
+
+    #[test]
+    fn test_forwarded_headers_only_trusted_from_proxies() {
+        let proxy: IpAddr = "10.0.0.2".parse().unwrap();
+        let trusted = [proxy];
+        let raw = "GET /workouts/1 HTTP/1.1\r\n\
+                   X-Forwarded-For: 1.1.1.1, 203.0.113.9\r\n\
+                   X-Forwarded-Proto: https\r\n\r\n";
+
+        let mut request = read_request(&mut raw.as_bytes(), 1024).unwrap().unwrap();
+        request.resolve_client(&ConnectionInfo { peer: Some(proxy), scheme: "http", max_body: 1024, trusted_proxies: &trusted });
+        assert_eq!(request.remote_addr, Some("203.0.113.9".parse().unwrap()));
+        assert_eq!(request.scheme, "https");
+
+        let stranger: IpAddr = "198.51.100.7".parse().unwrap();
+        let mut request = read_request(&mut raw.as_bytes(), 1024).unwrap().unwrap();
+        request.resolve_client(&ConnectionInfo { peer: Some(stranger), scheme: "http", max_body: 1024, trusted_proxies: &trusted });
+        assert_eq!(request.remote_addr, Some(stranger));
+        assert_eq!(request.scheme, "http");
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn test_plain_http_server_without_certificates() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let config = Config { tls: false, workers: 2, ..Config::default() };
+        std::thread::spawn(move || serve(listener, None, &config, test_router(), ()));
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"GET /workouts/7 HTTP/1.1\r\nConnection: close\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("7"));
+    }
+
+    #[test]
+    fn test_migrations_are_tracked_and_idempotent() {
+        let conn = setup_database();
+        let version: u32 = conn.query_row("PRAGMA user_version", [], |row| row.get(0)).unwrap();
+        assert_eq!(version, migrations::latest_version());
+        assert_eq!(migrations::run(&conn).unwrap(), 0);
+
+        // Databases created from the old schema file start at version 0.
+        let legacy = Connection::open_in_memory().unwrap();
+        legacy
+            .execute_batch("CREATE TABLE workouts (id INTEGER PRIMARY KEY AUTOINCREMENT, user_id INTEGER NOT NULL, name TEXT,
+                                start_time DATETIME DEFAULT CURRENT_TIMESTAMP, end_time DATETIME, notes TEXT, prs INTEGER);
+                            CREATE INDEX idx_workouts_user ON workouts(user_id);")
+            .unwrap();
+        assert_eq!(migrations::run(&legacy).unwrap(), migrations::latest_version());
+    }
+
+    #[test]
+    fn test_migrating_legacy_workouts() {
+        // The record backfill reads columns added after its own migration, so
+        // a database with history from before migrations must still upgrade.
+        let legacy = Connection::open_in_memory().unwrap();
+        legacy.execute_batch(include_str!("../migrations/0001_initial.sql")).unwrap();
+        legacy
+            .execute_batch(
+                "INSERT INTO users (id, username, password_hash) VALUES (1, 'lifter', 'x');
+                 INSERT INTO user_exercises (id, user_id, name, muscle_group) VALUES (1, 1, 'Bench Press', 'Chest');
+                 INSERT INTO workouts (id, user_id, start_time) VALUES (1, 1, '2024-03-01 10:00:00'), (2, 1, '2024-03-08 10:00:00');
+                 INSERT INTO workout_exercises (id, workout_id, exercise_id) VALUES (1, 1, 1), (2, 2, 1);
+                 INSERT INTO sets (workout_exercise_id, set_number, weight, reps)
+                 VALUES (1, 1, 100, 5), (1, 2, 100, 5), (2, 1, 110, 5);",
+            )
+            .unwrap();
+
+        assert_eq!(migrations::run(&legacy).unwrap(), migrations::latest_version());
+        let prs: Vec<u32> = legacy
+            .prepare("SELECT prs FROM workouts ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(prs[0], 0);
+        assert!(prs[1] > 0);
+        assert_eq!(migrations::run(&legacy).unwrap(), 0);
+
+        // Legacy times were the lifter's wall clock, converted once their timezone is known.
+        let db_handler = DatabaseHandler::from_connection(legacy).unwrap();
+        let update = PreferencesUpdate { timezone: Some("Europe/Berlin".to_string()), ..Default::default() };
+        db_handler.update_preferences(1, &update).unwrap();
+        let start_time: String =
+            db_handler.conn.query_row("SELECT start_time FROM workouts WHERE id = 1", [], |row| row.get(0)).unwrap();
+        assert_eq!(start_time, "2024-03-01 09:00:00");
+        db_handler.update_preferences(1, &update).unwrap();
+        assert_eq!(db_handler.get_workout(1, 1).unwrap().unwrap().start_time, "2024-03-01T10:00:00+01:00");
+    }
+
+
+    fn sample_workout(exercise_id: u32, client_id: Option<&str>) -> Workout {
+        Workout {
+            user_id: String::new(),
+            start_time: "2024-03-01 10:00:00".to_string(),
+            end_time: "2024-03-01 11:00:00".to_string(),
+            exercises: vec![ExerciseRecord {
+                exercise_id,
+                sets: vec![Set { reps: 5, weight: 100.0, ..Default::default() }, Set { reps: 5, weight: 105.0, ..Default::default() }],
+                group: None,
+            }],
+            notes: String::new(),
+            client_id: client_id.map(str::to_string),
+            template_id: None,
+        }
+    }
+
+    fn add_bench_press(db_handler: &DatabaseHandler, session_token: &str) -> u32 {
+        db_handler
+            .add_exercise_to_user(ExerciseRequest {
+                user_id: session_token.to_string(),
+                name: "Bench Press".to_string(),
+                body_part: "Chest".to_string(),
+                kind: ExerciseKind::WeightReps,
+            })
+            .unwrap()
+    }
+
+    fn count_rows(db_handler: &DatabaseHandler, table: &str) -> u32 {
+        db_handler
+            .conn
+            .query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_save_workout_rejects_other_users_exercises() {
+        let db_handler = DatabaseHandler::from_connection(setup_database()).unwrap();
+        let (_, session_token) = register_and_login_user(&db_handler);
+        let bench = add_bench_press(&db_handler, &session_token);
+        db_handler.register_user("someone_else", "password123").unwrap();
+        let other_token = db_handler.login("someone_else", "password123").unwrap();
+
+        let body = serde_json::to_string(&sample_workout(bench, None)).unwrap();
+        let save = |token: &str| {
+            let query_params = HashMap::from([("userid".to_string(), token.to_string())]);
+            routes::handle_workout_route(body.as_bytes(), query_params, None, &db_handler, body.len()).0
+        };
+        assert_eq!(save(&other_token), "HTTP/1.1 400 BAD REQUEST");
+        assert_eq!(count_rows(&db_handler, "workouts"), 0);
+        assert_eq!(save(&session_token), "HTTP/1.1 200 OK");
+        assert_eq!(count_rows(&db_handler, "workouts"), 1);
+    }
+
+    #[test]
+    fn test_save_workout_replay_is_idempotent() {
+        let db_handler = DatabaseHandler::from_connection(setup_database()).unwrap();
+        let (user_id, session_token) = register_and_login_user(&db_handler);
+        let exercise_id = add_bench_press(&db_handler, &session_token);
+
+        let first = db_handler.save_workout(sample_workout(exercise_id, Some("abc")), user_id).unwrap();
+        let retry = db_handler.save_workout(sample_workout(exercise_id, Some("abc")), user_id).unwrap();
+
+        assert!(!first.replayed);
+        assert!(retry.replayed);
+        assert_eq!(first.workout_id, retry.workout_id);
+        assert_eq!(retry.sets_saved, 2);
+        assert_eq!(count_rows(&db_handler, "workouts"), 1);
+        assert_eq!(count_rows(&db_handler, "sets"), 2);
+    }
+
+    #[test]
+    fn test_failed_save_workout_leaves_nothing_behind() {
+        let db_handler = DatabaseHandler::from_connection(setup_database()).unwrap();
+        let (user_id, session_token) = register_and_login_user(&db_handler);
+        let exercise_id = add_bench_press(&db_handler, &session_token);
+        db_handler
+            .conn
+            .execute_batch("CREATE TRIGGER fail_second_set BEFORE INSERT ON sets WHEN NEW.set_number = 2
+                            BEGIN SELECT RAISE(ABORT, 'disk full'); END;")
+            .unwrap();
+
+        assert!(db_handler.save_workout(sample_workout(exercise_id, None), user_id).is_err());
+        assert_eq!(count_rows(&db_handler, "workouts"), 0);
+        assert_eq!(count_rows(&db_handler, "workout_exercises"), 0);
+        assert_eq!(count_rows(&db_handler, "sets"), 0);
+    }
+
+
+    #[test]
+    fn test_edit_and_delete_workout() {
+        let db_handler = DatabaseHandler::from_connection(setup_database()).unwrap();
+        let (user_id, session_token) = register_and_login_user(&db_handler);
+        let bench = add_bench_press(&db_handler, &session_token);
+        let squat = db_handler
+            .add_exercise_to_user(ExerciseRequest {
+                user_id: session_token.clone(),
+                name: "Squat".to_string(),
+                body_part: "Legs".to_string(),
+                kind: ExerciseKind::WeightReps,
+            })
+            .unwrap();
+        let saved = db_handler.save_workout(sample_workout(bench, None), user_id).unwrap();
+
+        let update = WorkoutUpdate {
+            notes: Some("Fixed a typo".to_string()),
+            exercises: Some(vec![
+                ExerciseRecord { exercise_id: squat, sets: vec![Set { reps: 3, weight: 140.0, ..Default::default() }], group: None },
+                ExerciseRecord { exercise_id: bench, sets: vec![Set { reps: 5, weight: 102.5, ..Default::default() }], group: None },
+            ]),
+            ..Default::default()
+        };
+        assert!(db_handler.update_workout(user_id, saved.workout_id, &update).unwrap());
+
+        let workout = db_handler.get_workout(user_id, saved.workout_id).unwrap().unwrap();
+        assert_eq!(workout.notes, "Fixed a typo");
+        assert_eq!(workout.start_time, "2024-03-01T10:00:00+00:00");
+        let names: Vec<&str> = workout.exercises.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["Squat", "Bench Press"]);
+        assert_eq!(workout.exercises[1].sets.len(), 1);
+        assert_eq!(workout.exercises[1].sets[0].set.weight, 102.5);
+        assert_eq!(count_rows(&db_handler, "sets"), 2);
+
+        // Another user can neither see, edit nor delete it.
+        let other_user = db_handler.register_user("someone_else", "password123").unwrap();
+        assert!(db_handler.get_workout(other_user, saved.workout_id).unwrap().is_none());
+        assert!(!db_handler.update_workout(other_user, saved.workout_id, &update).unwrap());
+        assert!(!db_handler.delete_workout(other_user, saved.workout_id).unwrap());
+        assert!(!db_handler.owns_exercises(other_user, &[bench]).unwrap());
+
+        assert!(db_handler.delete_workout(user_id, saved.workout_id).unwrap());
+        assert!(db_handler.get_workout(user_id, saved.workout_id).unwrap().is_none());
+        assert_eq!(count_rows(&db_handler, "workout_exercises"), 0);
+        assert_eq!(count_rows(&db_handler, "sets"), 0);
+    }
+
+
+    #[test]
+    fn test_history_pages_and_filters() {
+        let db_handler = DatabaseHandler::from_connection(setup_database()).unwrap();
+        let (user_id, session_token) = register_and_login_user(&db_handler);
+        let bench = add_bench_press(&db_handler, &session_token);
+        let squat = db_handler
+            .add_exercise_to_user(ExerciseRequest {
+                user_id: session_token.clone(),
+                name: "Squat".to_string(),
+                body_part: "Legs".to_string(),
+                kind: ExerciseKind::WeightReps,
+            })
+            .unwrap();
+
+        for day in 1..=5 {
+            let exercise_id = if day % 2 == 0 { squat } else { bench };
+            let mut workout = sample_workout(exercise_id, None);
+            workout.start_time = format!("2024-03-0{} 10:00:00", day);
+            workout.end_time = format!("2024-03-0{} 11:00:00", day);
+            db_handler.save_workout(workout, user_id).unwrap();
+        }
+
+        let query = |limit, cursor| HistoryQuery {
+            limit: Some(limit),
+            cursor,
+            from: None,
+            to: None,
+            exercise_id: None,
+            muscle_group: None,
+        };
+
+        let mut seen = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = db_handler.get_history_data(user_id, &query(2, cursor)).unwrap();
+            assert!(page.workouts.len() <= 2);
+            seen.extend(page.workouts.iter().map(|w| w.date.clone()));
+            match page.next_cursor {
+                Some(next) => cursor = HistoryCursor::decode(&next.encode()),
+                None => break,
+            }
+        }
+        assert_eq!(seen.len(), 5);
+        assert!(seen[0].contains("05 Mar") && seen[4].contains("01 Mar"));
+        let everything = HistoryQuery { limit: None, ..query(1, None) };
+        let page = db_handler.get_history_data(user_id, &everything).unwrap();
+        assert_eq!((page.workouts.len(), page.next_cursor), (5, None));
+
+        let first = &db_handler.get_history_data(user_id, &query(1, None)).unwrap().workouts[0];
+        assert_eq!(first.exercises[0].sets.len(), 2);
+        assert_eq!(first.total_volume, 1025);
+
+        let legs = HistoryQuery { muscle_group: Some("legs".to_string()), ..query(20, None) };
+        assert_eq!(db_handler.get_history_data(user_id, &legs).unwrap().workouts.len(), 2);
+
+        let bench_only = HistoryQuery { exercise_id: Some(bench), ..query(20, None) };
+        assert_eq!(db_handler.get_history_data(user_id, &bench_only).unwrap().workouts.len(), 3);
+
+        let range = HistoryQuery {
+            from: NaiveDate::from_ymd_opt(2024, 3, 2),
+            to: NaiveDate::from_ymd_opt(2024, 3, 3),
+            ..query(20, None)
+        };
+        assert_eq!(db_handler.get_history_data(user_id, &range).unwrap().workouts.len(), 2);
+
+        let mut raw = io::Cursor::new(b"GET /history?muscle_group=Full%20Body&x=a+b HTTP/1.1\r\n\r\n".to_vec());
+        let request = read_request(&mut raw, 0).unwrap().unwrap();
+        assert_eq!(request.query_params["muscle_group"], "Full Body");
+        assert_eq!(request.query_params["x"], "a b");
+    }
+
+
+    #[test]
+    fn test_personal_records_detected_on_save() {
+        let db_handler = DatabaseHandler::from_connection(setup_database()).unwrap();
+        let (user_id, session_token) = register_and_login_user(&db_handler);
+        let bench = add_bench_press(&db_handler, &session_token);
+
+        let save = |day: u32, sets: Vec<Set>| {
+            let mut workout = sample_workout(bench, None);
+            workout.start_time = format!("2024-03-0{} 10:00:00", day);
+            workout.end_time = format!("2024-03-0{} 11:00:00", day);
+            workout.exercises[0].sets = sets;
+            db_handler.save_workout(workout, user_id).unwrap().workout_id
+        };
+        let prs = |workout_id: u32| -> u32 {
+            db_handler
+                .conn
+                .query_row("SELECT prs FROM workouts WHERE id = ?1", [workout_id], |row| row.get(0))
+                .unwrap()
+        };
+
+        // The first session is the baseline, not a record.
+        let first = save(1, vec![Set { reps: 5, weight: 100.0, ..Default::default() }, Set { reps: 8, weight: 80.0, ..Default::default() }]);
+        assert_eq!(prs(first), 0);
+
+        // Heavier top set, more reps at 80 and more total volume. The second
+        // set at 105 is no better than the first, so it earns nothing.
+        let second = save(2, vec![
+            Set { reps: 5, weight: 105.0, ..Default::default() },
+            Set { reps: 3, weight: 105.0, ..Default::default() },
+            Set { reps: 10, weight: 80.0, ..Default::default() },
+        ]);
+        let history = db_handler
+            .get_history_data(user_id, &HistoryQuery {
+                limit: Some(1),
+                cursor: None,
+                from: None,
+                to: None,
+                exercise_id: None,
+                muscle_group: None,
+            })
+            .unwrap();
+        let exercise = &history.workouts[0].exercises[0];
+        let sorted = |records: &[String]| {
+            let mut records = records.to_vec();
+            records.sort();
+            records
+        };
+        assert_eq!(sorted(&exercise.sets[0].records), ["estimated_1rm", "heaviest_weight"]);
+        assert!(exercise.sets[1].records.is_empty());
+        assert_eq!(sorted(&exercise.sets[2].records), ["reps_at_weight", "set_volume"]);
+        assert_eq!(exercise.records, ["session_volume"]);
+        assert_eq!(prs(second), 5);
+
+        // Logging an older, stronger session retroactively takes those records away.
+        let earlier = {
+            let mut workout = sample_workout(bench, None);
+            workout.start_time = "2024-02-01 10:00:00".to_string();
+            workout.end_time = "2024-02-01 11:00:00".to_string();
+            workout.exercises[0].sets = vec![Set { reps: 12, weight: 110.0, ..Default::default() }];
+            db_handler.save_workout(workout, user_id).unwrap().workout_id
+        };
+        assert_eq!(prs(earlier), 0);
+        assert_eq!(prs(second), 2);
+
+        assert!(db_handler.delete_workout(user_id, earlier).unwrap());
+        assert_eq!(prs(second), 5);
+    }
+
+    #[test]
+    fn test_incremental_records_match_full_rebuild() {
+        let db_handler = DatabaseHandler::from_connection(setup_database()).unwrap();
+        let (user_id, session_token) = register_and_login_user(&db_handler);
+        let bench = add_bench_press(&db_handler, &session_token);
+
+        type RecordRow = (u32, Option<u32>, String, f64, f64, Option<f64>, u32);
+        let records = || -> Vec<RecordRow> {
+            db_handler
+                .conn
+                .prepare(
+                    "SELECT pr.workout_id, pr.set_id, pr.record_type, pr.value, pr.previous_value, pr.weight, w.prs
+                     FROM personal_records pr JOIN workouts w ON w.id = pr.workout_id
+                     ORDER BY pr.workout_id, pr.record_type, pr.set_id",
+                )
+                .unwrap()
+                .query_map([], |row| {
+                    Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?, row.get(6)?))
+                })
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap()
+        };
+        // Each change only revisits later workouts; the result must match a rebuild from scratch.
+        let assert_matches_full_rebuild = || {
+            let incremental = records();
+            personal_records::rebuild_all(&db_handler.conn).unwrap();
+            assert_eq!(incremental, records());
+        };
+        let save = |day: u32, sets: &[(u32, f64)]| {
+            let mut workout = sample_workout(bench, None);
+            workout.start_time = format!("2024-03-0{} 10:00:00", day);
+            workout.end_time = format!("2024-03-0{} 11:00:00", day);
+            workout.exercises[0].sets =
+                sets.iter().map(|&(reps, weight)| Set { reps, weight, ..Default::default() }).collect();
+            let saved = db_handler.save_workout(workout, user_id).unwrap();
+            assert_matches_full_rebuild();
+            saved.workout_id
+        };
+
+        save(1, &[(5, 100.0), (5, 105.0)]);
+        save(5, &[(3, 110.0), (8, 100.0)]);
+        let middle = save(3, &[(6, 105.0), (5, 107.5)]);
+        let last = save(7, &[(4, 110.0), (9, 100.0)]);
+
+        let earlier = WorkoutUpdate { start_time: Some("2024-03-02 10:00:00".to_string()), ..Default::default() };
+        assert!(db_handler.update_workout(user_id, last, &earlier).unwrap());
+        assert_matches_full_rebuild();
+        assert!(db_handler.delete_workout(user_id, middle).unwrap());
+        assert_matches_full_rebuild();
+        assert!(!records().is_empty());
+    }
+
+
+    #[test]
+    fn test_one_rep_max_formulas() {
+        let close = |a: Option<f64>, b: f64| (a.unwrap() - b).abs() < 0.1;
+        assert!(close(OneRepMaxFormula::Epley.estimate(100.0, 10, None), 133.3));
+        assert!(close(OneRepMaxFormula::Brzycki.estimate(100.0, 10, None), 133.3));
+        assert!(close(OneRepMaxFormula::Lombardi.estimate(100.0, 10, None), 125.9));
+        assert!(close(OneRepMaxFormula::Rpe.estimate(100.0, 5, Some(8.0)), 100.0 / 0.811));
+        assert!(close(OneRepMaxFormula::Epley.estimate(100.0, 1, None), 100.0));
+        assert_eq!(OneRepMaxFormula::Epley.estimate(60.0, 20, None), None);
+        assert_eq!(OneRepMaxFormula::Rpe.estimate(60.0, 10, Some(7.0)), None);
+        assert_eq!("Brzycki".parse::<OneRepMaxFormula>(), Ok(OneRepMaxFormula::Brzycki));
+        assert!("wendler".parse::<OneRepMaxFormula>().is_err());
+    }
+
+    #[test]
+    fn test_one_rep_max_uses_reps_and_preference() {
+        let db_handler = DatabaseHandler::from_connection(setup_database()).unwrap();
+        let (user_id, session_token) = register_and_login_user(&db_handler);
+        let bench = add_bench_press(&db_handler, &session_token);
+
+        let mut workout = sample_workout(bench, None);
+        workout.start_time = "2024-03-15 10:00:00".to_string();
+        workout.end_time = "2024-03-15 11:00:00".to_string();
+        workout.exercises[0].sets = vec![
+            Set { reps: 1, weight: 100.0, ..Default::default() },
+            Set { reps: 10, weight: 100.0, ..Default::default() },
+            Set { reps: 30, weight: 110.0, ..Default::default() },
+        ];
+        db_handler.save_workout(workout, user_id).unwrap();
+
+        let today = NaiveDate::from_ymd_opt(2024, 4, 2).unwrap();
+        let range = TimeRange::last(8, Bucket::Month, today);
+        let maxes = db_handler.get_1_rep_maxes(user_id, bench, OneRepMaxFormula::Epley, &range).unwrap();
+        let maxes = serde_json::to_value(maxes).unwrap();
+        assert_eq!(maxes["formula"], "epley");
+        assert_eq!(maxes["starts"][6], "2024-03-01");
+        assert_eq!(maxes["data"][6], 133.3);
+        assert_eq!(maxes["sets"][6]["reps"], 10);
+        // Carried forward into April, and nothing before March.
+        assert_eq!(maxes["data"][7], 133.3);
+        assert!(maxes["sets"][0].is_null());
+
+        assert_eq!(db_handler.get_preferences(user_id).unwrap().one_rep_max_formula, OneRepMaxFormula::Epley);
+        let update = PreferencesUpdate { one_rep_max_formula: Some(OneRepMaxFormula::Lombardi), ..Default::default() };
+        let preferences = db_handler.update_preferences(user_id, &update).unwrap();
+        assert_eq!(preferences.one_rep_max_formula, OneRepMaxFormula::Lombardi);
+        let unchanged = db_handler.update_preferences(user_id, &PreferencesUpdate::default()).unwrap();
+        assert_eq!(unchanged.one_rep_max_formula, OneRepMaxFormula::Lombardi);
+    }
+
+
+    #[test]
+    fn test_time_ranges_and_bucketed_counts() {
+        let date = |s: &str| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+
+        let weeks = TimeRange::last(8, Bucket::Week, date("2024-03-06"));
+        assert_eq!(weeks.from, date("2024-01-15"));
+        assert_eq!(weeks.bucket_starts().len(), 8);
+
+        let params = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+        };
+        let days = TimeRange::from_query(&params(&[("bucket", "day")]), weeks).unwrap();
+        assert_eq!((days.from, days.to), (date("2024-02-28"), date("2024-03-06")));
+        let years = TimeRange::from_query(&params(&[("bucket", "year"), ("from", "2020-06-01")]), weeks).unwrap();
+        assert_eq!(years.bucket_starts(), [date("2020-01-01"), date("2021-01-01"), date("2022-01-01"), date("2023-01-01"), date("2024-01-01")]);
+        assert!(TimeRange::from_query(&params(&[("bucket", "fortnight")]), weeks).is_err());
+        assert!(TimeRange::from_query(&params(&[("bucket", "day"), ("from", "2000-01-01")]), weeks).is_err());
+        assert!(TimeRange::from_query(&params(&[("from", "2024-04-01")]), weeks).is_err());
+
+        let db_handler = DatabaseHandler::from_connection(setup_database()).unwrap();
+        let (user_id, session_token) = register_and_login_user(&db_handler);
+        let bench = add_bench_press(&db_handler, &session_token);
+        for start in ["2024-01-31 23:30:00", "2024-02-01 08:00:00", "2024-02-04 18:00:00", "2024-03-04 07:00:00"] {
+            let mut workout = sample_workout(bench, None);
+            workout.start_time = start.to_string();
+            workout.end_time = start.to_string();
+            db_handler.save_workout(workout, user_id).unwrap();
+        }
+
+        let months = TimeRange { from: date("2024-01-10"), to: date("2024-03-10"), bucket: Bucket::Month };
+        let counts = serde_json::to_value(db_handler.get_workouts_per_week(user_id, &months).unwrap()).unwrap();
+        assert_eq!(counts["labels"], serde_json::json!(["Jan", "Feb", "Mar"]));
+        assert_eq!(counts["data"], serde_json::json!([1, 2, 1]));
+
+        let weekly = TimeRange { from: date("2024-01-29"), to: date("2024-02-11"), bucket: Bucket::Week };
+        let counts = serde_json::to_value(db_handler.get_workouts_per_week(user_id, &weekly).unwrap()).unwrap();
+        assert_eq!(counts["starts"], serde_json::json!(["2024-01-29", "2024-02-05"]));
+        assert_eq!(counts["data"], serde_json::json!([3, 0]));
+    }
+
+
+    #[test]
+    fn test_times_follow_user_timezone() {
+        let db_handler = DatabaseHandler::from_connection(setup_database()).unwrap();
+        let (user_id, session_token) = register_and_login_user(&db_handler);
+        let bench = add_bench_press(&db_handler, &session_token);
+
+        let update = PreferencesUpdate {
+            timezone: Some("America/New_York".to_string()),
+            locale: Some("fr_FR".to_string()),
+            ..Default::default()
+        };
+        db_handler.update_preferences(user_id, &update).unwrap();
+        let clock = db_handler.user_clock(user_id).unwrap();
+
+        // Sunday 11pm in New York is already Monday in UTC.
+        let stored = user_time::to_storage(clock.parse_client_time("2024-03-03 23:00:00").unwrap());
+        assert_eq!(stored, "2024-03-04 04:00:00");
+        let offset = clock.parse_client_time("2024-03-03T23:00:00-05:00").unwrap();
+        assert_eq!(user_time::to_storage(offset), stored);
+        assert!(clock.parse_client_time("03/03/2024 11pm").is_none());
+
+        let mut workout = sample_workout(bench, None);
+        workout.start_time = stored.clone();
+        workout.end_time = "2024-03-04 05:15:00".to_string();
+        let saved = db_handler.save_workout(workout, user_id).unwrap();
+
+        let history = db_handler
+            .get_history_data(user_id, &HistoryQuery {
+                limit: Some(10),
+                cursor: None,
+                from: NaiveDate::from_ymd_opt(2024, 3, 3),
+                to: NaiveDate::from_ymd_opt(2024, 3, 3),
+                exercise_id: None,
+                muscle_group: None,
+            })
+            .unwrap();
+        assert_eq!(history.workouts.len(), 1);
+        assert_eq!(history.workouts[0].date, "dimanche, 03 mars");
+        assert_eq!(history.workouts[0].duration, "1h 15m");
+
+        let weeks = TimeRange {
+            from: NaiveDate::from_ymd_opt(2024, 2, 26).unwrap(),
+            to: NaiveDate::from_ymd_opt(2024, 3, 4).unwrap(),
+            bucket: Bucket::Week,
+        };
+        let counts = serde_json::to_value(db_handler.get_workouts_per_week(user_id, &weeks).unwrap()).unwrap();
+        assert_eq!(counts["data"], serde_json::json!([1, 0]));
+
+        let detail = db_handler.get_workout(user_id, saved.workout_id).unwrap().unwrap();
+        assert_eq!(detail.start_time, "2024-03-03T23:00:00-05:00");
+    }
+
+
+    #[test]
+    fn test_set_types_and_warmups() {
+        let db_handler = DatabaseHandler::from_connection(setup_database()).unwrap();
+        let (user_id, session_token) = register_and_login_user(&db_handler);
+        let bench = add_bench_press(&db_handler, &session_token);
+
+        let mut baseline = sample_workout(bench, None);
+        baseline.start_time = "2024-03-01 10:00:00".to_string();
+        db_handler.save_workout(baseline, user_id).unwrap();
+
+        let mut workout = sample_workout(bench, None);
+        workout.start_time = "2024-03-02 10:00:00".to_string();
+        workout.end_time = "2024-03-02 11:00:00".to_string();
+        workout.exercises[0].sets = vec![
+            // Heavier than anything before, but only a warm-up.
+            Set { reps: 5, weight: 120.0, set_type: SetType::Warmup, ..Default::default() },
+            Set { reps: 5, weight: 100.0, rir: Some(2), ..Default::default() },
+            Set {
+                reps: 8,
+                weight: 80.0,
+                set_type: SetType::Amrap,
+                rpe: Some(9.5),
+                notes: Some("Grip slipped".to_string()),
+                ..Default::default()
+            },
+        ];
+        let saved = db_handler.save_workout(workout, user_id).unwrap();
+
+        let history = db_handler
+            .get_history_data(user_id, &HistoryQuery {
+                limit: Some(1),
+                cursor: None,
+                from: None,
+                to: None,
+                exercise_id: None,
+                muscle_group: None,
+            })
+            .unwrap();
+        let sets = &history.workouts[0].exercises[0].sets;
+        assert!(sets[0].records.is_empty());
+        assert_eq!(sets[0].set_type, SetType::Warmup);
+        assert_eq!(history.workouts[0].total_volume, 500 + 640);
+
+        let previous = db_handler.get_previous_sets(user_id, bench).unwrap();
+        assert_eq!(previous.len(), 3);
+        assert_eq!(previous[1].rir, Some(2));
+        assert_eq!(previous[1].effective_rpe(), Some(8.0));
+        assert_eq!(previous[2].set_type, SetType::Amrap);
+        assert_eq!(previous[2].notes.as_deref(), Some("Grip slipped"));
+
+        let detail = serde_json::to_value(db_handler.get_workout(user_id, saved.workout_id).unwrap()).unwrap();
+        assert_eq!(detail["exercises"][0]["sets"][2]["set_type"], "amrap");
+        assert_eq!(detail["exercises"][0]["sets"][2]["rpe"], 9.5);
+
+        let set: Set = serde_json::from_str(r#"{"reps": 5, "weight": 60}"#).unwrap();
+        assert_eq!(set.set_type, SetType::Working);
+        assert!(Set { rpe: Some(8.5), ..Default::default() }.validate().is_ok());
+        assert!(Set { rpe: Some(8.3), ..Default::default() }.validate().is_err());
+        assert!(Set { rir: Some(11), ..Default::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn test_exercise_groups() {
+        let db_handler = DatabaseHandler::from_connection(setup_database()).unwrap();
+        let (user_id, session_token) = register_and_login_user(&db_handler);
+        let bench = add_bench_press(&db_handler, &session_token);
+        let row = db_handler
+            .add_exercise_to_user(ExerciseRequest {
+                user_id: session_token.clone(),
+                name: "Barbell Row".to_string(),
+                body_part: "Back".to_string(),
+                kind: ExerciseKind::WeightReps,
+            })
+            .unwrap();
+
+        let superset = |id| Some(ExerciseGroup { id, group_type: GroupType::Superset });
+        let mut workout = sample_workout(bench, None);
+        workout.exercises[0].group = superset(7);
+        workout.exercises.push(ExerciseRecord {
+            exercise_id: row,
+            sets: vec![Set { reps: 8, weight: 60.0, ..Default::default() }],
+            group: superset(7),
+        });
+        workout.exercises.push(ExerciseRecord { exercise_id: bench, sets: Vec::new(), group: None });
+        let saved = db_handler.save_workout(workout, user_id).unwrap();
+
+        let history = db_handler
+            .get_history_data(user_id, &HistoryQuery {
+                limit: Some(1),
+                cursor: None,
+                from: None,
+                to: None,
+                exercise_id: None,
+                muscle_group: None,
+            })
+            .unwrap();
+        let exercises = &history.workouts[0].exercises;
+        // Groups come back numbered by position, whatever id the client used.
+        assert_eq!(exercises[0].group, superset(1));
+        assert_eq!(exercises[1].group, superset(1));
+        assert_eq!(exercises[2].group, None);
+
+        let detail = db_handler.get_workout(user_id, saved.workout_id).unwrap().unwrap();
+        assert_eq!(detail.exercises[1].group, superset(1));
+
+        db_handler.delete_workout(user_id, saved.workout_id).unwrap();
+        assert_eq!(count_rows(&db_handler, "workout_exercise_groups"), 0);
+
+        let template: TemplateRequest = serde_json::from_str(&format!(
+            r#"{{"user_id": "{}", "name": "Push pull", "exercises": [
+                {{"exercise_id": {}, "sets": 3, "group": {{"id": 1, "group_type": "circuit"}}}},
+                {{"exercise_id": {}, "sets": 3, "group": {{"id": 1, "group_type": "circuit"}}}}
+            ]}}"#,
+            session_token, bench, row
+        ))
+        .unwrap();
+        db_handler.save_template(template).unwrap();
+        let templates = serde_json::to_value(db_handler.get_templates(user_id).unwrap()).unwrap();
+        assert_eq!(templates[0]["exercises"][1]["group"]["group_type"], "circuit");
+
+        assert!(validate_groups(&[superset(1), None, superset(1)]).is_err());
+        assert!(validate_groups(&[superset(1), superset(1), superset(1)]).is_err());
+        assert!(validate_groups(&[
+            superset(1),
+            Some(ExerciseGroup { id: 1, group_type: GroupType::Circuit }),
+        ])
+        .is_err());
+        assert!(validate_groups(&[None, superset(2), superset(2), None]).is_ok());
+    }
+
+    #[test]
+    fn test_exercise_kinds() {
+        let conn = setup_database();
+        let db_handler = DatabaseHandler::from_connection(conn).unwrap();
+        let (user_id, session_token) = register_and_login_user(&db_handler);
+        let exercises = db_handler.get_user_exercises(user_id, false).unwrap();
+        let side_plank = exercises.iter().find(|e| e.name == "Side Plank").unwrap();
+        assert_eq!(side_plank.kind, ExerciseKind::Timed);
+
+        let add = |name: &str, kind| {
+            db_handler
+                .add_exercise_to_user(ExerciseRequest {
+                    user_id: session_token.clone(),
+                    name: name.to_string(),
+                    body_part: "Other".to_string(),
+                    kind,
+                })
+                .unwrap()
+        };
+        let pull_up = add("Pull-Up", ExerciseKind::Bodyweight);
+        let assisted_dip = add("Assisted Dip", ExerciseKind::Assisted);
+        let plank = add("Plank", ExerciseKind::Timed);
+        let run = add("Run", ExerciseKind::Cardio);
+
+        let session = |day: u32, plank_seconds: u32| {
+            let mut workout = sample_workout(pull_up, None);
+            workout.start_time = format!("2024-03-0{} 10:00:00", day);
+            workout.end_time = format!("2024-03-0{} 11:00:00", day);
+            workout.exercises = vec![
+                ExerciseRecord {
+                    exercise_id: pull_up,
+                    sets: vec![
+                        Set { reps: 5, weight: 10.0, bodyweight: Some(80.0), ..Default::default() },
+                        Set { reps: 10, weight: 0.0, bodyweight: Some(80.0), ..Default::default() },
+                    ],
+                    group: None,
+                },
+                ExerciseRecord {
+                    exercise_id: assisted_dip,
+                    sets: vec![Set { reps: 8, weight: 20.0, bodyweight: Some(80.0), ..Default::default() }],
+                    group: None,
+                },
+                ExerciseRecord {
+                    exercise_id: plank,
+                    sets: vec![Set { duration_seconds: Some(plank_seconds), ..Default::default() }],
+                    group: None,
+                },
+                ExerciseRecord {
+                    exercise_id: run,
+                    sets: vec![Set { distance_meters: Some(5000.0), duration_seconds: Some(1500), ..Default::default() }],
+                    group: None,
+                },
+            ];
+            workout
+        };
+        db_handler.save_workout(session(1, 60), user_id).unwrap();
+        db_handler.save_workout(session(2, 90), user_id).unwrap();
+
+        let history = db_handler
+            .get_history_data(user_id, &HistoryQuery {
+                limit: Some(1),
+                cursor: None,
+                from: None,
+                to: None,
+                exercise_id: None,
+                muscle_group: None,
+            })
+            .unwrap();
+        let workout = &history.workouts[0];
+        // Pull-ups count bodyweight plus added weight, dips bodyweight less assistance.
+        assert_eq!(workout.total_volume, 90 * 5 + 80 * 10 + 60 * 8);
+        assert_eq!(workout.exercises[2].kind, ExerciseKind::Timed);
+        assert_eq!(workout.exercises[2].sets[0].duration_seconds, Some(90));
+        assert_eq!(workout.exercises[2].sets[0].records, ["longest_duration"]);
+        assert!(workout.exercises[3].sets[0].records.is_empty());
+
+        let best_sets: HashMap<u32, Option<String>> = db_handler
+            .get_user_exercises(user_id, false)
+            .unwrap()
+            .into_iter()
+            .map(|exercise| (exercise.id, exercise.best_set))
+            .collect();
+        assert_eq!(best_sets[&pull_up].as_deref(), Some("10 reps"));
+        assert_eq!(best_sets[&assisted_dip].as_deref(), Some("8 x -20kg"));
+        assert_eq!(best_sets[&plank].as_deref(), Some("1:30"));
+        assert_eq!(best_sets[&run].as_deref(), Some("5 km in 25:00"));
+
+        assert!(ExerciseKind::Cardio.validate(&Set::default()).is_err());
+        assert!(ExerciseKind::Timed.validate(&Set { reps: 1, ..Default::default() }).is_ok());
+        assert_eq!(ExerciseKind::Assisted.load(20.0, None), None);
+    }
+
+    #[test]
+    fn test_weight_units() {
+        let db_handler = DatabaseHandler::from_connection(setup_database()).unwrap();
+        let (user_id, session_token) = register_and_login_user(&db_handler);
+        let bench = add_bench_press(&db_handler, &session_token);
+        db_handler
+            .update_preferences(user_id, &PreferencesUpdate { weight_unit: Some(WeightUnit::Lb), ..Default::default() })
+            .unwrap();
+
+        let mut workout = sample_workout(bench, None);
+        workout.exercises[0].sets = vec![
+            Set { reps: 5, weight: 225.0, ..Default::default() },
+            Set { reps: 5, weight: 100.0, unit: Some(WeightUnit::Kg), ..Default::default() },
+        ];
+        db_handler.save_workout(workout, user_id).unwrap();
+
+        // Stored in kg, with the unit each set was entered in.
+        let stored: Vec<(f64, String)> = db_handler
+            .conn
+            .prepare("SELECT weight, weight_unit FROM sets ORDER BY set_number")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert!((stored[0].0 - 102.058).abs() < 0.001);
+        assert_eq!(stored[0].1, "lb");
+        assert_eq!(stored[1], (100.0, "kg".to_string()));
+
+        let history_query = HistoryQuery {
+            limit: Some(1),
+            cursor: None,
+            from: None,
+            to: None,
+            exercise_id: None,
+            muscle_group: None,
+        };
+        let history = db_handler.get_history_data(user_id, &history_query).unwrap();
+        let sets = &history.workouts[0].exercises[0].sets;
+        assert_eq!(sets[0].weight, "225");
+        assert_eq!(sets[1].weight, "220.46");
+        assert_eq!(sets[1].plate_weight, "220");
+        assert_eq!(sets[1].unit, WeightUnit::Lb);
+        assert_eq!(history.workouts[0].total_volume, 1125 + 1102);
+
+        let previous = db_handler.get_previous_sets(user_id, bench).unwrap();
+        assert_eq!(previous[0].weight, 225.0);
+        assert_eq!(previous[0].unit, Some(WeightUnit::Lb));
+
+        let exercises = db_handler.get_user_exercises(user_id, false).unwrap();
+        let bench_press = exercises.iter().find(|exercise| exercise.id == bench).unwrap();
+        assert_eq!(bench_press.best_set.as_deref(), Some("5 x 225lb"));
+
+        let range = TimeRange::last(1, Bucket::Year, NaiveDate::from_ymd_opt(2024, 3, 1).unwrap());
+        let maxes = serde_json::to_value(
+            db_handler.get_1_rep_maxes(user_id, bench, OneRepMaxFormula::Epley, &range).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(maxes["unit"], "lb");
+        assert_eq!(maxes["data"][0], 262.5);
+
+        db_handler
+            .update_preferences(user_id, &PreferencesUpdate { weight_unit: Some(WeightUnit::Kg), ..Default::default() })
+            .unwrap();
+        let history = db_handler.get_history_data(user_id, &history_query).unwrap();
+        assert_eq!(history.workouts[0].exercises[0].sets[0].weight, "102.06");
+        assert_eq!(history.workouts[0].exercises[0].sets[0].plate_weight, "102.5");
+        assert_eq!(WeightUnit::Kg.round_to_plates(101.2), 100.0);
+        assert_eq!("lbs".parse::<WeightUnit>(), Ok(WeightUnit::Lb));
+    }
+
+    #[test]
+    fn test_edit_archive_and_merge_exercises() {
+        let db_handler = DatabaseHandler::from_connection(setup_database()).unwrap();
+        let (user_id, session_token) = register_and_login_user(&db_handler);
+        let bench = add_bench_press(&db_handler, &session_token);
+        let typo = db_handler
+            .add_exercise_to_user(ExerciseRequest {
+                user_id: session_token.clone(),
+                name: "Bench Pres".to_string(),
+                body_part: "Chest".to_string(),
+                kind: ExerciseKind::WeightReps,
+            })
+            .unwrap();
+
+        assert!(db_handler.exercise_name_taken(user_id, " bench press ", None).unwrap());
+        assert!(!db_handler.exercise_name_taken(user_id, "Bench press", Some(bench)).unwrap());
+
+        let rename = ExerciseUpdate { description: Some("Flat, paused".to_string()), ..Default::default() };
+        assert!(db_handler.update_exercise(user_id, bench, &rename).unwrap());
+        assert!(!db_handler.update_exercise(user_id + 1, bench, &rename).unwrap());
+        let renamed = ExerciseUpdate { name: Some("Flat Bench Press ".to_string()), ..Default::default() };
+        db_handler.update_exercise(user_id, bench, &renamed).unwrap();
+        let exercises = db_handler.get_user_exercises(user_id, false).unwrap();
+        let exercise = exercises.iter().find(|exercise| exercise.id == bench).unwrap();
+        assert_eq!(exercise.name, "Flat Bench Press");
+        assert_eq!(exercise.description.as_deref(), Some("Flat, paused"));
+
+        // Heavier sets logged under the misspelt name.
+        db_handler.save_workout(sample_workout(bench, None), user_id).unwrap();
+        let mut heavier = sample_workout(typo, None);
+        heavier.start_time = "2024-03-02 10:00:00".to_string();
+        heavier.end_time = "2024-03-02 11:00:00".to_string();
+        heavier.exercises[0].sets[1].weight = 110.0;
+        let template: TemplateRequest = serde_json::from_str(&format!(
+            r#"{{"user_id": "{}", "name": "Push", "exercises": [{{"exercise_id": {},
+                "targets": [{{"reps_min": 5, "weight": 100}}], "progression": {{"scheme": "linear", "increment": 2.5}}}}]}}"#,
+            session_token, typo
+        ))
+        .unwrap();
+        let template_id = db_handler.save_template(template).unwrap();
+        heavier.template_id = Some(template_id);
+        let heavier = db_handler.save_workout(heavier, user_id).unwrap();
+        db_handler.set_training_max(user_id, typo, 120.0).unwrap();
+        let both: TemplateRequest = serde_json::from_str(&format!(
+            r#"{{"user_id": "{}", "name": "Both", "exercises": [
+                {{"exercise_id": {}, "targets": [{{"reps_min": 5}}]}},
+                {{"exercise_id": {}, "targets": [{{"reps_min": 8}}, {{"reps_min": 8}}]}}
+            ]}}"#,
+            session_token, bench, typo
+        ))
+        .unwrap();
+        db_handler.save_template(both).unwrap();
+
+        assert!(!db_handler.merge_exercises(user_id, typo, typo).unwrap());
+        assert!(db_handler.merge_exercises(user_id, typo, bench).unwrap());
+        let remaining: Vec<u32> =
+            db_handler.get_user_exercises(user_id, true).unwrap().iter().map(|exercise| exercise.id).collect();
+        assert!(!remaining.contains(&typo));
+        let templates = db_handler.get_templates(user_id).unwrap();
+        assert_eq!(templates[0].exercises[0].exercise_id, bench);
+        let both = templates.iter().find(|template| template.name == "Both").unwrap();
+        assert_eq!(both.exercises.len(), 1);
+        assert_eq!(both.exercises[0].targets.len(), 1);
+        assert_eq!(count_rows(&db_handler, "template_sets"), 2);
+        assert_eq!(db_handler.progression_log(user_id, template_id).unwrap()[0].exercise_id, bench);
+        let training_maxes = db_handler.get_training_maxes(user_id).unwrap();
+        assert_eq!((training_maxes[0].exercise_id, training_maxes[0].weight), (bench, 120.0));
+        // The merged history now holds a record over the earlier bench session.
+        let history = db_handler
+            .get_history_data(user_id, &HistoryQuery {
+                limit: Some(1),
+                cursor: None,
+                from: None,
+                to: None,
+                exercise_id: Some(bench),
+                muscle_group: None,
+            })
+            .unwrap();
+        assert_eq!(history.workouts[0].id, heavier.workout_id);
+        assert!(history.workouts[0].prs > 0);
+
+        let archive = ExerciseUpdate { archived: Some(true), ..Default::default() };
+        db_handler.update_exercise(user_id, bench, &archive).unwrap();
+        let listed = |include_archived| {
+            db_handler
+                .get_user_exercises(user_id, include_archived)
+                .unwrap()
+                .iter()
+                .any(|exercise| exercise.id == bench)
+        };
+        assert!(!listed(false));
+        assert!(listed(true));
+    }
+
+    #[test]
+    fn test_master_catalogue() {
+        let conn = setup_database();
+        let db_handler = DatabaseHandler::from_connection(conn).unwrap();
+        let (user_id, session_token) = register_and_login_user(&db_handler);
+        let exercises = db_handler.get_user_exercises(user_id, false).unwrap();
+        assert_eq!(exercises.len(), CATALOGUE.len());
+        let unlinked: u32 = db_handler
+            .conn
+            .query_row(
+                "SELECT COUNT(*) FROM user_exercises WHERE user_id = ?1 AND master_exercise_id IS NULL",
+                [user_id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(unlinked, 0);
+
+        // Seeding again only updates what is already there.
+        catalogue::seed(&db_handler.conn).unwrap();
+        assert_eq!(db_handler.get_user_exercises(user_id, false).unwrap().len(), CATALOGUE.len());
+
+        let carry = db_handler
+            .add_exercise_to_user(ExerciseRequest {
+                user_id: session_token,
+                name: "Farmer's Walk".to_string(),
+                body_part: "Grip".to_string(),
+                kind: ExerciseKind::WeightReps,
+            })
+            .unwrap();
+        db_handler
+            .conn
+            .execute_batch(
+                "INSERT INTO master_exercises (name, muscle_group, catalogue_version) VALUES ('Farmers Carry', 'Grip', 2);
+                 INSERT INTO master_exercise_aliases (master_exercise_id, alias)
+                 VALUES (last_insert_rowid(), 'farmer''s walk');
+                 INSERT INTO master_exercises (name, muscle_group, catalogue_version) VALUES ('Sled Push', 'Legs', 2);",
+            )
+            .unwrap();
+        assert_eq!(catalogue::sync_users(&db_handler.conn).unwrap(), 1);
+        assert_eq!(catalogue::sync_users(&db_handler.conn).unwrap(), 0);
+
+        let exercises = db_handler.get_user_exercises(user_id, false).unwrap();
+        assert_eq!(exercises.len(), CATALOGUE.len() + 2);
+        assert!(exercises.iter().any(|exercise| exercise.name == "Sled Push"));
+        assert!(!exercises.iter().any(|exercise| exercise.name == "Farmers Carry"));
+        let linked: Option<u32> = db_handler
+            .conn
+            .query_row("SELECT master_exercise_id FROM user_exercises WHERE id = ?1", [carry], |row| row.get(0))
+            .unwrap();
+        assert!(linked.is_some());
+    }
+
+    #[test]
+    fn test_template_management() {
+        let db_handler = DatabaseHandler::from_connection(setup_database()).unwrap();
+        let (user_id, session_token) = register_and_login_user(&db_handler);
+        let bench = add_bench_press(&db_handler, &session_token);
+        let row = db_handler
+            .add_exercise_to_user(ExerciseRequest {
+                user_id: session_token.clone(),
+                name: "Barbell Row".to_string(),
+                body_part: "Back".to_string(),
+                kind: ExerciseKind::WeightReps,
+            })
+            .unwrap();
+        let template: TemplateRequest = serde_json::from_str(&format!(
+            r#"{{"user_id": "{}", "name": "Upper", "exercises": [
+                {{"exercise_id": {}, "sets": 3}},
+                {{"exercise_id": {}, "sets": 4, "group": {{"id": 1, "group_type": "circuit"}}}},
+                {{"exercise_id": {}, "sets": 2, "group": {{"id": 1, "group_type": "circuit"}}}}
+            ]}}"#,
+            session_token, bench, row, bench
+        ))
+        .unwrap();
+        let template_id = db_handler.save_template(template).unwrap();
+
+        let copy_id = db_handler.duplicate_template(user_id, template_id, None).unwrap().unwrap();
+        assert!(db_handler.duplicate_template(user_id + 1, template_id, None).unwrap().is_none());
+
+        // Reorder: the row moves to the front and the circuit is dropped.
+        let update: TemplateUpdate = serde_json::from_str(&format!(
+            r#"{{"name": " Upper A ", "exercises": [{{"exercise_id": {}, "sets": 5}}, {{"exercise_id": {}, "sets": 3}}]}}"#,
+            row, bench
+        ))
+        .unwrap();
+        assert!(db_handler.update_template(user_id, template_id, &update).unwrap());
+        assert!(!db_handler.update_template(user_id + 1, template_id, &update).unwrap());
+
+        let templates = db_handler.get_templates(user_id).unwrap();
+        let updated = templates.iter().find(|template| template.id == template_id).unwrap();
+        assert_eq!(updated.name, "Upper A");
+        let order: Vec<(u32, u32, u32)> =
+            updated.exercises.iter().map(|exercise| (exercise.position, exercise.exercise_id, exercise.sets)).collect();
+        assert_eq!(order, vec![(1, row, 5), (2, bench, 3)]);
+        assert!(updated.exercises.iter().all(|exercise| exercise.group.is_none()));
+
+        let copy = templates.iter().find(|template| template.id == copy_id).unwrap();
+        assert_eq!(copy.name, "Upper (copy)");
+        assert_eq!(copy.exercises.len(), 3);
+        assert_eq!(copy.exercises[2].group.map(|group| group.group_type), Some(GroupType::Circuit));
+
+        let mut workout = sample_workout(bench, None);
+        workout.template_id = Some(template_id);
+        let workout_id = db_handler.save_workout(workout, user_id).unwrap().workout_id;
+        let program: ProgramRequest =
+            serde_json::from_str(&format!(r#"{{"name": "Split", "weeks": [{{"days": [{}, {}]}}]}}"#, template_id, copy_id))
+                .unwrap();
+        db_handler.create_program(user_id, &program).unwrap().unwrap();
+
+        assert!(db_handler.delete_template(user_id, template_id).unwrap());
+        assert!(!db_handler.delete_template(user_id, template_id).unwrap());
+        assert_eq!(db_handler.get_templates(user_id).unwrap().len(), 1);
+        assert_eq!(count_rows(&db_handler, "template_exercise_groups"), 1);
+        assert_eq!(count_rows(&db_handler, "program_days"), 1);
+        let detail = db_handler.get_workout(user_id, workout_id).unwrap().unwrap();
+        assert_eq!(detail.template_id, None);
+    }
+
+    #[test]
+    fn test_template_set_targets() {
+        let db_handler = DatabaseHandler::from_connection(setup_database()).unwrap();
+        let (user_id, session_token) = register_and_login_user(&db_handler);
+        let bench = add_bench_press(&db_handler, &session_token);
+        // Best set 5 x 105kg puts the estimated 1RM at 122.5kg.
+        db_handler.save_workout(sample_workout(bench, None), user_id).unwrap();
+
+        let template: TemplateRequest = serde_json::from_str(&format!(
+            r#"{{"user_id": "{}", "name": "Heavy bench", "exercises": [{{"exercise_id": {}, "targets": [
+                {{"reps_min": 3, "reps_max": 5, "percent_e1rm": 80, "rpe": 8, "rest_seconds": 180}},
+                {{"reps_min": 8, "reps_max": 8, "weight": 60}}
+            ]}}]}}"#,
+            session_token, bench
+        ))
+        .unwrap();
+        assert!(template.exercises[0].validate().is_ok());
+        let template_id = db_handler.save_template(template).unwrap();
+        let copy_id = db_handler.duplicate_template(user_id, template_id, None).unwrap().unwrap();
+
+        for template in db_handler.get_templates(user_id).unwrap() {
+            let exercise = &template.exercises[0];
+            assert_eq!(exercise.sets, 2);
+            assert_eq!(exercise.targets.len(), 2);
+            assert_eq!(exercise.targets[0].suggested_weight, Some(97.5));
+            assert_eq!(exercise.targets[0].rest_seconds, Some(180));
+            assert_eq!(exercise.targets[1].weight, Some(60.0));
+            assert_eq!(exercise.targets[1].suggested_weight, Some(60.0));
+        }
+
+        let update = PreferencesUpdate { weight_unit: Some(WeightUnit::Lb), ..Default::default() };
+        db_handler.update_preferences(user_id, &update).unwrap();
+        let templates = db_handler.get_templates(user_id).unwrap();
+        let copy = templates.iter().find(|template| template.id == copy_id).unwrap();
+        assert_eq!(copy.exercises[0].targets[1].weight, Some(132.28));
+
+        let mismatched: TemplateExerciseRequest =
+            serde_json::from_str(r#"{"exercise_id": 1, "sets": 3, "targets": [{"reps_max": 5}]}"#).unwrap();
+        assert!(mismatched.validate().is_err());
+        let inverted: TemplateExerciseRequest =
+            serde_json::from_str(r#"{"exercise_id": 1, "targets": [{"reps_min": 6, "reps_max": 4}]}"#).unwrap();
+        assert!(inverted.validate().is_err());
+    }
+
+    #[test]
+    fn test_start_workout_from_template() {
+        let db_handler = DatabaseHandler::from_connection(setup_database()).unwrap();
+        let (user_id, session_token) = register_and_login_user(&db_handler);
+        let bench = add_bench_press(&db_handler, &session_token);
+        let row = db_handler
+            .add_exercise_to_user(ExerciseRequest {
+                user_id: session_token.clone(),
+                name: "Barbell Row".to_string(),
+                body_part: "Back".to_string(),
+                kind: ExerciseKind::WeightReps,
+            })
+            .unwrap();
+        db_handler.save_workout(sample_workout(bench, None), user_id).unwrap();
+
+        let template: TemplateRequest = serde_json::from_str(&format!(
+            r#"{{"user_id": "{}", "name": "Upper", "exercises": [
+                {{"exercise_id": {}, "sets": 3}},
+                {{"exercise_id": {}, "targets": [{{"reps_min": 8, "weight": 50, "rpe": 7}}]}}
+            ]}}"#,
+            session_token, bench, row
+        ))
+        .unwrap();
+        let template_id = db_handler.save_template(template).unwrap();
+        let other_user = db_handler.register_user("someone_else", "password123").unwrap();
+        assert!(db_handler.start_template(other_user, template_id).unwrap().is_none());
+
+        let draft = db_handler.start_template(user_id, template_id).unwrap().unwrap();
+        assert_eq!(draft.template_id, template_id);
+        let prefilled: Vec<(u32, f64)> = draft.exercises[0].sets.iter().map(|set| (set.reps, set.weight)).collect();
+        // Three sets against two last time: the extra one repeats the last.
+        assert_eq!(prefilled, vec![(5, 100.0), (5, 105.0), (5, 105.0)]);
+        assert_eq!(draft.exercises[0].previous.len(), 2);
+        let target_set = &draft.exercises[1].sets[0];
+        assert_eq!((target_set.reps, target_set.weight, target_set.rpe), (8, 50.0, Some(7.0)));
+        assert!(draft.exercises[1].previous.is_empty());
+
+        let mut workout = sample_workout(bench, None);
+        workout.template_id = Some(draft.template_id);
+        let saved = db_handler.save_workout(workout, user_id).unwrap();
+        assert_eq!(db_handler.get_workout(user_id, saved.workout_id).unwrap().unwrap().template_id, Some(template_id));
+        let mut foreign = sample_workout(bench, None);
+        foreign.template_id = Some(template_id + 100);
+        let saved = db_handler.save_workout(foreign, user_id).unwrap();
+        assert_eq!(db_handler.get_workout(user_id, saved.workout_id).unwrap().unwrap().template_id, None);
+    }
+
+    #[test]
+    fn test_programs_and_todays_workout() {
+        let db_handler = DatabaseHandler::from_connection(setup_database()).unwrap();
+        let (user_id, session_token) = register_and_login_user(&db_handler);
+        let bench = add_bench_press(&db_handler, &session_token);
+        assert!(db_handler.set_training_max(user_id, bench, 100.0).unwrap());
+        assert_eq!(db_handler.get_training_maxes(user_id).unwrap()[0].weight, 100.0);
+
+        let save_template = |name: &str, exercise: &str| {
+            let template: TemplateRequest = serde_json::from_str(&format!(
+                r#"{{"user_id": "{}", "name": "{}", "exercises": [{}]}}"#,
+                session_token, name, exercise
+            ))
+            .unwrap();
+            db_handler.save_template(template).unwrap()
+        };
+        let heavy = save_template(
+            "Heavy",
+            &format!(r#"{{"exercise_id": {}, "targets": [{{"reps_min": 5, "percent_training_max": 85}}]}}"#, bench),
+        );
+        let light = save_template("Light", &format!(r#"{{"exercise_id": {}, "sets": 1}}"#, bench));
+
+        let program: ProgramRequest = serde_json::from_str(&format!(
+            r#"{{"name": "Block", "weeks": [{{"days": [{}, {}]}}, {{"deload": true, "days": [{}]}}]}}"#,
+            heavy, light, heavy
+        ))
+        .unwrap();
+        let program_id = db_handler.create_program(user_id, &program).unwrap().unwrap();
+        assert_eq!(db_handler.get_programs(user_id).unwrap()[0].weeks, program.weeks);
+        assert!(db_handler.todays_workout(user_id).unwrap().is_none());
+        assert!(db_handler.start_program(user_id, program_id).unwrap());
+
+        let log = |template_id: u32| {
+            let mut workout = sample_workout(bench, None);
+            workout.template_id = Some(template_id);
+            db_handler.save_workout(workout, user_id).unwrap();
+            db_handler.todays_workout(user_id).unwrap().unwrap()
+        };
+        let today = db_handler.todays_workout(user_id).unwrap().unwrap();
+        assert_eq!((today.position.week, today.position.day, today.position.template_id), (1, 1, heavy));
+        assert_eq!(today.workout.exercises[0].sets[0].weight, 85.0);
+
+        let today = log(heavy);
+        assert_eq!((today.position.week, today.position.day), (1, 2));
+        // Logging a template that isn't due doesn't move the program on.
+        let today = log(heavy);
+        assert_eq!((today.position.week, today.position.day), (1, 2));
+
+        let today = log(light);
+        assert_eq!((today.position.week, today.position.day, today.position.deload), (2, 1, true));
+        assert_eq!(today.workout.exercises[0].sets[0].weight, 50.0);
+
+        let today = log(heavy);
+        assert_eq!((today.position.cycle, today.position.week, today.position.day), (2, 1, 1));
+
+        // A day whose template is deleted is skipped rather than stalling the program.
+        assert!(db_handler.delete_template(user_id, light).unwrap());
+        let today = log(heavy);
+        assert_eq!((today.position.cycle, today.position.week, today.position.day), (2, 2, 1));
+
+        assert!(db_handler.delete_program(user_id, program_id).unwrap());
+        assert!(db_handler.todays_workout(user_id).unwrap().is_none());
+        for table in ["program_weeks", "program_days", "user_programs"] {
+            assert_eq!(count_rows(&db_handler, table), 0);
+        }
+    }
+
+    #[test]
+    fn test_progression_rules() {
+        let db_handler = DatabaseHandler::from_connection(setup_database()).unwrap();
+        let (user_id, session_token) = register_and_login_user(&db_handler);
+        let bench = add_bench_press(&db_handler, &session_token);
+        let row = db_handler
+            .add_exercise_to_user(ExerciseRequest {
+                user_id: session_token.clone(),
+                name: "Barbell Row".to_string(),
+                body_part: "Back".to_string(),
+                kind: ExerciseKind::WeightReps,
+            })
+            .unwrap();
+        let template: TemplateRequest = serde_json::from_str(&format!(
+            r#"{{"user_id": "{}", "name": "Strength", "exercises": [
+                {{"exercise_id": {}, "targets": [{{"reps_min": 5, "weight": 100}}, {{"reps_min": 5, "weight": 100}}],
+                  "progression": {{"scheme": "linear", "increment": 2.5, "deload_after": 3}}}},
+                {{"exercise_id": {}, "targets": [{{"reps_min": 8, "reps_max": 12, "weight": 50}}],
+                  "progression": {{"scheme": "double_progression", "increment": 2.5}}}}
+            ]}}"#,
+            session_token, bench, row
+        ))
+        .unwrap();
+        assert!(template.exercises.iter().all(|exercise| exercise.validate().is_ok()));
+        let template_id = db_handler.save_template(template).unwrap();
+
+        let log = |exercise_id: u32, sets: &[(u32, f64)]| {
+            let mut workout = sample_workout(exercise_id, None);
+            workout.template_id = Some(template_id);
+            workout.exercises[0].sets =
+                sets.iter().map(|&(reps, weight)| Set { reps, weight, ..Default::default() }).collect();
+            db_handler.save_workout(workout, user_id).unwrap();
+        };
+        let target_weights = |position: usize| -> Vec<Option<f64>> {
+            let templates = db_handler.get_templates(user_id).unwrap();
+            templates[0].exercises[position].targets.iter().map(|target| target.weight).collect()
+        };
+
+        log(bench, &[(5, 100.0), (5, 100.0)]);
+        assert_eq!(target_weights(0), vec![Some(102.5), Some(102.5)]);
+        // Three sessions short of the reps cut the weight by 10%.
+        log(bench, &[(5, 102.5), (4, 102.5)]);
+        log(bench, &[(5, 102.5), (3, 102.5)]);
+        assert_eq!(target_weights(0), vec![Some(102.5), Some(102.5)]);
+        // Editing the template keeps the count of failed sessions.
+        let update: TemplateUpdate = serde_json::from_str(&format!(
+            r#"{{"exercises": [
+                {{"exercise_id": {}, "targets": [{{"reps_min": 8, "reps_max": 12, "weight": 50}}],
+                  "progression": {{"scheme": "double_progression", "increment": 2.5}}}},
+                {{"exercise_id": {}, "targets": [{{"reps_min": 5, "weight": 102.5}}, {{"reps_min": 5, "weight": 102.5}}],
+                  "progression": {{"scheme": "linear", "increment": 2.5, "deload_after": 3}}}}
+            ]}}"#,
+            row, bench
+        ))
+        .unwrap();
+        assert!(db_handler.update_template(user_id, template_id, &update).unwrap());
+        log(bench, &[(4, 102.5), (4, 102.5)]);
+        assert_eq!(target_weights(1), vec![Some(92.5), Some(92.5)]);
+
+        // Inside the rep range nothing moves; the top of it adds weight.
+        log(row, &[(10, 50.0)]);
+        assert_eq!(target_weights(0), vec![Some(50.0)]);
+        log(row, &[(12, 50.0)]);
+        assert_eq!(target_weights(0), vec![Some(52.5)]);
+
+        let entries = db_handler.progression_log(user_id, template_id).unwrap();
+        let outcomes: Vec<&str> = entries.iter().map(|entry| entry.outcome.as_str()).collect();
+        assert_eq!(outcomes, vec!["progressed", "held", "deloaded", "failed", "failed", "progressed"]);
+        assert_eq!((entries[1].weight_before, entries[1].weight_after), (Some(50.0), Some(50.0)));
+        assert_eq!((entries[2].weight_before, entries[2].weight_after), (Some(102.5), Some(92.5)));
+        assert_eq!(entries[0].exercise_id, row);
+        let update = PreferencesUpdate { timezone: Some("Asia/Kolkata".to_string()), ..Default::default() };
+        db_handler.update_preferences(user_id, &update).unwrap();
+        let entries = db_handler.progression_log(user_id, template_id).unwrap();
+        assert!(entries[0].created_at.ends_with("+05:30"));
+
+        let no_weights: TemplateExerciseRequest = serde_json::from_str(
+            r#"{"exercise_id": 1, "targets": [{"reps_min": 5}], "progression": {"scheme": "linear", "increment": 2.5}}"#,
+        )
+        .unwrap();
+        assert!(no_weights.validate().is_err());
+
+        // A light weight that would round back to itself still drops a plate step.
+        assert_eq!(progression::deloaded_weight(10.0, 10.0, WeightUnit::Kg), 7.5);
+        assert_eq!(progression::deloaded_weight(100.0, 10.0, WeightUnit::Kg), 90.0);
+
+        assert!(db_handler.delete_template(user_id, template_id).unwrap());
+        assert_eq!(count_rows(&db_handler, "progression_log"), 0);
+    }
+
+    #[test]
+    fn test_progression_undone_with_workout() {
+        let db_handler = DatabaseHandler::from_connection(setup_database()).unwrap();
+        let (user_id, session_token) = register_and_login_user(&db_handler);
+        let bench = add_bench_press(&db_handler, &session_token);
+        let template: TemplateRequest = serde_json::from_str(&format!(
+            r#"{{"user_id": "{}", "name": "Strength", "exercises": [
+                {{"exercise_id": {}, "targets": [{{"reps_min": 5, "weight": 100}}],
+                  "progression": {{"scheme": "linear", "increment": 2.5}}}}
+            ]}}"#,
+            session_token, bench
+        ))
+        .unwrap();
+        let template_id = db_handler.save_template(template).unwrap();
+        let light: TemplateRequest = serde_json::from_str(&format!(
+            r#"{{"user_id": "{}", "name": "Light", "exercises": [{{"exercise_id": {}, "sets": 1}}]}}"#,
+            session_token, bench
+        ))
+        .unwrap();
+        let light = db_handler.save_template(light).unwrap();
+        let program: ProgramRequest =
+            serde_json::from_str(&format!(r#"{{"name": "Block", "weeks": [{{"days": [{}, {}]}}]}}"#, template_id, light))
+                .unwrap();
+        let program_id = db_handler.create_program(user_id, &program).unwrap().unwrap();
+        assert!(db_handler.start_program(user_id, program_id).unwrap());
+
+        let sets = |reps: u32, weight: f64| {
+            let mut exercise = sample_workout(bench, None).exercises.remove(0);
+            exercise.sets = vec![Set { reps, weight, ..Default::default() }];
+            exercise
+        };
+        let log = |reps: u32, weight: f64| {
+            let mut workout = sample_workout(bench, None);
+            workout.template_id = Some(template_id);
+            workout.exercises = vec![sets(reps, weight)];
+            db_handler.save_workout(workout, user_id).unwrap().workout_id
+        };
+        let edit = |workout_id: u32, reps: u32, weight: f64| {
+            let update = WorkoutUpdate { exercises: Some(vec![sets(reps, weight)]), ..Default::default() };
+            assert!(db_handler.update_workout(user_id, workout_id, &update).unwrap());
+        };
+        let target_weight = || db_handler.get_templates(user_id).unwrap()[0].exercises[0].targets[0].weight;
+        let outcomes = || -> Vec<(String, Option<u32>)> {
+            let entries = db_handler.progression_log(user_id, template_id).unwrap();
+            entries.into_iter().map(|entry| (entry.outcome, entry.workout_id)).collect()
+        };
+        let today = || db_handler.todays_workout(user_id).unwrap().unwrap().position.day;
+
+        // Deleting the latest session takes back its progression and program day.
+        let first = log(5, 100.0);
+        assert_eq!((target_weight(), today()), (Some(102.5), 2));
+        assert!(db_handler.delete_workout(user_id, first).unwrap());
+        assert_eq!((target_weight(), today()), (Some(100.0), 1));
+        assert!(outcomes().is_empty());
+
+        // Editing the sets of the latest session judges it again.
+        let failed = log(4, 100.0);
+        assert_eq!(outcomes(), vec![("failed".to_string(), Some(failed))]);
+        edit(failed, 5, 100.0);
+        assert_eq!(target_weight(), Some(102.5));
+        assert_eq!(outcomes(), vec![("progressed".to_string(), Some(failed))]);
+        assert_eq!(db_handler.progression_log(user_id, template_id).unwrap()[0].failed_sessions, 0);
+
+        // Once a later session has built on it, an older one is left as judged.
+        let later = log(5, 102.5);
+        assert_eq!(target_weight(), Some(105.0));
+        edit(failed, 3, 100.0);
+        assert_eq!(target_weight(), Some(105.0));
+        assert!(db_handler.delete_workout(user_id, failed).unwrap());
+        assert_eq!(target_weight(), Some(105.0));
+        assert_eq!(
+            outcomes(),
+            vec![("progressed".to_string(), Some(later)), ("progressed".to_string(), None)]
+        );
+
+        assert!(db_handler.delete_workout(user_id, later).unwrap());
+        assert_eq!(target_weight(), Some(102.5));
+        assert_eq!(outcomes(), vec![("progressed".to_string(), None)]);
+        for table in ["progression_changes", "program_advances"] {
+            assert_eq!(count_rows(&db_handler, table), 0);
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+/// Fixed set of worker threads fed through a bounded queue.
+///
+/// Every submitted item is passed to the same handler. When all workers are
+/// busy and the queue is full, `try_submit` hands the item back so the caller
+/// can shed the load instead of blocking.
+pub struct ThreadPool<T: Send + 'static> {
+    sender: SyncSender<T>,
+    _workers: Vec<thread::JoinHandle<()>>,
+}
+
+impl<T: Send + 'static> ThreadPool<T> {
+    pub fn new<F>(size: usize, queue_depth: usize, handler: F) -> Self
+    where
+        F: Fn(T) + Send + Sync + 'static,
+    {
+        assert!(size > 0, "Thread pool needs at least one worker");
+
+        let (sender, receiver) = mpsc::sync_channel(queue_depth);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let handler = Arc::new(handler);
+
+        let workers = (0..size)
+            .map(|_| {
+                let receiver = Arc::clone(&receiver);
+                let handler = Arc::clone(&handler);
+                thread::spawn(move || worker_loop(&receiver, &*handler))
+            })
+            .collect();
+
+        ThreadPool { sender, _workers: workers }
+    }
+
+    pub fn try_submit(&self, item: T) -> Result<(), T> {
+        match self.sender.try_send(item) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(item)) | Err(TrySendError::Disconnected(item)) => Err(item),
+        }
+    }
+}
+
+fn worker_loop<T, F: Fn(T)>(receiver: &Mutex<Receiver<T>>, handler: &F) {
+    loop {
+        let item = match receiver.lock().unwrap().recv() {
+            Ok(item) => item,
+            Err(_) => return,
+        };
+
+        // A panicking request must not take the worker down with it.
+        if panic::catch_unwind(AssertUnwindSafe(|| handler(item))).is_err() {
+            eprintln!("Worker recovered from a panicking connection");
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use std::{collections::HashMap, str::FromStr};
+
+use chrono::{Datelike, Duration, Locale, Months, NaiveDate};
+use serde::Serialize;
+
+use crate::user_time::UserClock;
+
+/// Most buckets a single series may have, so a year of days is fine but a
+/// century of them is not.
+pub const MAX_BUCKETS: usize = 400;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Bucket {
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+impl Bucket {
+    /// First day of the bucket containing `date`. Weeks start on Monday.
+    pub fn start_of(&self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Bucket::Day => date,
+            Bucket::Week => date - Duration::days(date.weekday().num_days_from_monday() as i64),
+            Bucket::Month => date.with_day(1).unwrap_or(date),
+            Bucket::Year => date.with_ordinal(1).unwrap_or(date),
+        }
+    }
+
+    /// First day of the bucket after the one starting at `start`.
+    pub fn next(&self, start: NaiveDate) -> NaiveDate {
+        match self {
+            Bucket::Day => start + Duration::days(1),
+            Bucket::Week => start + Duration::weeks(1),
+            Bucket::Month => start + Months::new(1),
+            Bucket::Year => start + Months::new(12),
+        }
+    }
+
+    /// SQL expression giving the bucket start, as `YYYY-MM-DD`, for a
+    /// `YYYY-MM-DD HH:MM:SS` timestamp expression. Pass the user's local
+    /// time, not the stored UTC one, so buckets follow their calendar.
+    pub fn sql_key(&self, column: &str) -> String {
+        match self {
+            Bucket::Day => format!("date({})", column),
+            Bucket::Week => format!("date({}, 'weekday 0', '-6 days')", column),
+            Bucket::Month => format!("strftime('%Y-%m-01', {})", column),
+            Bucket::Year => format!("strftime('%Y-01-01', {})", column),
+        }
+    }
+
+    /// Short chart label, matching what the app has always shown for weeks and months.
+    pub fn label(&self, start: NaiveDate, locale: Locale) -> String {
+        match self {
+            Bucket::Day | Bucket::Week => format!("{}/{}", start.day(), start.month()),
+            Bucket::Month => start.format_localized("%b", locale).to_string(),
+            Bucket::Year => start.format("%Y").to_string(),
+        }
+    }
+}
+
+impl FromStr for Bucket {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "day" => Ok(Bucket::Day),
+            "week" => Ok(Bucket::Week),
+            "month" => Ok(Bucket::Month),
+            "year" => Ok(Bucket::Year),
+            _ => Err(format!("Unknown bucket: {}, expected day, week, month or year", s)),
+        }
+    }
+}
+
+/// An inclusive date range split into buckets. Both ends are widened to
+/// whole buckets, so every bucket in a series covers the same span.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeRange {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub bucket: Bucket,
+}
+
+impl TimeRange {
+    /// The `count` most recent buckets, ending with the one containing `today`.
+    pub fn last(count: u32, bucket: Bucket, today: NaiveDate) -> TimeRange {
+        let mut from = bucket.start_of(today);
+        for _ in 1..count {
+            from = bucket.start_of(from - Duration::days(1));
+        }
+        TimeRange { from, to: today, bucket }
+    }
+
+    /// Reads `from`, `to` (`YYYY-MM-DD`) and `bucket` from the query string.
+    /// Anything missing comes from `default`; a new bucket size alone keeps
+    /// the default number of buckets.
+    pub fn from_query(query_params: &HashMap<String, String>, default: TimeRange) -> Result<TimeRange, String> {
+        let date = |name: &str| -> Result<Option<NaiveDate>, String> {
+            match query_params.get(name).filter(|d| !d.is_empty()) {
+                Some(d) => NaiveDate::parse_from_str(d, "%Y-%m-%d")
+                    .map(Some)
+                    .map_err(|_| format!("{} must be formatted as YYYY-MM-DD", name)),
+                None => Ok(None),
+            }
+        };
+
+        let bucket = match query_params.get("bucket").filter(|b| !b.is_empty()) {
+            Some(bucket) => bucket.parse()?,
+            None => default.bucket,
+        };
+        let to = date("to")?.unwrap_or(default.to);
+        let from = match date("from")? {
+            Some(from) => from,
+            None => TimeRange::last(default.bucket_starts().len() as u32, bucket, to).from,
+        };
+
+        if from > to {
+            return Err("from must not be after to".to_string());
+        }
+        let range = TimeRange { from, to, bucket };
+        if range.bucket_starts().len() > MAX_BUCKETS {
+            return Err(format!("Range has more than {} buckets, use a larger bucket", MAX_BUCKETS));
+        }
+        Ok(range)
+    }
+
+    /// Start date of every bucket in the range, oldest first.
+    pub fn bucket_starts(&self) -> Vec<NaiveDate> {
+        let mut starts = Vec::new();
+        let mut start = self.bucket.start_of(self.from);
+        while start <= self.to && starts.len() <= MAX_BUCKETS {
+            starts.push(start);
+            start = self.bucket.next(start);
+        }
+        starts
+    }
+
+    /// `[start, end)` stored timestamps covering every bucket, with bucket
+    /// boundaries at midnight in the user's timezone.
+    pub fn sql_bounds(&self, clock: &UserClock) -> (String, String) {
+        let start = self.bucket.start_of(self.from);
+        let end = self.bucket.next(self.bucket.start_of(self.to));
+        (clock.start_of_day(start), clock.start_of_day(end))
+    }
+}
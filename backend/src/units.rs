@@ -0,0 +1,73 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+pub const KG_PER_LB: f64 = 0.453_592_37;
+
+/// Unit weights are entered and shown in. They are always stored in kg.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WeightUnit {
+    #[default]
+    Kg,
+    Lb,
+}
+
+impl WeightUnit {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WeightUnit::Kg => "kg",
+            WeightUnit::Lb => "lb",
+        }
+    }
+
+    pub fn to_kg(self, weight: f64) -> f64 {
+        match self {
+            WeightUnit::Kg => weight,
+            WeightUnit::Lb => weight * KG_PER_LB,
+        }
+    }
+
+    /// Converts a stored weight for display, to two decimal places so a
+    /// weight entered in this unit reads back exactly as it was typed.
+    pub fn convert_kg(&self, kg: f64) -> f64 {
+        let weight = match self {
+            WeightUnit::Kg => kg,
+            WeightUnit::Lb => kg / KG_PER_LB,
+        };
+        (weight * 100.0).round() / 100.0
+    }
+
+    /// Nearest weight that can be loaded with the smallest common plates,
+    /// a pair of 1.25 kg or 2.5 lb.
+    pub fn round_to_plates(&self, weight: f64) -> f64 {
+        let step = self.plate_step();
+        (weight / step).round() * step
+    }
+
+    /// Smallest change in weight a pair of plates makes.
+    pub fn plate_step(&self) -> f64 {
+        match self {
+            WeightUnit::Kg => 2.5,
+            WeightUnit::Lb => 5.0,
+        }
+    }
+}
+
+impl FromStr for WeightUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "kg" => Ok(WeightUnit::Kg),
+            "lb" | "lbs" => Ok(WeightUnit::Lb),
+            _ => Err(format!("Unknown weight unit: {}, expected kg or lb", s)),
+        }
+    }
+}
+
+impl fmt::Display for WeightUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
@@ -0,0 +1,107 @@
+use chrono::{DateTime, Locale, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+/// How timestamps are stored: UTC, in a form SQLite's date functions and
+/// plain string comparison both understand.
+pub const STORAGE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+pub const DEFAULT_LOCALE: &str = "en_GB";
+
+pub fn to_storage(time: DateTime<Utc>) -> String {
+    time.format(STORAGE_FORMAT).to_string()
+}
+
+pub fn from_storage(stored: &str) -> Option<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(stored, STORAGE_FORMAT)
+        .ok()
+        .map(|naive| Utc.from_utc_datetime(&naive))
+}
+
+pub fn is_valid_timezone(name: &str) -> bool {
+    name.parse::<Tz>().is_ok()
+}
+
+pub fn is_valid_locale(name: &str) -> bool {
+    Locale::try_from(name).is_ok()
+}
+
+/// A user's timezone and locale, used to read times sent by their device and
+/// to show stored UTC times back to them.
+#[derive(Debug, Clone, Copy)]
+pub struct UserClock {
+    pub timezone: Tz,
+    pub locale: Locale,
+}
+
+impl Default for UserClock {
+    fn default() -> Self {
+        UserClock {
+            timezone: Tz::UTC,
+            locale: Locale::en_GB,
+        }
+    }
+}
+
+impl UserClock {
+    /// Unknown names fall back to UTC and British English rather than failing.
+    pub fn new(timezone: &str, locale: &str) -> UserClock {
+        UserClock {
+            timezone: timezone.parse().unwrap_or(Tz::UTC),
+            locale: Locale::try_from(locale).unwrap_or(Locale::en_GB),
+        }
+    }
+
+    /// Accepts RFC 3339 times with an offset, or `YYYY-MM-DD HH:MM:SS` wall
+    /// clock times which are taken to be in the user's timezone.
+    pub fn parse_client_time(&self, time: &str) -> Option<DateTime<Utc>> {
+        if let Ok(time) = DateTime::parse_from_rfc3339(time) {
+            return Some(time.with_timezone(&Utc));
+        }
+        let naive = NaiveDateTime::parse_from_str(time, STORAGE_FORMAT)
+            .or_else(|_| NaiveDateTime::parse_from_str(time, "%Y-%m-%dT%H:%M:%S"))
+            .ok()?;
+        self.resolve_local(naive)
+    }
+
+    /// Resolves a wall clock time; in a DST overlap the earlier instant wins,
+    /// and times skipped by a DST gap are read with the offset from before it.
+    fn resolve_local(&self, naive: NaiveDateTime) -> Option<DateTime<Utc>> {
+        let local = self
+            .timezone
+            .from_local_datetime(&naive)
+            .earliest()
+            .or_else(|| {
+                let before_gap = self.timezone.from_local_datetime(&(naive - chrono::Duration::hours(1))).earliest()?;
+                Some(before_gap + chrono::Duration::hours(1))
+            })?;
+        Some(local.with_timezone(&Utc))
+    }
+
+    pub fn today(&self) -> NaiveDate {
+        Utc::now().with_timezone(&self.timezone).date_naive()
+    }
+
+    /// Stored (UTC) timestamp of the start of `date` in the user's timezone.
+    pub fn start_of_day(&self, date: NaiveDate) -> String {
+        let midnight = date.and_hms_opt(0, 0, 0).unwrap_or_default();
+        match self.resolve_local(midnight) {
+            Some(time) => to_storage(time),
+            None => midnight.format(STORAGE_FORMAT).to_string(),
+        }
+    }
+
+    /// A stored timestamp as wall clock time in the user's timezone.
+    pub fn local_time(&self, stored: &str) -> Option<DateTime<Tz>> {
+        from_storage(stored).map(|time| time.with_timezone(&self.timezone))
+    }
+
+    /// Formats a stored timestamp in the user's timezone and language.
+    pub fn format(&self, stored: &str, format: &str) -> Option<String> {
+        self.local_time(stored)
+            .map(|time| time.format_localized(format, self.locale).to_string())
+    }
+
+    pub fn format_rfc3339(&self, stored: &str) -> Option<String> {
+        self.local_time(stored).map(|time| time.to_rfc3339())
+    }
+}
@@ -1,23 +1,377 @@
 use serde::{Serialize, Deserialize};
 
+use crate::exercise_kind::ExerciseKind;
+use crate::one_rep_max::OneRepMaxFormula;
+use crate::units::WeightUnit;
+
 #[derive(Serialize, Deserialize)]
 pub struct Exercise {
     pub id: u32,
     pub name: String,
     pub body_part: String,
-    pub best_set: String
+    pub description: Option<String>,
+    pub best_set: String,
+    pub kind: ExerciseKind,
+    pub archived: bool,
+}
+
+/// Partial edit of a user exercise; fields left out are unchanged.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ExerciseUpdate {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub body_part: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Archived exercises are hidden from the exercise list but keep their history.
+    #[serde(default)]
+    pub archived: Option<bool>,
 }
 
+/// Folds the exercise in the path into `into`, which keeps its name.
 #[derive(Serialize, Deserialize, Debug)]
+pub struct ExerciseMerge {
+    pub into: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SetType {
+    /// Left out of personal records and volume.
+    Warmup,
+    #[default]
+    Working,
+    Drop,
+    Failure,
+    Amrap,
+}
+
+impl SetType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SetType::Warmup => "warmup",
+            SetType::Working => "working",
+            SetType::Drop => "drop",
+            SetType::Failure => "failure",
+            SetType::Amrap => "amrap",
+        }
+    }
+}
+
+impl std::str::FromStr for SetType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "warmup" => Ok(SetType::Warmup),
+            "working" => Ok(SetType::Working),
+            "drop" => Ok(SetType::Drop),
+            "failure" => Ok(SetType::Failure),
+            "amrap" => Ok(SetType::Amrap),
+            _ => Err(format!("Unknown set type: {}", s)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct Set {
     pub reps: u32,
     pub weight: f64,
+    #[serde(default)]
+    pub set_type: SetType,
+    /// Rate of perceived exertion, 1 to 10 in half steps.
+    #[serde(default)]
+    pub rpe: Option<f64>,
+    /// Reps in reserve.
+    #[serde(default)]
+    pub rir: Option<u32>,
+    #[serde(default)]
+    pub notes: Option<String>,
+    /// Time held or moving, for timed and cardio exercises.
+    #[serde(default)]
+    pub duration_seconds: Option<u32>,
+    #[serde(default)]
+    pub distance_meters: Option<f64>,
+    /// The lifter's bodyweight, for bodyweight and assisted exercises.
+    #[serde(default)]
+    pub bodyweight: Option<f64>,
+    /// Unit of `weight` and `bodyweight`. Sets sent without one are in the
+    /// user's preferred unit, and sets read back are always in it.
+    #[serde(default)]
+    pub unit: Option<WeightUnit>,
+}
+
+impl Set {
+    /// Checks the optional effort fields are in range.
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(rpe) = self.rpe {
+            if !(1.0..=10.0).contains(&rpe) || (rpe * 2.0).fract() != 0.0 {
+                return Err(format!("RPE must be between 1 and 10 in steps of 0.5, got {}", rpe));
+            }
+        }
+        if self.rir.is_some_and(|rir| rir > 10) {
+            return Err("RIR must be between 0 and 10".to_string());
+        }
+        if self.distance_meters.is_some_and(|distance| distance < 0.0) {
+            return Err("distance_meters must not be negative".to_string());
+        }
+        if self.bodyweight.is_some_and(|bodyweight| bodyweight <= 0.0) {
+            return Err("bodyweight must be positive".to_string());
+        }
+        Ok(())
+    }
+
+    /// The set, as read from storage in kg, with its weights shown in `unit`.
+    pub fn in_unit(self, unit: WeightUnit) -> Set {
+        Set {
+            weight: unit.convert_kg(self.weight),
+            bodyweight: self.bodyweight.map(|bodyweight| unit.convert_kg(bodyweight)),
+            unit: Some(unit),
+            ..self
+        }
+    }
+
+    /// RPE as logged, or derived from RIR (RPE 10 means no reps left).
+    pub fn effective_rpe(&self) -> Option<f64> {
+        self.rpe.or(self.rir.map(|rir| 10.0 - rir as f64))
+    }
+}
+
+/// A workout ready to be logged, built from a template. Nothing is saved
+/// until it is sent to `/workout` along with its `template_id`.
+#[derive(Serialize, Debug)]
+pub struct WorkoutDraft {
+    pub template_id: u32,
+    pub name: String,
+    pub unit: WeightUnit,
+    pub exercises: Vec<DraftExercise>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DraftExercise {
+    pub exercise_id: u32,
+    pub name: String,
+    pub muscle_group: String,
+    pub kind: ExerciseKind,
+    pub group: Option<ExerciseGroup>,
+    /// Pre-filled sets, in the user's unit.
+    pub sets: Vec<Set>,
+    /// The template's target for each set, if it has them.
+    pub targets: Vec<SetTarget>,
+    /// Sets from the last workout with this exercise.
+    pub previous: Vec<Set>,
+}
+
+/// A training program as sent to `/programs`. Each week lists the template
+/// for each of its days in order.
+#[derive(Deserialize, Debug)]
+pub struct ProgramRequest {
+    pub name: String,
+    #[serde(default = "default_deload_percent")]
+    pub deload_percent: f64,
+    pub weeks: Vec<ProgramWeek>,
+}
+
+fn default_deload_percent() -> f64 {
+    60.0
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProgramWeek {
+    /// Weights are scaled down to the program's `deload_percent`.
+    #[serde(default)]
+    pub deload: bool,
+    /// Template id for each day.
+    pub days: Vec<u32>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Program {
+    pub id: u32,
+    pub name: String,
+    pub deload_percent: f64,
+    pub created_at: String,
+    pub weeks: Vec<ProgramWeek>,
+}
+
+/// Where a user is in the program they follow. Weeks and days count from 1;
+/// `cycle` goes up each time the program starts over.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ProgramPosition {
+    pub program_id: u32,
+    pub program_name: String,
+    pub cycle: u32,
+    pub week: u32,
+    pub day: u32,
+    pub deload: bool,
+    pub template_id: u32,
+}
+
+/// The workout due today in the user's program.
+#[derive(Serialize, Debug)]
+pub struct TodaysWorkout {
+    #[serde(flatten)]
+    pub position: ProgramPosition,
+    pub workout: WorkoutDraft,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TrainingMax {
+    pub exercise_id: u32,
+    pub name: String,
+    pub weight: f64,
+    pub unit: WeightUnit,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TrainingMaxUpdate {
+    pub weight: f64,
+    /// Unit of `weight`; the user's preferred unit if left out.
+    #[serde(default)]
+    pub unit: Option<WeightUnit>,
+}
+
+/// What a template prescribes for one set. Every target is optional.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct SetTarget {
+    #[serde(default)]
+    pub reps_min: Option<u32>,
+    #[serde(default)]
+    pub reps_max: Option<u32>,
+    /// In the user's preferred unit.
+    #[serde(default)]
+    pub weight: Option<f64>,
+    /// Percentage of the exercise's estimated one-rep max, for when no
+    /// fixed `weight` is given.
+    #[serde(default)]
+    pub percent_e1rm: Option<f64>,
+    /// Percentage of the user's stored training max for the exercise.
+    #[serde(default)]
+    pub percent_training_max: Option<f64>,
+    #[serde(default)]
+    pub rpe: Option<f64>,
+    #[serde(default)]
+    pub rest_seconds: Option<u32>,
+    /// Weight to load, from `weight` or a percentage of the training max or
+    /// current estimate, rounded to plates. Only filled in when reading.
+    #[serde(default, skip_deserializing)]
+    pub suggested_weight: Option<f64>,
+}
+
+impl SetTarget {
+    pub fn validate(&self) -> Result<(), String> {
+        if let (Some(min), Some(max)) = (self.reps_min, self.reps_max) {
+            if min > max {
+                return Err(format!("reps_min {} is above reps_max {}", min, max));
+            }
+        }
+        let loads = [self.weight, self.percent_e1rm, self.percent_training_max];
+        if loads.iter().flatten().count() > 1 {
+            return Err("Give only one of weight, percent_e1rm and percent_training_max".to_string());
+        }
+        if self.weight.is_some_and(|weight| weight < 0.0) {
+            return Err("Target weight must not be negative".to_string());
+        }
+        if self.percent_e1rm.is_some_and(|percent| !(percent > 0.0 && percent <= 150.0)) {
+            return Err("percent_e1rm must be above 0 and at most 150".to_string());
+        }
+        if self.percent_training_max.is_some_and(|percent| !(percent > 0.0 && percent <= 150.0)) {
+            return Err("percent_training_max must be above 0 and at most 150".to_string());
+        }
+        if let Some(rpe) = self.rpe {
+            if !(1.0..=10.0).contains(&rpe) || (rpe * 2.0).fract() != 0.0 {
+                return Err(format!("RPE must be between 1 and 10 in steps of 0.5, got {}", rpe));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupType {
+    /// Two exercises done back to back.
+    Superset,
+    /// Three exercises done back to back.
+    Triset,
+    /// Two or more exercises done in rounds.
+    Circuit,
+}
+
+impl GroupType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GroupType::Superset => "superset",
+            GroupType::Triset => "triset",
+            GroupType::Circuit => "circuit",
+        }
+    }
+
+    fn accepts(&self, exercises: usize) -> bool {
+        match self {
+            GroupType::Superset => exercises == 2,
+            GroupType::Triset => exercises == 3,
+            GroupType::Circuit => exercises >= 2,
+        }
+    }
+}
+
+impl std::str::FromStr for GroupType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "superset" => Ok(GroupType::Superset),
+            "triset" => Ok(GroupType::Triset),
+            "circuit" => Ok(GroupType::Circuit),
+            _ => Err(format!("Unknown group type: {}", s)),
+        }
+    }
+}
+
+/// Places an exercise in a superset, tri-set or circuit. Exercises sharing an
+/// `id` form one group; ids only need to be unique within a workout or
+/// template. When read back, `id` is the group's position, starting at 1.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExerciseGroup {
+    pub id: u32,
+    pub group_type: GroupType,
+}
+
+/// Checks each group's exercises are listed next to each other, agree on the
+/// group type and are the right number for it.
+pub fn validate_groups(groups: &[Option<ExerciseGroup>]) -> Result<(), String> {
+    let mut seen: Vec<u32> = Vec::new();
+    for run in groups.chunk_by(|a, b| a.map(|g| g.id) == b.map(|g| g.id)) {
+        let Some(group) = run[0] else {
+            continue;
+        };
+        if seen.contains(&group.id) {
+            return Err(format!("Exercises in group {} must be listed together", group.id));
+        }
+        seen.push(group.id);
+        if run.iter().any(|g| g.map(|g| g.group_type) != Some(group.group_type)) {
+            return Err(format!("Exercises in group {} have different group types", group.id));
+        }
+        if !group.group_type.accepts(run.len()) {
+            return Err(format!(
+                "A {} can't have {} exercise{}",
+                group.group_type.as_str(),
+                run.len(),
+                if run.len() == 1 { "" } else { "s" }
+            ));
+        }
+    }
+    Ok(())
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ExerciseRecord {
     pub exercise_id: u32,
     pub sets: Vec<Set>,
+    #[serde(default)]
+    pub group: Option<ExerciseGroup>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -26,5 +380,77 @@ pub struct Workout {
     pub start_time: String,
     pub end_time: String,
     pub exercises: Vec<ExerciseRecord>,
+    #[serde(default)]
     pub(crate) notes: String,
-}
\ No newline at end of file
+    /// Client-generated id for this workout; saving the same id twice is a no-op.
+    #[serde(default)]
+    pub client_id: Option<String>,
+    /// Template the workout was started from, as given by `/templates/{id}/start`.
+    #[serde(default)]
+    pub template_id: Option<u32>,
+}
+/// Partial edit of a saved workout. Fields left out are kept as they are;
+/// `exercises`, when given, replaces every exercise and set in the workout
+/// in the order listed.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct WorkoutUpdate {
+    #[serde(default)]
+    pub start_time: Option<String>,
+    #[serde(default)]
+    pub end_time: Option<String>,
+    #[serde(default)]
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub exercises: Option<Vec<ExerciseRecord>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct WorkoutDetail {
+    pub id: u32,
+    pub start_time: String,
+    pub end_time: String,
+    pub notes: String,
+    pub template_id: Option<u32>,
+    pub exercises: Vec<WorkoutDetailExercise>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct WorkoutDetailExercise {
+    pub exercise_id: u32,
+    pub name: String,
+    pub kind: ExerciseKind,
+    pub group: Option<ExerciseGroup>,
+    pub sets: Vec<WorkoutDetailSet>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct WorkoutDetailSet {
+    pub id: u32,
+    pub set_number: u32,
+    #[serde(flatten)]
+    pub set: Set,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UserPreferences {
+    pub one_rep_max_formula: OneRepMaxFormula,
+    /// IANA name, e.g. `Europe/London`.
+    pub timezone: String,
+    /// Language for day and month names, e.g. `en_GB` or `fr_FR`.
+    pub locale: String,
+    /// Unit weights are shown in and, unless a set says otherwise, sent in.
+    pub weight_unit: WeightUnit,
+}
+
+/// Partial change to `UserPreferences`; fields left out are unchanged.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct PreferencesUpdate {
+    #[serde(default)]
+    pub one_rep_max_formula: Option<OneRepMaxFormula>,
+    #[serde(default)]
+    pub timezone: Option<String>,
+    #[serde(default)]
+    pub locale: Option<String>,
+    #[serde(default)]
+    pub weight_unit: Option<WeightUnit>,
+}
@@ -1,5 +1,5 @@
 import { useState, useEffect, useCallback, useMemo } from 'react';
-import { WorkoutExercise, Exercise, WorkoutPayload, WorkoutSet } from '../types/workoutTypes';
+import { WorkoutExercise, Exercise, WorkoutPayload, WorkoutSet, SetTarget, WorkoutDraft } from '../types/workoutTypes';
 import { saveWorkout as apiSaveWorkout, getExercises as apiGetExercises, getPreviousSets } from '../services/api';
 import { AuthContext } from '../providers/AuthProvider';
 import React from 'react';
@@ -14,6 +14,7 @@ export const useWorkout = () => {
   const [isLoadingExercises, setIsLoadingExercises] = useState(true);
   const [isSaving, setIsSaving] = useState(false);
   const [startTime, setStartTime] = useState<string>();
+  const [templateId, setTemplateId] = useState<number>();
   const { logout, user } = React.useContext(AuthContext);
   
 
@@ -178,26 +179,45 @@ export const useWorkout = () => {
   );
 
   
-  const initializeFromTemplate = useCallback((templateExercises: any[]) => {
-    templateExercises.forEach((exercise) => {
-      const newExercise: WorkoutExercise = {
-        id: exercise.exercise_id,
-        name: exercise.name,
-        body_part: exercise.muscle_group,
-        sets: Array.from({ length: exercise.sets }).map(() => ({
+  const initializeFromTemplate = useCallback((draft: WorkoutDraft) => {
+    setTemplateId(draft.template_id);
+    const newExercises: WorkoutExercise[] = draft.exercises.map((exercise) => ({
+      id: exercise.exercise_id,
+      name: exercise.name,
+      body_part: exercise.muscle_group,
+      sets: exercise.sets.map((set, index) => {
+        const target = exercise.targets[index];
+        const previous = exercise.previous[index];
+        return {
           id: Date.now() + Math.random(),
-          reps: '',
-          weight: '',
+          reps: set.reps ? `${set.reps}` : '',
+          weight: set.weight ? `${set.weight}` : '',
           completed: false,
-          previous: '',
-        })),
-      };
+          previous: target
+            ? describeTarget(target)
+            : previous ? `${previous.reps} x ${previous.weight}${draft.unit}` : '',
+        };
+      }),
+    }));
 
-      setExercises((prev) => [...prev, newExercise]);
-    });
+    setExercises((prev) => [...prev, ...newExercises]);
   }, []);
 
   
+  const describeTarget = (target: SetTarget): string => {
+    const { reps_min, reps_max } = target;
+    const reps = reps_min != null && reps_max != null && reps_min !== reps_max
+      ? `${reps_min}-${reps_max}`
+      : `${reps_max ?? reps_min ?? ''}`;
+    const parts = [reps];
+    if (target.percent_e1rm != null) parts.push(`@ ${target.percent_e1rm}%`);
+    if (target.percent_training_max != null) parts.push(`@ ${target.percent_training_max}% TM`);
+    if (target.rpe != null) parts.push(`RPE ${target.rpe}`);
+    if (target.rest_seconds != null) parts.push(`${target.rest_seconds}s rest`);
+    return parts.filter(Boolean).join(' ');
+  };
+
+  
   const getCurrentTimeFormatted = (): string => {
     const now = new Date();
 
@@ -209,7 +229,13 @@ export const useWorkout = () => {
     const minutes = String(now.getMinutes()).padStart(2, '0');
     const seconds = String(now.getSeconds()).padStart(2, '0');
 
-    return `${year}-${month}-${day} ${hours}:${minutes}:${seconds}`;
+    // RFC 3339 with the device's offset, so the server never has to guess the zone.
+    const offsetMinutes = -now.getTimezoneOffset();
+    const sign = offsetMinutes >= 0 ? '+' : '-';
+    const offsetHours = String(Math.floor(Math.abs(offsetMinutes) / 60)).padStart(2, '0');
+    const offsetRest = String(Math.abs(offsetMinutes) % 60).padStart(2, '0');
+
+    return `${year}-${month}-${day}T${hours}:${minutes}:${seconds}${sign}${offsetHours}:${offsetRest}`;
   };
 
   
@@ -238,6 +264,7 @@ export const useWorkout = () => {
       })),
       start_time: start_time,
       end_time: getCurrentTimeFormatted(),
+      template_id: templateId,
     };
 
     try {
@@ -250,7 +277,7 @@ export const useWorkout = () => {
     } finally {
       setIsSaving(false);
     }
-  }, [exercises, startTime]);
+  }, [exercises, startTime, templateId]);
 
   
   const hasIncompleteSets = useMemo(() => {
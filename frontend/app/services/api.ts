@@ -99,6 +99,19 @@ export const getTemplates = async (userId: string) => {
   }
 };
 
+export const startWorkoutFromTemplate = async (userId: string, templateId: number) => {
+  try {
+    const response = await fetch(`${API_BASE}/templates/${templateId}/start?userid=${userId}`, {
+      method: 'POST',
+    });
+    if (!response.ok) throw new Error('Failed to start workout from template');
+    return await response.json();
+  } catch (error) {
+    console.error('API Error:', error);
+    throw error;
+  }
+};
+
 export const createTemplate = async (templateData: {
   name: string;
   user_id: string;
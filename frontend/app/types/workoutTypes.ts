@@ -12,6 +12,37 @@ export interface Exercise {
     previous: string,
   }
   
+  export interface SetTarget {
+    reps_min?: number | null;
+    reps_max?: number | null;
+    weight?: number | null;
+    percent_e1rm?: number | null;
+    percent_training_max?: number | null;
+    rpe?: number | null;
+    rest_seconds?: number | null;
+    suggested_weight?: number | null;
+  }
+  
+  export interface DraftSet {
+    reps: number;
+    weight: number;
+    unit?: string;
+  }
+  
+  export interface WorkoutDraft {
+    template_id: number;
+    name: string;
+    unit: string;
+    exercises: Array<{
+      exercise_id: number;
+      name: string;
+      muscle_group: string;
+      sets: DraftSet[];
+      targets: SetTarget[];
+      previous: DraftSet[];
+    }>;
+  }
+  
   export interface WorkoutExercise extends Exercise {
     sets: WorkoutSet[];
   }
@@ -26,6 +57,7 @@ export interface Exercise {
     user_id: string;
     start_time: string,
     end_time: string,
+    template_id?: number,
     exercises: Array<{
       exercise_id: number;
       sets: Array<{
@@ -11,6 +11,7 @@ import LoadingOverlay from '../components/LoadingOverlay';
 import { buttons, colors, layout } from '../shared/theme';
 import { GestureHandlerRootView } from 'react-native-gesture-handler';
 import { AuthContext } from '../providers/AuthProvider';
+import { startWorkoutFromTemplate } from '../services/api';
 
 const WorkoutScreen: React.FC = () => {
   const { logout, user } = React.useContext(AuthContext);
@@ -53,7 +54,9 @@ const WorkoutScreen: React.FC = () => {
   useEffect(() => {
     if (!user_id || !template || templateLoaded) return;
   
-    initializeFromTemplate(template.exercises);
+    startWorkoutFromTemplate(user_id, template.id)
+      .then(initializeFromTemplate)
+      .catch((error) => console.error('Failed to start workout from template:', error));
 
     
     